@@ -1,100 +1,789 @@
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::OsString,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
-use crate::{error::Error, Location};
+use crate::{bytes::Bytes, error::Error, utils, Location};
 
-pub trait Backend {
+/// Files that came along with a read, keyed by path. Get cached next to the requested file.
+pub type ExtraFiles = Option<Vec<(String, Vec<u8>)>>;
+
+/// Source and sink of asset files. Install a custom one with What::set_backend.
+pub trait Backend: Send {
+    /// Read `path`, resolved against `base`. Files above `mmap_threshold` may be memory mapped.
     fn read_file(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error>;
+
+    /// Like read_file, but reports the expected size of the file through `size_hint` before
+    /// reading it, if it is known up front. Lets the cache make room ahead of a download.
+    fn read_file_with_hint(
+        &self,
         base: &Option<Location>,
         path: &str,
-    ) -> Result<(Vec<u8>, Option<Vec<(String, Vec<u8>)>>), Error>;
-    fn write_file(path: &str, bytes: Vec<u8>, overwrite: bool) -> Result<(), String>;
+        mmap_threshold: Option<u64>,
+        _size_hint: &mut dyn FnMut(u64),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.read_file(base, path, mmap_threshold)
+    }
+
+    /// Like read_file_with_hint, but also calls `progress` with the bytes read so far and the
+    /// total, if known, while reading. Sleeping in `progress` throttles the read.
+    fn read_file_with_progress(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+        size_hint: &mut dyn FnMut(u64),
+        progress: &mut dyn FnMut(u64, Option<u64>),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        let (bytes, other) = self.read_file_with_hint(base, path, mmap_threshold, size_hint)?;
+        progress(bytes.len() as u64, Some(bytes.len() as u64));
+        Ok((bytes, other))
+    }
+
+    /// Read `length` bytes (or everything) starting at `offset` without loading the whole file.
+    /// Falls back to reading the whole file.
+    fn read_range(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<Vec<u8>, Error> {
+        let (bytes, _) = self.read_file(base, path, None)?;
+
+        let start = (offset as usize).min(bytes.len());
+        let end = match length {
+            Some(length) => start.saturating_add(length.try_into().unwrap_or(usize::MAX)),
+            None => bytes.len(),
+        };
+
+        bytes.get(start..end).map(<[u8]>::to_vec).ok_or_else(|| {
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("Range {}..{} exceeds {}.", start, end, path),
+            ))
+        })
+    }
+
+    /// Read the last `length` bytes of `path` (fewer if it is shorter) together with the size
+    /// of the whole file. None if the backend can't read from the end without loading it all.
+    fn read_tail(
+        &self,
+        _base: &Option<Location>,
+        _path: &str,
+        _length: u64,
+    ) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        Ok(None)
+    }
+
+    fn write_file(&self, path: &Path, _content: &[u8], _overwrite: bool) -> Result<(), Error> {
+        Err(Error::Io(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("Backend can't write {}.", path.display()),
+        )))
+    }
 }
 
-#[cfg(target_arch = "wasm32")]
-impl Backend for What {
-    fn read_file(path: &str) -> Result<Vec<u8>, String> {
-        const MAX_REQUESTS: usize = 5;
-
-        for i in 0..MAX_REQUESTS {
-            match ureq::get(&path).call() {
-                Ok(file) => {
-                    let mut bytes: Vec<u8> = if let Some(value) = file.header("Content-Length") {
-                        value.parse().map_or(Vec::new(), Vec::with_capacity)
-                    } else {
-                        Vec::new()
-                    };
-
-                    return if let Err(err) = file.into_reader().read_to_end(&mut bytes) {
-                        Err(format!("Failed to read file at {}. Err: {}", path, err))
-                    } else {
-                        Ok(bytes)
-                    };
+/// How http reads retry failed requests. Waits `base_delay * backoff^n` before the n-th retry,
+/// except on wasm where sleeping would panic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub backoff: f32,
+    /// Status codes worth retrying. Transport errors are always retried.
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            backoff: 2.0,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Give up after the first failure.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn delay(&self, retry: u32) -> Duration {
+        self.base_delay.mul_f32(self.backoff.powi(retry as i32))
+    }
+
+    fn call(&self, request: ureq::Request) -> Result<ureq::Response, Box<ureq::Error>> {
+        let max_attempts = self.max_attempts.max(1);
+
+        for attempt in 1.. {
+            match request.clone().call() {
+                Ok(response) => return Ok(response),
+                Err(ureq::Error::Status(code, response))
+                    if !self.retry_statuses.contains(&code) =>
+                {
+                    return Err(Box::new(ureq::Error::Status(code, response)))
                 }
+                Err(err) if attempt >= max_attempts => return Err(Box::new(err)),
                 Err(err) => {
                     log::warn!(
-                        "Failed to retrieve file {}. ({}/{MAX_REQUESTS}) Trying again. Err: {}",
-                        path,
-                        i + 1,
+                        "Failed to retrieve file {}. ({}/{}) Trying again. Err: {}",
+                        request.url(),
+                        attempt,
+                        max_attempts,
                         err
                     );
+                    //Browsers can't block the thread, so wasm retries right away.
+                    #[cfg(not(target_arch = "wasm32"))]
+                    std::thread::sleep(self.delay(attempt - 1));
                 }
             }
         }
 
-        Err(format!("Failed to retrieve file {}.", path))
+        unreachable!()
+    }
+}
+
+/// Backend used unless another one is installed. Reads files, zip archives and http.
+pub struct DefaultBackend {
+    retry: RetryPolicy,
+    headers: Vec<(String, String)>,
+    //Shared by all requests, so connections are pooled.
+    agent: ureq::Agent,
+}
+
+impl Default for DefaultBackend {
+    fn default() -> Self {
+        DefaultBackend {
+            retry: RetryPolicy::default(),
+            headers: Vec::new(),
+            agent: agent(Duration::from_secs(10), Duration::from_secs(30)),
+        }
+    }
+}
+
+impl DefaultBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// How long http requests wait for the connection and for each read from it, 10 and 30
+    /// seconds by default. Running out fails the attempt with Error::Timeout, which is
+    /// retried like any other transport error.
+    pub fn with_timeouts(mut self, connect: Duration, read: Duration) -> Self {
+        self.agent = agent(connect, read);
+        self
+    }
+
+    /// Send `name: value` with every http request, e.g. an `Authorization` token.
+    /// Header values are never logged.
+    pub fn with_header<S: Into<String>>(mut self, name: S, value: S) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    fn get(&self, url: &str) -> ureq::Request {
+        self.headers
+            .iter()
+            .fold(self.agent.get(url), |request, (name, value)| {
+                request.set(name, value)
+            })
+    }
+}
+
+fn agent(connect: Duration, read: Duration) -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout_connect(connect)
+        .timeout_read(read)
+        .build()
+}
+
+//Whether `err` or one of its sources is an io timeout.
+fn is_timeout(err: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(err);
+
+    while let Some(err) = source {
+        if let Some(err) = err.downcast_ref::<std::io::Error>() {
+            if err.kind() == std::io::ErrorKind::TimedOut {
+                return true;
+            }
+        }
+
+        source = err.source();
     }
 
-    fn write_file(_path: &str, _bytes: Vec<u8>, _overwrite: bool) -> Result<(), String> {
-        unimplemented!("Files can only be written in native builds, not in WASM")
+    false
+}
+
+//Turn a failed body read into Error::Timeout if the server stopped sending.
+fn read_error(url: &str, err: std::io::Error) -> Error {
+    if is_timeout(&err) {
+        Error::Timeout(url.to_string())
+    } else {
+        Error::Io(err)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Backend for DefaultBackend {
+    fn read_file(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.read_file_with_hint(base, path, mmap_threshold, &mut |_| {})
+    }
+
+    fn read_file_with_hint(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+        size_hint: &mut dyn FnMut(u64),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.read_file_with_progress(base, path, mmap_threshold, size_hint, &mut |_, _| {})
+    }
+
+    fn read_file_with_progress(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        _mmap_threshold: Option<u64>,
+        size_hint: &mut dyn FnMut(u64),
+        progress: &mut dyn FnMut(u64, Option<u64>),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        if let Some(Location::Static(files)) = base {
+            return read_static_impl(files, path).map(|bytes| {
+                progress(bytes.len() as u64, Some(bytes.len() as u64));
+                (Bytes::Static(bytes), None)
+            });
+        }
+
+        let url = match base {
+            Some(Location::Http(base)) => utils::join_url(base, path)?.to_string(),
+            _ => path.to_string(),
+        };
+
+        let file = self.retry.call(self.get(&url)).map_err(|err| {
+            if is_timeout(err.as_ref()) {
+                return Error::Timeout(url.clone());
+            }
+
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Failed to retrieve file {}. Err: {}", url, err),
+            ))
+        })?;
+
+        let length = file
+            .header("Content-Length")
+            .and_then(|value| value.parse::<u64>().ok());
+
+        if let Some(length) = length {
+            size_hint(length);
+        }
+
+        read_with_progress(file.into_reader(), length, progress)
+            .map_err(|err| read_error(&url, err))
+            .map(|bytes| (bytes.into(), None))
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-impl Backend for crate::What {
+impl Backend for DefaultBackend {
     fn read_file(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.read_file_with_hint(base, path, mmap_threshold, &mut |_| {})
+    }
+
+    fn read_file_with_hint(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+        size_hint: &mut dyn FnMut(u64),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        //Without anyone watching, local files are read in one go.
+        match base {
+            Some(Location::File(base)) => {
+                read_file_impl(&base.join(path), mmap_threshold, size_hint, None)
+            }
+            None => read_file_impl(&PathBuf::from(path), mmap_threshold, size_hint, None),
+            _ => {
+                self.read_file_with_progress(base, path, mmap_threshold, size_hint, &mut |_, _| {})
+            }
+        }
+    }
+
+    fn read_file_with_progress(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+        size_hint: &mut dyn FnMut(u64),
+        progress: &mut dyn FnMut(u64, Option<u64>),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        match base {
+            Some(Location::File(base)) => {
+                read_file_impl(&base.join(path), mmap_threshold, size_hint, Some(progress))
+            }
+            Some(Location::Http(base)) => {
+                read_http_impl(self, base, path, None, size_hint, progress)
+                    .map(|bytes| (bytes.into(), None))
+            }
+            Some(Location::Archive(archive)) => {
+                read_archive_impl(archive, path, size_hint, progress)
+            }
+            Some(Location::Static(files)) => read_static_impl(files, path).map(|bytes| {
+                progress(bytes.len() as u64, Some(bytes.len() as u64));
+                (Bytes::Static(bytes), None)
+            }),
+            None => read_file_impl(
+                &PathBuf::from(path),
+                mmap_threshold,
+                size_hint,
+                Some(progress),
+            ),
+        }
+    }
+
+    fn read_range(
+        &self,
         base: &Option<Location>,
         path: &str,
-    ) -> Result<(Vec<u8>, Option<Vec<(String, Vec<u8>)>>), Error> {
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<Vec<u8>, Error> {
         match base {
-            Some(Location::File(base)) => read_file_impl(&base.join(path)),
-            Some(Location::Http(_base)) => todo!("Http fetch not yet implemented."),
-            None => read_file_impl(&PathBuf::from(path)),
+            Some(Location::File(base)) => read_range_impl(&base.join(path), offset, length),
+            Some(Location::Http(base)) => read_http_impl(
+                self,
+                base,
+                path,
+                Some((offset, length)),
+                &mut |_| {},
+                &mut |_, _| {},
+            ),
+            Some(Location::Archive(archive)) => {
+                read_archive_range_impl(archive, path, offset, length)
+            }
+            Some(Location::Static(files)) => {
+                let bytes = read_static_impl(files, path)?;
+                let start = (offset as usize).min(bytes.len());
+                let end = length.map_or(bytes.len(), |length| {
+                    start.saturating_add(length.try_into().unwrap_or(usize::MAX))
+                });
+
+                bytes
+                    .get(start..end)
+                    .map(<[u8]>::to_vec)
+                    .ok_or_else(|| Error::Io(std::io::ErrorKind::UnexpectedEof.into()))
+            }
+            None => read_range_impl(Path::new(path), offset, length),
+        }
+    }
+
+    fn read_tail(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+        length: u64,
+    ) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        match base {
+            Some(Location::File(base)) => read_tail_impl(&base.join(path), length).map(Some),
+            Some(Location::Archive(archive)) => {
+                read_archive_tail_impl(archive, path, length).map(Some)
+            }
+            Some(Location::Static(files)) => {
+                let bytes = read_static_impl(files, path)?;
+                let start = bytes
+                    .len()
+                    .saturating_sub(length.try_into().unwrap_or(usize::MAX));
+                Ok(Some((bytes[start..].to_vec(), bytes.len() as u64)))
+            }
+            //Suffix ranges aren't worth a request per header, http reads from the start.
+            Some(Location::Http(_)) => Ok(None),
+            None => read_tail_impl(Path::new(path), length).map(Some),
         }
     }
 
-    fn write_file(path: &str, content: Vec<u8>, overwrite: bool) -> Result<(), String> {
-        let path = Path::new(&path);
+    fn write_file(&self, path: &Path, content: &[u8], overwrite: bool) -> Result<(), Error> {
+        //A bare file name has an empty parent, which is the working directory.
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent).map_err(Error::Io)?;
+            }
+        }
 
-        if path.exists() {
+        if overwrite && path.exists() {
+            log::warn!("Overwrite flag set. Overwriting file {}", path.display());
+        }
+
+        //Write next to the target and move it into place, so a crash or failed write
+        //never leaves a partial asset behind and readers see either the old or the new file.
+        let temp = temp_path(path);
+
+        let result = std::fs::write(&temp, content).and_then(|_| {
             if overwrite {
-                log::warn!("Overwrite flag set. Overwriting file {}", path.display());
+                std::fs::rename(&temp, path)
             } else {
-                return Err(format!("File {} already exists.", path.display()));
+                link_new(&temp, path)
             }
+        });
+
+        let _ = std::fs::remove_file(&temp);
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                Err(Error::AlreadyExists(path.to_path_buf()))
+            }
+            Err(err) => Err(Error::Io(err)),
         }
+    }
+}
 
-        if let Err(e) = std::fs::create_dir_all(path.parent().unwrap()) {
-            return Err(format!(
-                "Could not create parent folders of {}. Err: {}",
-                path.display(),
-                e
-            ));
+//Unique per process and write, so concurrent writers never share a temp file.
+fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(name)
+}
+
+//Moves temp to path, failing with AlreadyExists if path is already there.
+fn link_new(temp: &Path, path: &Path) -> std::io::Result<()> {
+    //Unlike rename, hard_link refuses atomically if the target already exists.
+    match std::fs::hard_link(temp, path) {
+        Err(err) if err.kind() != std::io::ErrorKind::AlreadyExists => {
+            //Some file systems have no hard links. Claim the name first, then move over it.
+            log::debug!("Hard link failed ({}), falling back to rename", err);
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)?;
+            std::fs::rename(temp, path)
         }
+        result => result,
+    }
+}
+
+fn read_file_impl(
+    path: &PathBuf,
+    mmap_threshold: Option<u64>,
+    size_hint: &mut dyn FnMut(u64),
+    progress: Option<&mut dyn FnMut(u64, Option<u64>)>,
+) -> Result<(Bytes, ExtraFiles), Error> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("File {} not found.", path.display()),
+            )))
+        }
+        Err(err) => return Err(Error::Io(err)),
+    };
+
+    size_hint(metadata.len());
 
-        std::fs::write(path, content)
-            .map_err(|err| format!("Failed to write to file {}. Err: {}", path.display(), err))
+    if let Some(threshold) = mmap_threshold {
+        if let Some(map) = map_file(path, threshold) {
+            if let Some(progress) = progress {
+                progress(map.len() as u64, Some(map.len() as u64));
+            }
+            return Ok((Bytes::Mapped(std::sync::Arc::new(map)), None));
+        }
+    }
+
+    match progress {
+        Some(progress) => std::fs::File::open(path)
+            .and_then(|file| read_with_progress(file, Some(metadata.len()), progress)),
+        None => std::fs::read(path),
     }
+    .map_err(Error::Io)
+    .map(|bytes| (bytes.into(), None))
 }
 
-fn read_file_impl(path: &PathBuf) -> Result<(Vec<u8>, Option<Vec<(String, Vec<u8>)>>), Error> {
-    if !path.exists() {
-        return Err(Error::Io(std::io::Error::new(
+//Read everything, calling `progress` after every chunk. A known total is allocated up front,
+//but only up to a limit, since servers may send any Content-Length.
+fn read_with_progress<R: Read>(
+    mut read: R,
+    total: Option<u64>,
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> std::io::Result<Vec<u8>> {
+    const CHUNK_SIZE: usize = 64 * 1024;
+
+    let mut bytes = Vec::with_capacity(preallocation(total));
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+        match read.read(&mut chunk) {
+            Ok(0) => return Ok(bytes),
+            Ok(length) => {
+                bytes.extend_from_slice(&chunk[..length]);
+                progress(bytes.len() as u64, total);
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_http_impl(
+    backend: &DefaultBackend,
+    base: &str,
+    path: &str,
+    range: Option<(u64, Option<u64>)>,
+    size_hint: &mut dyn FnMut(u64),
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<Vec<u8>, Error> {
+    if let Some((_, Some(0))) = range {
+        return Ok(Vec::new());
+    }
+
+    let url = utils::join_url(base, path)?;
+    let mut request = backend.get(url.as_str());
+
+    if let Some((offset, length)) = range {
+        let bytes = match length {
+            Some(length) => {
+                //No file is that large, so the range can't be satisfied anyway.
+                let end = offset.checked_add(length - 1).ok_or_else(|| {
+                    Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        format!("Range of {} bytes at {} exceeds {}.", length, offset, path),
+                    ))
+                })?;
+                format!("bytes={}-{}", offset, end)
+            }
+            None => format!("bytes={}-", offset),
+        };
+        request = request.set("Range", &bytes);
+    }
+
+    let response = backend.retry.call(request).map_err(|err| match *err {
+        ureq::Error::Status(404, _) => Error::Io(std::io::Error::new(
             std::io::ErrorKind::NotFound,
-            format!("File {} not found.", path.display()),
-        )));
+            format!("File {} not found.", url),
+        )),
+        err if is_timeout(&err) => Error::Timeout(url.to_string()),
+        err => Error::Io(std::io::Error::other(format!(
+            "Failed to retrieve file {}. Err: {}",
+            url, err
+        ))),
+    })?;
+
+    let partial = response.status() == 206;
+
+    //Without a Content-Length the buffer just grows while reading.
+    let length = response
+        .header("Content-Length")
+        .and_then(|value| value.parse::<u64>().ok());
+
+    if let Some(length) = length {
+        size_hint(length);
+    }
+
+    let bytes = read_with_progress(response.into_reader(), length, progress)
+        .map_err(|err| read_error(url.as_str(), err))?;
+
+    match range {
+        //The server ignored the range and sent the whole file.
+        Some((offset, length)) if !partial => {
+            let start = (offset as usize).min(bytes.len());
+            let end = match length {
+                Some(length) => start
+                    .saturating_add(length.try_into().unwrap_or(usize::MAX))
+                    .min(bytes.len()),
+                None => bytes.len(),
+            };
+            Ok(bytes[start..end].to_vec())
+        }
+        _ => Ok(bytes),
+    }
+}
+
+fn read_range_impl(path: &Path, offset: u64, length: Option<u64>) -> Result<Vec<u8>, Error> {
+    let mut file = std::fs::File::open(path).map_err(Error::Io)?;
+    file.seek(SeekFrom::Start(offset)).map_err(Error::Io)?;
+
+    read_limited(file, length)
+}
+
+//Up front allocation for an expected size. Sizes come from headers that may lie, so it's capped.
+fn preallocation(length: Option<u64>) -> usize {
+    const MAX_PREALLOCATION: u64 = 256 * 1024 * 1024;
+
+    length.map_or(0, |length| length.min(MAX_PREALLOCATION)) as usize
+}
+
+fn read_tail_impl(path: &Path, length: u64) -> Result<(Vec<u8>, u64), Error> {
+    let mut file = std::fs::File::open(path).map_err(Error::Io)?;
+    let size = file.metadata().map_err(Error::Io)?.len();
+    let length = length.min(size);
+
+    file.seek(SeekFrom::Start(size - length))
+        .map_err(Error::Io)?;
+    read_limited(file, Some(length)).map(|bytes| (bytes, size))
+}
+
+fn read_limited<R: Read>(mut read: R, length: Option<u64>) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(preallocation(length));
+
+    match length {
+        Some(length) => {
+            read.take(length)
+                .read_to_end(&mut bytes)
+                .map_err(Error::Io)?;
+
+            if (bytes.len() as u64) < length {
+                return Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        None => {
+            read.read_to_end(&mut bytes).map_err(Error::Io)?;
+        }
     }
 
-    std::fs::read(path)
-        .map_err(Error::Io)
-        .map(|bytes| (bytes, None))
+    Ok(bytes)
+}
+
+fn map_file(path: &PathBuf, threshold: u64) -> Option<memmap2::Mmap> {
+    let file = std::fs::File::open(path).ok()?;
+
+    if file.metadata().ok()?.len() < threshold {
+        return None;
+    }
+
+    //Safety: The map is only valid as long as nobody truncates or rewrites the file.
+    //Assets are treated as read only while loaded, everything else falls back to fs::read.
+    match unsafe { memmap2::Mmap::map(&file) } {
+        Ok(map) => Some(map),
+        Err(err) => {
+            log::warn!(
+                "Failed to memory map {}. Reading it instead. Err: {}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+fn read_static_impl(
+    files: &[(&'static str, &'static [u8])],
+    path: &str,
+) -> Result<&'static [u8], Error> {
+    files
+        .iter()
+        .find(|(name, _)| utils::normalize_path(name) == path)
+        .map(|(_, bytes)| *bytes)
+        .ok_or_else(|| {
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Embedded file {} not found.", path),
+            ))
+        })
+}
+
+fn read_archive_impl(
+    archive: &Path,
+    path: &str,
+    size_hint: &mut dyn FnMut(u64),
+    progress: &mut dyn FnMut(u64, Option<u64>),
+) -> Result<(Bytes, ExtraFiles), Error> {
+    let file = std::fs::File::open(archive).map_err(Error::Io)?;
+    let mut archive =
+        zip::ZipArchive::new(std::io::BufReader::new(file)).map_err(|err| Error::Io(err.into()))?;
+
+    let mut entry = match archive.by_name(path) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Entry {} not found in archive.", path),
+            )))
+        }
+        Err(err) => return Err(Error::Io(err.into())),
+    };
+
+    size_hint(entry.size());
+
+    let size = entry.size();
+    let bytes = read_with_progress(&mut entry, Some(size), progress).map_err(Error::Io)?;
+
+    Ok((bytes.into(), None))
+}
+
+fn read_archive_range_impl(
+    archive: &Path,
+    path: &str,
+    offset: u64,
+    length: Option<u64>,
+) -> Result<Vec<u8>, Error> {
+    let file = std::fs::File::open(archive).map_err(Error::Io)?;
+    let mut archive =
+        zip::ZipArchive::new(std::io::BufReader::new(file)).map_err(|err| Error::Io(err.into()))?;
+
+    let mut entry = archive.by_name(path).map_err(|err| Error::Io(err.into()))?;
+
+    //Compressed entries can't seek, so skip ahead by decoding.
+    std::io::copy(&mut (&mut entry).take(offset), &mut std::io::sink()).map_err(Error::Io)?;
+
+    read_limited(entry, length)
+}
+
+fn read_archive_tail_impl(
+    archive: &Path,
+    path: &str,
+    length: u64,
+) -> Result<(Vec<u8>, u64), Error> {
+    let file = std::fs::File::open(archive).map_err(Error::Io)?;
+    let mut archive =
+        zip::ZipArchive::new(std::io::BufReader::new(file)).map_err(|err| Error::Io(err.into()))?;
+
+    let mut entry = archive.by_name(path).map_err(|err| Error::Io(err.into()))?;
+    let size = entry.size();
+    let length = length.min(size);
+
+    std::io::copy(&mut (&mut entry).take(size - length), &mut std::io::sink())
+        .map_err(Error::Io)?;
+
+    read_limited(entry, Some(length)).map(|bytes| (bytes, size))
 }
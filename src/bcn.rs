@@ -0,0 +1,50 @@
+use image::RgbaImage;
+use intel_tex_2::{astc, bc5, bc7, RgSurface, RgbaSurface};
+
+use crate::TextureFormat;
+
+/// Compress an image into 4x4 blocks of `format`, row by row. Edge blocks of images whose
+/// sides aren't a multiple of four repeat the last row and column.
+/// Returns None for formats that aren't block compressed.
+pub fn compress(image: &RgbaImage, format: TextureFormat) -> Option<Vec<u8>> {
+    let image = pad(image);
+    let (width, height) = image.dimensions();
+
+    let surface = RgbaSurface {
+        width,
+        height,
+        stride: width * 4,
+        data: image.as_raw(),
+    };
+
+    match format {
+        TextureFormat::Bc5 => {
+            let data = image
+                .pixels()
+                .flat_map(|pixel| [pixel[0], pixel[1]])
+                .collect::<Vec<u8>>();
+
+            Some(bc5::compress_blocks(&RgSurface {
+                width,
+                height,
+                stride: width * 2,
+                data: &data,
+            }))
+        }
+        TextureFormat::Bc7 => Some(bc7::compress_blocks(&bc7::alpha_basic_settings(), &surface)),
+        TextureFormat::Astc4x4 => Some(astc::compress_blocks(
+            &astc::alpha_fast_settings(4, 4),
+            &surface,
+        )),
+        _ => None,
+    }
+}
+
+//The encoders only take whole blocks.
+fn pad(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+
+    RgbaImage::from_fn(width.div_ceil(4) * 4, height.div_ceil(4) * 4, |x, y| {
+        *image.get_pixel(x.min(width - 1), y.min(height - 1))
+    })
+}
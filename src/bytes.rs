@@ -0,0 +1,54 @@
+use std::{ops::Deref, sync::Arc};
+
+use crate::lfu::ItemSize;
+
+/// Cheaply clonable view of a loaded file. Owned memory, a memory mapped file or data
+/// embedded in the binary.
+#[derive(Clone)]
+pub enum Bytes {
+    Owned(Arc<Vec<u8>>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Mapped(Arc<memmap2::Mmap>),
+    Static(&'static [u8]),
+}
+
+impl Bytes {
+    pub fn is_mapped(&self) -> bool {
+        match self {
+            Bytes::Owned(_) | Bytes::Static(_) => false,
+            #[cfg(not(target_arch = "wasm32"))]
+            Bytes::Mapped(_) => true,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(value: Vec<u8>) -> Self {
+        Bytes::Owned(Arc::new(value))
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Bytes::Owned(bytes) => bytes,
+            #[cfg(not(target_arch = "wasm32"))]
+            Bytes::Mapped(map) => map,
+            Bytes::Static(bytes) => bytes,
+        }
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ItemSize for Bytes {
+    fn size(&self) -> usize {
+        self.len()
+    }
+}
@@ -0,0 +1,74 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+
+use crate::{error::Error, HeaderEncryption};
+
+const CIPHER: &str = "chacha20-poly1305";
+
+/// Bytes encryption adds to a payload.
+pub const TAG_SIZE: usize = 16;
+
+pub fn encrypt(key: &[u8; 32], content: &[u8]) -> Result<(HeaderEncryption, Vec<u8>), String> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = rand::random::<[u8; 12]>();
+
+    let content = cipher
+        .encrypt(Nonce::from_slice(&nonce), content)
+        .map_err(|_| "Failed to encrypt asset payload.".to_string())?;
+
+    Ok((
+        HeaderEncryption {
+            cipher: CIPHER.to_string(),
+            nonce: STANDARD.encode(nonce),
+        },
+        content,
+    ))
+}
+
+/// Identifies `key` without revealing it, e.g. to notice outputs written with another key.
+pub fn key_id(key: &[u8; 32]) -> String {
+    //The tag of an empty message under a fixed nonce only depends on the key.
+    let tag = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&[0; 12]), &[][..])
+        .unwrap_or_default();
+
+    tag.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Encryption header of the same size as a real one, for estimates.
+pub fn placeholder() -> HeaderEncryption {
+    HeaderEncryption {
+        cipher: CIPHER.to_string(),
+        nonce: STANDARD.encode([0u8; 12]),
+    }
+}
+
+pub fn decrypt(
+    key: &Option<[u8; 32]>,
+    encryption: &HeaderEncryption,
+    content: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if encryption.cipher != CIPHER {
+        return Err(Error::Decryption(format!(
+            "Unsupported cipher {}.",
+            encryption.cipher
+        )));
+    }
+
+    let key = key.as_ref().ok_or_else(|| {
+        Error::Decryption("Asset is encrypted but no key was provided.".to_string())
+    })?;
+
+    let nonce = STANDARD
+        .decode(&encryption.nonce)
+        .ok()
+        .filter(|nonce| nonce.len() == 12)
+        .ok_or_else(|| Error::Decryption("Invalid nonce.".to_string()))?;
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(&nonce), content)
+        .map_err(|_| Error::Decryption("Authentication failed. Wrong key?".to_string()))
+}
@@ -0,0 +1,77 @@
+use std::f32::consts::PI;
+
+use image::{Rgba, Rgba32FImage};
+
+/// Sample an equirectangular panorama into six `size` x `size` cube faces,
+/// ordered +x, -x, +y, -y, +z, -z.
+/// The horizontal seam wraps around and rows clamp at the poles.
+pub fn to_cube_faces(panorama: &Rgba32FImage, size: u32) -> Vec<Rgba32FImage> {
+    //Average a 2x2 grid per texel, so faces smaller than the panorama don't alias.
+    const SAMPLES: u32 = 2;
+
+    (0..6)
+        .map(|face| {
+            Rgba32FImage::from_fn(size, size, |x, y| {
+                let mut sum = [0.0f32; 4];
+
+                for sy in 0..SAMPLES {
+                    for sx in 0..SAMPLES {
+                        let u = (x as f32 + (sx as f32 + 0.5) / SAMPLES as f32) / size as f32;
+                        let v = (y as f32 + (sy as f32 + 0.5) / SAMPLES as f32) / size as f32;
+
+                        let texel = sample(panorama, direction(face, u * 2.0 - 1.0, v * 2.0 - 1.0));
+                        sum.iter_mut().zip(texel).for_each(|(sum, c)| *sum += c);
+                    }
+                }
+
+                Rgba(sum.map(|c| c / (SAMPLES * SAMPLES) as f32))
+            })
+        })
+        .collect()
+}
+
+//Direction through a point of a face, following the usual GPU cubemap layout.
+fn direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
+
+fn sample(panorama: &Rgba32FImage, [x, y, z]: [f32; 3]) -> [f32; 4] {
+    let (width, height) = panorama.dimensions();
+
+    let length = (x * x + y * y + z * z).sqrt();
+    let longitude = x.atan2(-z);
+    let latitude = (y / length).clamp(-1.0, 1.0).acos();
+
+    //Texel space of the panorama, -z looks at its center.
+    let px = (0.5 + longitude / (2.0 * PI)) * width as f32 - 0.5;
+    let py = latitude / PI * height as f32 - 0.5;
+
+    let x0 = px.floor();
+    let y0 = py.floor();
+    let (fx, fy) = (px - x0, py - y0);
+
+    let texel = |x: f32, y: f32| {
+        let x = (x as i64).rem_euclid(width as i64) as u32;
+        let y = (y as i64).clamp(0, height as i64 - 1) as u32;
+        panorama.get_pixel(x, y).0
+    };
+
+    let lerp = |a: [f32; 4], b: [f32; 4], t: f32| {
+        let mut out = a;
+        out.iter_mut().zip(b).for_each(|(a, b)| *a += (b - *a) * t);
+        out
+    };
+
+    lerp(
+        lerp(texel(x0, y0), texel(x0 + 1.0, y0), fx),
+        lerp(texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0), fx),
+        fy,
+    )
+}
@@ -1,19 +1,106 @@
-use std::fmt::Debug;
+use std::{
+    fmt::{Debug, Display},
+    path::PathBuf,
+};
+
+use crate::AssetKind;
 
 pub enum Error {
     Io(std::io::Error),
     GltfError(gltf::Error),
+    GltfValidation(Vec<String>),
     JsonError(serde_json::Error),
+    Decryption(String),
+    AlreadyExists(PathBuf),
+    /// The file was written by an incompatible format version (major, minor).
+    UnsupportedVersion(u16, u16),
+    /// Reading an input file failed.
+    Read(PathBuf, std::io::Error),
+    /// An input image couldn't be decoded or encoded.
+    Image(PathBuf, image::ImageError),
+    /// A shader failed to parse or validate. Holds naga's diagnostic.
+    Shader(PathBuf, String),
+    /// Pinned items would need this many bytes, but the cache only holds that many.
+    CacheFull(usize, usize),
+    /// The file has no .fur header, e.g. an image that wasn't converted.
+    NotAFurFile(String),
+    /// A path that has to be stored or passed on as text isn't valid UTF-8.
+    InvalidPath(PathBuf),
+    /// The server at this url didn't answer in time. See DefaultBackend::with_timeouts.
+    Timeout(String),
+    /// The header size stored in the file (path, size) doesn't fit into the file.
+    InvalidHeader(String, u64),
+    /// A typed load such as What::load_texture found another kind of asset.
+    WrongAssetType {
+        expected: AssetKind,
+        found: AssetKind,
+    },
+    Conversion(String),
     Unknown(String),
 }
 
-impl Debug for Error {
+impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::Io(err) => write!(f, "Io error: {}", err),
             Error::GltfError(err) => write!(f, "Gltf error: {}", err),
+            Error::GltfValidation(warnings) => {
+                write!(f, "Gltf validation error: {}", warnings.join(" "))
+            }
             Error::JsonError(err) => write!(f, "Json error: {}", err),
+            Error::Decryption(err) => write!(f, "Decryption error: {}", err),
+            Error::AlreadyExists(path) => write!(f, "File {} already exists.", path.display()),
+            Error::UnsupportedVersion(major, minor) => {
+                write!(f, "Unsupported format version {}.{}.", major, minor)
+            }
+            Error::Read(path, err) => write!(f, "Failed to read {}: {}", path.display(), err),
+            Error::Image(path, err) => write!(f, "Image error in {}: {}", path.display(), err),
+            Error::Shader(path, err) => write!(f, "Shader error in {}: {}", path.display(), err),
+            Error::CacheFull(needed, capacity) => write!(
+                f,
+                "Pinned items need {} bytes, but the cache only holds {}.",
+                needed, capacity
+            ),
+            Error::NotAFurFile(path) => write!(
+                f,
+                "{} is not a .fur file. Convert it first, e.g. with what convert.",
+                path
+            ),
+            Error::InvalidPath(path) => {
+                write!(f, "Path {} is not valid UTF-8.", path.display())
+            }
+            Error::Timeout(url) => write!(f, "Timed out while retrieving {}.", url),
+            Error::InvalidHeader(path, size) => write!(
+                f,
+                "{} claims a header of {} bytes, which doesn't fit into the file.",
+                path, size
+            ),
+            Error::WrongAssetType { expected, found } => write!(
+                f,
+                "Expected a {} asset, but found a {}.",
+                expected.as_str(),
+                found.as_str()
+            ),
+            Error::Conversion(err) => write!(f, "Conversion error: {}", err),
             Error::Unknown(err) => write!(f, "Unknown error: {}", err),
         }
     }
 }
+
+impl Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) | Error::Read(_, err) => Some(err),
+            Error::GltfError(err) => Some(err),
+            Error::JsonError(err) => Some(err),
+            Error::Image(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -1,14 +1,38 @@
 use std::{
-    collections::{BinaryHeap, HashMap},
+    collections::{BinaryHeap, HashMap, HashSet, VecDeque},
+    fmt::Debug,
     hash::Hash,
 };
 
+use crate::error::Error;
+
+//How many evicted keys are remembered to detect thrashing.
+const RECENT_EVICTIONS: usize = 64;
+
+/// Bytes counted per item on top of the item and its key: the frequency and priority kept
+/// next to it and the hash map's control bytes. Like all sizes here, an approximation.
+pub const DEFAULT_ENTRY_OVERHEAD: usize = 2 * std::mem::size_of::<usize>() + 8;
+
+/// Bytes a value takes up in the cache. Keys implement it too, so their size is counted.
 pub trait ItemSize {
     fn size(&self) -> usize;
 }
 
+/// Which item the cache drops first once it is full. Lower priorities always go first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Least frequently used.
+    #[default]
+    Lfu,
+    /// Least recently used.
+    Lru,
+    /// First inserted.
+    Fifo,
+}
+
 struct CacheEntry<Key> {
     key: Key,
+    //Use count, last use or insertion time depending on the policy.
     frequency: usize,
     priority: usize,
 }
@@ -18,6 +42,15 @@ pub struct LfuCache<Key: Hash + Eq, Item: ItemSize> {
     max_size_in_bytes: usize,
     key_val: HashMap<Key, (Item, usize, usize)>,
     heap: BinaryHeap<CacheEntry<Key>>,
+    //Item handed out by get_mut and its size at that time, reconciled on the next call.
+    lent: Option<(Key, usize)>,
+    evicted: VecDeque<Key>,
+    //Never evicted. Their bytes still count towards the size.
+    pinned: HashSet<Key>,
+    pinned_bytes: usize,
+    policy: EvictionPolicy,
+    clock: usize,
+    entry_overhead: usize,
 }
 
 impl ItemSize for Vec<u8> {
@@ -26,12 +59,26 @@ impl ItemSize for Vec<u8> {
     }
 }
 
+impl ItemSize for String {
+    fn size(&self) -> usize {
+        std::mem::size_of::<String>() + self.len()
+    }
+}
+
+impl ItemSize for u32 {
+    fn size(&self) -> usize {
+        std::mem::size_of::<u32>()
+    }
+}
+
 impl<Key> Ord for CacheEntry<Key> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        //Compare first using priority and after that using frequency
-        self.priority
-            .cmp(&other.priority)
-            .then(self.frequency.cmp(&other.frequency))
+        //Compare first using priority and after that using frequency.
+        //Reversed, so the heap pops the least important entry first.
+        other
+            .priority
+            .cmp(&self.priority)
+            .then(other.frequency.cmp(&self.frequency))
     }
 }
 
@@ -50,31 +97,137 @@ impl<Key> PartialEq for CacheEntry<Key> {
     }
 }
 
-impl<Key: Hash + Eq + Clone, Item: ItemSize> LfuCache<Key, Item> {
+impl<Key: Hash + Eq + Clone + Debug + ItemSize, Item: ItemSize> LfuCache<Key, Item> {
     pub fn new(capacity: usize) -> Self {
+        Self::with_capacity(capacity, 0)
+    }
+
+    /// Like new, but allocates room for `entries` items up front.
+    pub fn with_capacity(capacity: usize, entries: usize) -> Self {
+        Self::with_policy(capacity, entries, EvictionPolicy::default())
+    }
+
+    /// Like with_capacity, but evicts according to `policy`.
+    pub fn with_policy(capacity: usize, entries: usize, policy: EvictionPolicy) -> Self {
         Self {
             size_in_bytes: 0,
             max_size_in_bytes: capacity,
-            key_val: HashMap::new(),
-            heap: BinaryHeap::new(),
+            key_val: HashMap::with_capacity(entries),
+            heap: BinaryHeap::with_capacity(entries),
+            lent: None,
+            evicted: VecDeque::new(),
+            pinned: HashSet::new(),
+            pinned_bytes: 0,
+            policy,
+            clock: 0,
+            entry_overhead: DEFAULT_ENTRY_OVERHEAD,
+        }
+    }
+
+    pub fn entry_overhead(&self) -> usize {
+        self.entry_overhead
+    }
+
+    /// Count `overhead` bytes per item on top of the item and its key, instead of
+    /// DEFAULT_ENTRY_OVERHEAD. Evicts if the cache is too full then.
+    pub fn set_entry_overhead(&mut self, overhead: usize) {
+        self.settle();
+        self.entry_overhead = overhead;
+        self.recount();
+        self.shrink_to_fit(self.max_size_in_bytes);
+    }
+
+    pub fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    /// Switch to another policy. Drops every item, since their order can't be carried over.
+    pub fn set_policy(&mut self, policy: EvictionPolicy) {
+        self.clear();
+        self.policy = policy;
+    }
+
+    /// Make room for at least `additional` more items without reallocating.
+    pub fn reserve(&mut self, additional: usize) {
+        self.key_val.reserve(additional);
+        self.heap.reserve(additional);
+    }
+
+    /// Number of items the cache can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.key_val.capacity().min(self.heap.capacity())
+    }
+
+    /// Bytes currently accounted for, including changes made through get_mut. Items count
+    /// with their key and the entry overhead, but allocator slack isn't known, so this only
+    /// approximates the memory in use.
+    pub fn size_in_bytes(&self) -> usize {
+        match &self.lent {
+            Some((key, size)) => match self.key_val.get(key) {
+                Some((item, _, _)) => self.size_in_bytes + item.size() - size,
+                None => self.size_in_bytes,
+            },
+            None => self.size_in_bytes,
         }
     }
 
     pub fn insert(&mut self, key: &Key, value: Item, priority: usize) {
-        let size = value.size() + std::mem::size_of::<CacheEntry<Key>>();
+        let frequency = match self.policy {
+            EvictionPolicy::Lfu => 0,
+            EvictionPolicy::Lru | EvictionPolicy::Fifo => self.tick(),
+        };
+
+        self.insert_ranked(key, value, frequency, priority);
+    }
+
+    /// Like insert, but keeps a frequency from an earlier session, as returned by entries.
+    /// Returns false instead of panicking if the item is too large for the cache.
+    pub fn restore(&mut self, key: &Key, value: Item, frequency: usize, priority: usize) -> bool {
+        if self.resident_size(key, &value) + std::mem::size_of::<CacheEntry<Key>>()
+            > self.max_size_in_bytes
+        {
+            return false;
+        }
+
+        if self.policy != EvictionPolicy::Lfu {
+            self.clock = self.clock.max(frequency);
+        }
+
+        self.insert_ranked(key, value, frequency, priority);
+        true
+    }
+
+    fn insert_ranked(&mut self, key: &Key, value: Item, frequency: usize, priority: usize) {
+        self.settle();
+
+        let size = self.resident_size(key, &value) + std::mem::size_of::<CacheEntry<Key>>();
 
         if size > self.max_size_in_bytes {
             panic!("Item is too large to fit in cache");
         }
+
+        //A replaced item stops counting now. Its heap entries go stale.
+        let old = self.key_val.remove(key);
+        if let Some((item, _, _)) = &old {
+            self.size_in_bytes -= self.resident_size(key, item);
+        }
         self.size_in_bytes += size;
 
         self.shrink_to_fit(self.max_size_in_bytes);
 
-        self.key_val.insert(key.clone(), (value, 0, priority));
+        self.evicted.retain(|evicted| evicted != key);
+
+        if self.pinned.contains(key) {
+            let old = old.map_or(0, |(item, _, _)| item.size());
+            self.pinned_bytes = self.pinned_bytes + value.size() - old;
+        }
+
+        self.key_val
+            .insert(key.clone(), (value, frequency, priority));
 
         let entry = CacheEntry::<Key> {
             key: key.clone(),
-            frequency: 0,
+            frequency,
             priority,
         };
 
@@ -82,36 +235,531 @@ impl<Key: Hash + Eq + Clone, Item: ItemSize> LfuCache<Key, Item> {
     }
 
     pub fn shrink_to_fit(&mut self, max_size: usize) {
+        self.settle();
         self.max_size_in_bytes = max_size;
+
+        //Entries of pinned items are put back once enough else is gone.
+        let mut kept = Vec::new();
+
         while self.size_in_bytes > self.max_size_in_bytes {
-            let entry = self.heap.pop().unwrap();
+            let Some(entry) = self.heap.pop() else {
+                break;
+            };
+
+            //Otherwise a stale entry of an item that was touched or removed since.
+            let current = matches!(
+                self.key_val.get(&entry.key),
+                Some((_, frequency, priority))
+                    if *frequency == entry.frequency && *priority == entry.priority
+            );
+
+            if current && self.pinned.contains(&entry.key) {
+                kept.push(entry);
+                continue;
+            }
+
             self.size_in_bytes -= std::mem::size_of::<CacheEntry<Key>>();
 
-            if entry.frequency != self.key_val[&entry.key].1
-                || entry.priority != self.key_val[&entry.key].2
-            {
+            if !current {
                 continue;
             }
 
-            self.size_in_bytes -= self.key_val[&entry.key].0.size();
+            let item = &self.key_val[&entry.key].0;
+            let size = item.size();
+            self.size_in_bytes -= self.resident_size(&entry.key, item);
             self.key_val.remove(&entry.key);
+
+            log::debug!(
+                "Evicted {:?} from the cache, freeing {} bytes.",
+                entry.key,
+                size
+            );
+
+            self.remember_evicted(entry.key);
+        }
+
+        self.heap.extend(kept);
+    }
+
+    /// Evict every item with a priority below `threshold`, no matter how often it was used.
+    /// Pinned items stay. Their heap entries are released on the next eviction. Returns how
+    /// many items were evicted.
+    pub fn evict_priority_below(&mut self, threshold: usize) -> usize {
+        self.settle();
+
+        let keys = self
+            .key_val
+            .iter()
+            .filter(|(key, (_, _, priority))| *priority < threshold && !self.pinned.contains(*key))
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+
+        for key in &keys {
+            if let Some((item, _, _)) = self.key_val.remove(key) {
+                self.size_in_bytes -= self.resident_size(key, &item);
+                self.remember_evicted(key.clone());
+            }
+        }
+
+        log::debug!(
+            "Evicted {} items below priority {} from the cache.",
+            keys.len(),
+            threshold
+        );
+
+        keys.len()
+    }
+
+    /// Keep `key` until it is unpinned or removed, no matter the pressure. Fails if the key
+    /// isn't cached or all pinned items together wouldn't fit into the cache.
+    pub fn pin(&mut self, key: &Key) -> Result<(), Error> {
+        self.settle();
+
+        if self.pinned.contains(key) {
+            return Ok(());
+        }
+
+        let size = match self.key_val.get(key) {
+            Some((item, _, _)) => {
+                self.resident_size(key, item) + std::mem::size_of::<CacheEntry<Key>>()
+            }
+            None => return Err(Error::Unknown(format!("{:?} is not cached.", key))),
+        };
+
+        if self.pinned_bytes + size > self.max_size_in_bytes {
+            return Err(Error::CacheFull(
+                self.pinned_bytes + size,
+                self.max_size_in_bytes,
+            ));
+        }
+
+        self.pinned.insert(key.clone());
+        self.pinned_bytes += size;
+
+        Ok(())
+    }
+
+    /// Insert and pin in one go. Fails without inserting if all pinned items together
+    /// wouldn't fit into the cache.
+    pub fn insert_pinned(&mut self, key: &Key, value: Item, priority: usize) -> Result<(), Error> {
+        self.settle();
+
+        let size = self.resident_size(key, &value) + std::mem::size_of::<CacheEntry<Key>>();
+        let pinned = self.pinned_bytes + size;
+
+        if pinned > self.max_size_in_bytes {
+            return Err(Error::CacheFull(pinned, self.max_size_in_bytes));
+        }
+
+        self.unpin(key);
+        self.insert(key, value, priority);
+        self.pin(key)
+    }
+
+    /// Let `key` be evicted again. Does nothing if it isn't pinned.
+    pub fn unpin(&mut self, key: &Key) {
+        self.settle();
+
+        if self.pinned.remove(key) {
+            if let Some((item, _, _)) = self.key_val.get(key) {
+                self.pinned_bytes -=
+                    self.resident_size(key, item) + std::mem::size_of::<CacheEntry<Key>>();
+            }
+        }
+    }
+
+    pub fn is_pinned(&self, key: &Key) -> bool {
+        self.pinned.contains(key)
+    }
+
+    /// Evict until an item of `size` bytes fits, e.g. before it is downloaded.
+    /// Does nothing if it wouldn't fit even into the empty cache.
+    pub fn make_room(&mut self, size: usize) {
+        let max_size = self.max_size_in_bytes;
+        //The key isn't known yet, so it isn't counted.
+        let needed = size + std::mem::size_of::<CacheEntry<Key>>() + self.entry_overhead;
+
+        if needed <= max_size {
+            self.shrink_to_fit(max_size - needed);
+            self.max_size_in_bytes = max_size;
         }
     }
 
+    /// Drop `key` without counting it as evicted. Its heap entries are released on the
+    /// next eviction.
+    pub fn remove(&mut self, key: &Key) -> Option<Item> {
+        self.unpin(key);
+
+        let (item, _, _) = self.key_val.remove(key)?;
+        self.size_in_bytes -= self.resident_size(key, &item);
+
+        Some(item)
+    }
+
+    /// Whether `key` was among the last evicted items and hasn't been inserted since.
+    pub fn recently_evicted(&self, key: &Key) -> bool {
+        self.evicted.contains(key)
+    }
+
+    /// Doesn't count as a use.
+    pub fn contains_key(&self, key: &Key) -> bool {
+        self.key_val.contains_key(key)
+    }
+
+    /// Resident keys. Doesn't count as a use.
+    pub fn keys(&self) -> impl Iterator<Item = &Key> {
+        self.key_val.keys()
+    }
+
+    /// Resident keys and items. Doesn't count as a use.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key, &Item)> {
+        self.key_val.iter().map(|(key, (item, _, _))| (key, item))
+    }
+
+    /// Resident keys and items with their frequency and priority. Doesn't count as a use.
+    pub fn entries(&self) -> impl Iterator<Item = (&Key, &Item, usize, usize)> {
+        self.key_val
+            .iter()
+            .map(|(key, (item, frequency, priority))| (key, item, *frequency, *priority))
+    }
+
+    pub fn max_size_in_bytes(&self) -> usize {
+        self.max_size_in_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.key_val.clear();
+        self.heap.clear();
+        self.size_in_bytes = 0;
+        self.lent = None;
+        self.evicted.clear();
+        self.pinned.clear();
+        self.pinned_bytes = 0;
+        self.clock = 0;
+    }
+
     pub fn get(&mut self, key: &Key) -> Option<&Item> {
-        if let Some((item, frequency, priority)) = self.key_val.get_mut(key) {
-            if let Some(result) = frequency.checked_add(1) {
-                *frequency = result;
-                self.heap.push(CacheEntry {
-                    key: key.clone(),
-                    frequency: *frequency,
-                    priority: *priority,
-                });
+        self.settle();
+        self.touch(key);
+
+        self.key_val.get(key).map(|(item, _, _)| item)
+    }
+
+    /// Like get, but allows changing the item in place.
+    /// Size changes are picked up on the next call into the cache. Until then the cache
+    /// may hold more than its maximum size.
+    pub fn get_mut(&mut self, key: &Key) -> Option<&mut Item> {
+        self.settle();
+        self.touch(key);
+
+        let (item, _, _) = self.key_val.get_mut(key)?;
+        self.lent = Some((key.clone(), item.size()));
+
+        Some(item)
+    }
+
+    fn touch(&mut self, key: &Key) {
+        if self.policy == EvictionPolicy::Fifo || !self.key_val.contains_key(key) {
+            return;
+        }
+
+        if self.policy == EvictionPolicy::Lfu
+            && matches!(self.key_val.get(key), Some((_, usize::MAX, _)))
+        {
+            self.renormalize();
+        }
+
+        let now = self.tick();
+
+        if let Some((_, frequency, priority)) = self.key_val.get_mut(key) {
+            match self.policy {
+                EvictionPolicy::Lfu => *frequency += 1,
+                _ => *frequency = now,
             }
+            self.size_in_bytes += std::mem::size_of::<CacheEntry<Key>>();
+            self.heap.push(CacheEntry {
+                key: key.clone(),
+                frequency: *frequency,
+                priority: *priority,
+            });
+        }
+    }
 
-            Some(item)
-        } else {
-            None
+    fn remember_evicted(&mut self, key: Key) {
+        if self.evicted.len() == RECENT_EVICTIONS {
+            self.evicted.pop_front();
         }
+        self.evicted.push_back(key);
+    }
+
+    fn tick(&mut self) -> usize {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn settle(&mut self) {
+        if let Some((key, size)) = &self.lent {
+            if let (true, Some((item, _, _))) = (self.pinned.contains(key), self.key_val.get(key)) {
+                self.pinned_bytes = self.pinned_bytes + item.size() - size;
+            }
+        }
+
+        self.size_in_bytes = self.size_in_bytes();
+        self.lent = None;
+    }
+
+    fn resident_size(&self, key: &Key, item: &Item) -> usize {
+        item.size() + key.size() + self.entry_overhead
+    }
+
+    //Account everything from scratch, e.g. after the entry overhead changed.
+    fn recount(&mut self) {
+        let entry = std::mem::size_of::<CacheEntry<Key>>();
+
+        self.size_in_bytes = self.heap.len() * entry;
+        self.pinned_bytes = 0;
+
+        for (key, (item, _, _)) in &self.key_val {
+            let size = self.resident_size(key, item);
+            self.size_in_bytes += size;
+
+            if self.pinned.contains(key) {
+                self.pinned_bytes += size + entry;
+            }
+        }
+    }
+
+    //Halve every frequency so they can keep counting. The relative order stays the same.
+    //The heap is rebuilt without stale entries, so it's back to one entry per item.
+    fn renormalize(&mut self) {
+        self.heap.clear();
+
+        for (key, (_, frequency, priority)) in self.key_val.iter_mut() {
+            *frequency /= 2;
+            self.heap.push(CacheEntry {
+                key: key.clone(),
+                frequency: *frequency,
+                priority: *priority,
+            });
+        }
+
+        self.recount();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: usize = std::mem::size_of::<CacheEntry<u32>>();
+    //Accounted size of an item of 100 bytes under a u32 key.
+    const ITEM: usize = 100 + 4 + DEFAULT_ENTRY_OVERHEAD + ENTRY;
+
+    #[test]
+    fn test_frequency_overflow() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(2 * ITEM + 2 * ENTRY);
+
+        cache.insert(&1, vec![0; 100], 0);
+        cache.insert(&2, vec![0; 100], 0);
+
+        cache.key_val.get_mut(&1).unwrap().1 = usize::MAX - 1;
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+
+        assert_eq!(cache.key_val[&1].1, usize::MAX / 2 + 1);
+        assert_eq!(cache.key_val[&2].1, 1);
+
+        cache.insert(&3, vec![0; 100], 0);
+
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&3).is_some());
+    }
+
+    #[test]
+    fn test_with_capacity() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::with_capacity(1000, 64);
+        assert!(cache.capacity() >= 64);
+
+        cache.reserve(256);
+        assert!(cache.capacity() >= 256);
+    }
+
+    #[test]
+    fn test_get_mut_size() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(1000);
+
+        cache.insert(&1, vec![0; 100], 0);
+        let size = cache.size_in_bytes();
+
+        cache.get_mut(&1).unwrap().resize(300, 1);
+        assert_eq!(cache.size_in_bytes(), size + ENTRY + 200);
+
+        cache.get_mut(&1).unwrap().truncate(50);
+        assert_eq!(cache.size_in_bytes(), size + 2 * ENTRY - 50);
+
+        assert_eq!(cache.get(&1).unwrap(), &vec![0; 50]);
+    }
+
+    #[test]
+    fn test_eviction_policies() {
+        let evicted = |policy| {
+            //Just too small for three items, so exactly one has to go.
+            let mut cache = LfuCache::<u32, Vec<u8>>::with_policy(3 * ITEM - 1, 0, policy);
+
+            cache.insert(&1, vec![0; 100], 0);
+            cache.insert(&2, vec![0; 100], 0);
+            cache.get(&1);
+            cache.get(&1);
+            cache.get(&2);
+            cache.insert(&3, vec![0; 100], 0);
+
+            [1, 2, 3]
+                .into_iter()
+                .filter(|key| cache.get(key).is_none())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(evicted(EvictionPolicy::Lfu), [2]);
+        assert_eq!(evicted(EvictionPolicy::Lru), [1]);
+        assert_eq!(evicted(EvictionPolicy::Fifo), [1]);
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(3 * ITEM);
+
+        cache.insert(&1, vec![0; 100], 0);
+        cache.pin(&1).unwrap();
+
+        //Higher priority and used more, but pinned items still stay.
+        for key in 2..20 {
+            cache.insert(&key, vec![0; 100], 5);
+            cache.get(&key);
+        }
+
+        assert!(cache.get(&1).is_some());
+        assert!(cache.keys().count() <= 3);
+
+        //Only pinned items left, so nothing can go.
+        cache.pin(&19).unwrap();
+        cache.shrink_to_fit(0);
+        assert_eq!(cache.keys().count(), 2);
+
+        cache.shrink_to_fit(2 * ITEM);
+        assert!(matches!(
+            cache.insert_pinned(&20, vec![0; 100], 0),
+            Err(Error::CacheFull(_, _))
+        ));
+        assert!(cache.get(&20).is_none());
+        assert!(cache.pin(&21).is_err());
+
+        cache.unpin(&1);
+        cache.shrink_to_fit(ITEM);
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&19).is_some());
+    }
+
+    #[test]
+    fn test_evict_priority_below() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(10 * ITEM);
+
+        cache.insert(&1, vec![0; 100], 0);
+        cache.insert(&2, vec![0; 100], 1);
+        cache.insert(&3, vec![0; 100], 2);
+        cache.insert(&4, vec![0; 100], 0);
+        cache.pin(&4).unwrap();
+
+        //Used a lot, but the priority is all that counts.
+        for _ in 0..10 {
+            cache.get(&1);
+        }
+
+        let size = cache.size_in_bytes();
+        assert_eq!(cache.evict_priority_below(1), 1);
+        assert_eq!(
+            cache.size_in_bytes(),
+            size - (100 + 4 + DEFAULT_ENTRY_OVERHEAD)
+        );
+
+        assert!(cache.recently_evicted(&1));
+        assert!(!cache.contains_key(&1));
+        assert!(cache.contains_key(&2));
+        assert!(cache.contains_key(&3));
+        assert!(cache.contains_key(&4));
+
+        assert_eq!(cache.evict_priority_below(2), 1);
+        assert_eq!(cache.keys().count(), 2);
+    }
+
+    #[test]
+    fn test_key_size() {
+        let mut cache = LfuCache::<String, Vec<u8>>::new(10000);
+
+        cache.insert(&"a".to_string(), vec![0; 10], 0);
+        let short = cache.size_in_bytes();
+
+        cache.insert(&"b".repeat(1000), vec![0; 10], 0);
+        assert!(cache.size_in_bytes() - short >= 1000 + 10);
+
+        //A larger overhead is counted for every resident item.
+        cache.set_entry_overhead(DEFAULT_ENTRY_OVERHEAD + 100);
+        assert_eq!(cache.entry_overhead(), DEFAULT_ENTRY_OVERHEAD + 100);
+        assert!(cache.size_in_bytes() >= short + 1000 + 10 + 2 * 100);
+
+        cache.remove(&"a".to_string());
+        cache.remove(&"b".repeat(1000));
+        assert_eq!(
+            cache.size_in_bytes(),
+            2 * std::mem::size_of::<CacheEntry<String>>()
+        );
+    }
+
+    #[test]
+    fn test_evicts_least_frequent() {
+        //Room for two items, whatever the overhead per entry.
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(2500);
+
+        cache.insert(&1, vec![0; 1000], 0);
+        cache.insert(&2, vec![0; 1000], 0);
+        cache.get(&1);
+        cache.get(&1);
+        cache.insert(&3, vec![0; 1000], 0);
+
+        //Popping the heap in its old order dropped the most used item.
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&2).is_none());
+        assert!(cache.get(&3).is_some());
+    }
+
+    #[test]
+    fn test_evicts_lowest_priority() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(2500);
+
+        cache.insert(&1, vec![0; 1000], 0);
+        cache.get(&1);
+        cache.get(&1);
+        cache.insert(&2, vec![0; 1000], 1);
+        cache.insert(&3, vec![0; 1000], 1);
+
+        assert!(cache.get(&1).is_none());
+        assert!(cache.get(&2).is_some());
+        assert!(cache.get(&3).is_some());
+    }
+
+    #[test]
+    fn test_replace_size() {
+        let mut cache = LfuCache::<u32, Vec<u8>>::new(10 * ITEM);
+
+        cache.insert(&1, vec![0; 100], 0);
+        let size = cache.size_in_bytes();
+
+        //Only the stale heap entry of the old item is left over.
+        cache.insert(&1, vec![0; 50], 0);
+        assert_eq!(cache.size_in_bytes(), size + ENTRY - 50);
+
+        cache.remove(&1);
+        assert_eq!(cache.size_in_bytes(), 2 * ENTRY);
     }
 }
@@ -1,19 +1,46 @@
-use backend::Backend;
 use byteorder::ReadBytesExt;
-use error::Error;
-use lfu::LfuCache;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    borrow::Cow,
+    collections::{HashMap, HashSet},
     io::Cursor,
+    ops::ControlFlow,
     path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant, UNIX_EPOCH},
 };
-use utils::{Guid, GuidGenerator};
+use utils::GuidGenerator;
 
 mod backend;
+#[cfg(feature = "bcn")]
+mod bcn;
+mod bytes;
+mod crypto;
+mod equirect;
 mod error;
 mod lfu;
+mod manifest;
+mod metadata;
+mod normal;
+mod preprocess;
+mod reader;
+mod reflect;
 mod utils;
+mod validation;
+#[cfg(not(target_arch = "wasm32"))]
+mod watch;
+
+pub use backend::{Backend, DefaultBackend, ExtraFiles, RetryPolicy};
+pub use bytes::Bytes;
+pub use error::Error;
+pub use lfu::{EvictionPolicy, ItemSize, LfuCache, DEFAULT_ENTRY_OVERHEAD};
+pub use manifest::{Manifest, ManifestEntry};
+pub use reader::TextureArrayReader;
+pub use reflect::{BindingKind, ShaderBinding, ShaderEntryPoint, ShaderIo, ShaderReflection};
+pub use utils::Guid;
+
+/// Extension of written assets unless changed with What::set_extension.
+pub const DEFAULT_EXTENSION: &str = "fur";
 
 //--------------------------------------------------------------------------------------------------
 // Internal Header Format
@@ -23,22 +50,78 @@ mod utils;
 struct HeaderEntry {
     key: String,
     offset: u64,
+    //Entries may share a payload, so the next entry's offset isn't necessarily the end.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    length: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize)]
 enum HeaderType {
     Texture(HeaderTexture),
     TextureArray(HeaderTextureArray),
+    Texture3D(HeaderTexture3D),
     Shader(HeaderShader),
+    ShaderProgram(HeaderShaderProgram),
     Gltf(HeaderGltf),
 }
 
+impl HeaderType {
+    fn kind(&self) -> AssetKind {
+        match self {
+            HeaderType::Texture(_) => AssetKind::Texture,
+            HeaderType::TextureArray(_) => AssetKind::TextureArray,
+            HeaderType::Texture3D(_) => AssetKind::Texture3D,
+            HeaderType::Shader(_) => AssetKind::Shader,
+            HeaderType::ShaderProgram(_) => AssetKind::ShaderProgram,
+            HeaderType::Gltf(_) => AssetKind::Gltf,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct HeaderTexture {
     width: u32,
     height: u32,
     format: Option<String>,
     offset: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    regions: Vec<TextureRegion>,
+    #[serde(default)]
+    color_space: ColorSpace,
+    #[serde(default)]
+    alpha_mode: AlphaMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sampler: Option<SamplerInfo>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    variants: Vec<HeaderTextureVariant>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    mips: Vec<HeaderTextureMip>,
+}
+
+//The main texture comes last in the payload, so readers that don't know variants still find it.
+#[derive(Serialize, Deserialize)]
+struct HeaderTextureVariant {
+    format: TextureFormat,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderTextureMip {
+    width: u32,
+    height: u32,
+    format: Option<String>,
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderTexture3D {
+    width: u32,
+    height: u32,
+    depth: u32,
+    format: Option<String>,
+    offset: u64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -51,12 +134,34 @@ struct HeaderTextureArray {
 #[derive(Serialize, Deserialize)]
 struct HeaderGltf {
     offset: u64,
+    //Buffers and images of split files, referenced by their key as URI. The JSON comes last.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    entries: Vec<HeaderEntry>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct HeaderShader {
     offset: u64,
     stages: ShaderStages,
+    #[serde(default)]
+    optimized: bool,
+    #[serde(default)]
+    reflection: Option<ShaderReflection>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    defines: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workgroup_size: Option<[u32; 3]>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderShaderProgram {
+    shaders: Vec<HeaderShader>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderEncryption {
+    cipher: String,
+    nonce: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -64,6 +169,36 @@ struct BaseHeader {
     major: u16,
     minor: u16,
     ctype: HeaderType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<HeaderEncryption>,
+    //Hash of the sources and settings, written by incremental builds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+    //Whether a Footer follows the payload.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    footer: bool,
+}
+
+//Header of a cache snapshot. The cached bytes follow it like the payload of a .fur file.
+#[derive(Serialize, Deserialize)]
+struct CacheSnapshot {
+    major: u16,
+    minor: u16,
+    entries: Vec<CacheSnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheSnapshotEntry {
+    guid: Guid,
+    path: String,
+    frequency: usize,
+    priority: usize,
+    offset: u64,
+    length: u64,
+    //Size and modification time of the source when saved. Entries whose source changed since
+    //are skipped on load.
+    #[serde(default)]
+    source: Option<(u64, u64)>,
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -71,10 +206,27 @@ struct BaseHeader {
 //--------------------------------------------------------------------------------------------------
 
 const VERSION_MAJOR: u16 = 1;
-const VERSION_MINOR: u16 = 0;
+//1.1 added encryption, fingerprints and the footer.
+const VERSION_MINOR: u16 = 1;
+
+/// Format version (major, minor) of the .fur files written by this crate. Files of the same
+/// major and an equal or lower minor version can be loaded.
+pub const FORMAT_VERSION: (u16, u16) = (VERSION_MAJOR, VERSION_MINOR);
+
+/// See FORMAT_VERSION.
+pub fn format_version() -> (u16, u16) {
+    FORMAT_VERSION
+}
+
+const CUBE_FACES: [&str; 6] = ["+x", "-x", "+y", "-y", "+z", "-z"];
+const SHADER_STAGE_SUFFIXES: [(&str, ShaderStages); 3] = [
+    (".vert.wgsl", ShaderStages::VERTEX),
+    (".frag.wgsl", ShaderStages::FRAGMENT),
+    (".comp.wgsl", ShaderStages::COMPUTE),
+];
 
 bitflags::bitflags! {
-    #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+    #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
     pub struct ShaderStages: u8 {
         const VERTEX = 0b00000001;
         const FRAGMENT = 0b00000010;
@@ -82,489 +234,4392 @@ bitflags::bitflags! {
     }
 }
 
+/// Serializable with serde, `data` is stored as base64.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ShaderData {
+    #[serde(with = "utils::base64_words")]
     pub data: Vec<u32>,
     pub stages: ShaderStages,
+    /// Whether the module was compacted before writing, see ShaderOptions::optimize.
+    pub optimized: bool,
+    pub reflection: Option<ShaderReflection>,
+    /// Preprocessor defines the shader was compiled with.
+    #[serde(default)]
+    pub defines: Vec<(String, String)>,
+    /// Workgroup size of the compute entry point, None for other stages.
+    #[serde(default)]
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShaderProgramData {
+    pub stages: HashMap<ShaderStages, ShaderData>,
 }
 
+/// Serializable with serde, `data` is stored as base64.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TextureData {
     pub width: u32,
     pub height: u32,
     pub format: Option<String>,
+    #[serde(with = "utils::base64_bytes")]
     pub data: Vec<u8>,
+    pub regions: Vec<TextureRegion>,
+    pub color_space: ColorSpace,
+    pub alpha_mode: AlphaMode,
+    pub sampler: Option<SamplerInfo>,
+    /// Other encodings of the same texture. Loading picks one of them or the main data, so
+    /// loaded textures have none.
+    #[serde(default)]
+    pub variants: Vec<TextureVariant>,
+    /// Smaller levels, each half the size of the one before. Loaded textures have none, use
+    /// What::load_texture_mip to load a single level.
+    #[serde(default)]
+    pub mips: Vec<TextureMip>,
 }
 
-pub struct TextureArrayData {
-    pub size: u32,
-    pub format: Option<String>,
-    pub keys: Vec<String>,
-    pub data: Vec<Vec<u8>>,
+/// Serializable with serde, `data` is stored as base64.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TextureVariant {
+    pub format: TextureFormat,
+    #[serde(with = "utils::base64_bytes")]
+    pub data: Vec<u8>,
 }
 
-pub enum Asset {
-    Texture(TextureData),
-    TextureArray(TextureArrayData),
-    Shader(ShaderData),
-    Gltf(
-        gltf::Document,
-        Vec<gltf::buffer::Data>,
-        Vec<gltf::image::Data>,
-    ),
+/// Serializable with serde, `data` is stored as base64.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TextureMip {
+    pub width: u32,
+    pub height: u32,
+    pub format: Option<String>,
+    #[serde(with = "utils::base64_bytes")]
+    pub data: Vec<u8>,
 }
 
-pub enum Location {
-    File(PathBuf),
-    Http(String),
+/// Raw texel layouts, stored as the format of textures that hold pixels instead of an image file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum TextureFormat {
+    Rgba8,
+    /// Four little endian f32 per texel.
+    Rgba32F,
+    /// Two channel 4x4 blocks of 16 bytes, e.g. for normal maps.
+    Bc5,
+    /// RGBA 4x4 blocks of 16 bytes.
+    Bc7,
+    /// RGBA 4x4 ASTC blocks of 16 bytes, for mobile GPUs.
+    Astc4x4,
 }
 
-pub struct What {
-    guid_generator: GuidGenerator,
-    paths: HashMap<String, Guid>,
-    cache: LfuCache<Guid, Vec<u8>>,
-    location: Option<Location>,
+impl TextureFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TextureFormat::Rgba8 => "rgba8",
+            TextureFormat::Rgba32F => "rgba32f",
+            TextureFormat::Bc5 => "bc5",
+            TextureFormat::Bc7 => "bc7",
+            TextureFormat::Astc4x4 => "astc4x4",
+        }
+    }
 }
 
-//--------------------------------------------------------------------------------------------------
-// Implementations
-//--------------------------------------------------------------------------------------------------
+impl std::str::FromStr for TextureFormat {
+    type Err = Error;
 
-impl What {
-    pub fn new(max_size: usize, location: Option<Location>) -> What {
-        What {
-            guid_generator: GuidGenerator::new(),
-            paths: HashMap::new(),
-            cache: LfuCache::new(max_size),
-            location,
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "rgba8" => Ok(TextureFormat::Rgba8),
+            "rgba32f" => Ok(TextureFormat::Rgba32F),
+            "bc5" => Ok(TextureFormat::Bc5),
+            "bc7" => Ok(TextureFormat::Bc7),
+            "astc4x4" => Ok(TextureFormat::Astc4x4),
+            _ => Err(Error::Unknown(format!(
+                "Unknown texture format {}.",
+                format
+            ))),
         }
     }
+}
 
-    pub fn shrink_to_fit(&mut self, max_size: usize) {
-        self.cache.shrink_to_fit(max_size);
+impl TextureData {
+    /// The raw texel layout, if the texture doesn't hold an image file.
+    pub fn texture_format(&self) -> Option<TextureFormat> {
+        self.format
+            .as_deref()
+            .and_then(|format| format.parse().ok())
     }
+}
 
-    pub fn load_file<S: AsRef<str>>(&mut self, path: S, priority: usize) -> Result<Vec<u8>, Error> {
-        let path = path.as_ref();
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum WrapMode {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
 
-        let key = if let Some(guid) = self.paths.get(path) {
-            guid
-        } else {
-            self.paths
-                .insert(path.to_string(), self.guid_generator.generate());
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum FilterMode {
+    Nearest,
+    Linear,
+}
 
-            if let Some(guid) = self.paths.get(path) {
-                guid
-            } else {
-                return Err(Error::Unknown(
-                    "I don't know what happened. Unable to generate Guid.".to_string(),
-                ));
-            }
-        };
+/// Sampling intent of a texture, kept with the data so renderers don't have to guess.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SamplerInfo {
+    pub wrap_s: WrapMode,
+    pub wrap_t: WrapMode,
+    pub min_filter: FilterMode,
+    pub mag_filter: FilterMode,
+    /// None if the texture isn't meant to be mipmapped.
+    pub mip_filter: Option<FilterMode>,
+}
+
+/// Whether color channels are already multiplied by alpha. Decides the blend setup.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum AlphaMode {
+    #[default]
+    Straight,
+    Premultiplied,
+}
+
+/// How texel values are meant to be interpreted. Data textures like normal maps are linear.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    Linear,
+}
 
-        if let Some(data) = self.cache.get(key) {
-            return Ok(data.clone());
+/// Filter used when convert_texture_with downscales an image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ResizeFilter {
+    Nearest,
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    #[default]
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    fn filter_type(self) -> image::imageops::FilterType {
+        match self {
+            ResizeFilter::Nearest => image::imageops::FilterType::Nearest,
+            ResizeFilter::Triangle => image::imageops::FilterType::Triangle,
+            ResizeFilter::CatmullRom => image::imageops::FilterType::CatmullRom,
+            ResizeFilter::Gaussian => image::imageops::FilterType::Gaussian,
+            ResizeFilter::Lanczos3 => image::imageops::FilterType::Lanczos3,
         }
+    }
+}
 
-        let (data, other) = <What as Backend>::read_file(&self.location, path)?;
-        self.cache.insert(key, data.clone(), priority);
+/// How much effort png encoding spends on compression.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
 
-        if let Some(other) = other {
-            for (key, data) in other {
-                let guid = self.guid_generator.generate();
-                self.paths.insert(key, guid);
-                self.cache.insert(&guid, data, priority);
+/// How convert_texture_with writes images it re-encodes, e.g. after resizing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageEncoding {
+    Png(PngCompression),
+    /// Quality from 1 to 100. Drops the alpha channel.
+    Jpeg(u8),
+}
+
+impl Default for ImageEncoding {
+    fn default() -> Self {
+        ImageEncoding::Png(PngCompression::Default)
+    }
+}
+
+impl ImageEncoding {
+    fn encode(self, image: &image::DynamicImage) -> image::ImageResult<Vec<u8>> {
+        let mut data = Vec::new();
+
+        match self {
+            ImageEncoding::Png(compression) => {
+                let compression = match compression {
+                    PngCompression::Fast => image::codecs::png::CompressionType::Fast,
+                    PngCompression::Default => image::codecs::png::CompressionType::Default,
+                    PngCompression::Best => image::codecs::png::CompressionType::Best,
+                };
+
+                image.write_with_encoder(image::codecs::png::PngEncoder::new_with_quality(
+                    &mut data,
+                    compression,
+                    image::codecs::png::FilterType::Adaptive,
+                ))?;
+            }
+            ImageEncoding::Jpeg(quality) => {
+                image::DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(
+                    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut data, quality),
+                )?;
             }
         }
+
         Ok(data)
     }
 
-    pub fn load_asset<S: AsRef<str>>(&mut self, path: S, priority: usize) -> Result<Asset, Error> {
-        let path = path.as_ref();
-        let data = self.load_file(path, priority)?;
+    fn format(self) -> &'static str {
+        match self {
+            ImageEncoding::Png(_) => "png",
+            ImageEncoding::Jpeg(_) => "jpg",
+        }
+    }
+}
 
-        const HEADER_BEGIN: usize = 8;
+/// Named sub-rectangle of a texture, e.g. a sprite in an atlas.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TextureRegion {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
 
-        let mut size_buf = [0u8; HEADER_BEGIN];
-        size_buf[..HEADER_BEGIN].copy_from_slice(&data[..HEADER_BEGIN]);
-        let size = u64::from_le_bytes(size_buf);
+/// Settings for convert_texture_with. The default matches convert_texture.
+#[derive(Clone, Debug, Default)]
+pub struct TextureOptions {
+    pub regions: Vec<TextureRegion>,
+    pub color_space: ColorSpace,
+    /// Premultiplied re-encodes the image with color multiplied by alpha.
+    pub alpha_mode: AlphaMode,
+    pub sampler: Option<SamplerInfo>,
+    /// Downscale images whose larger side exceeds this, keeping the aspect ratio.
+    pub max_size: Option<u32>,
+    pub resize_filter: ResizeFilter,
+    /// Round both sides down to a power of two.
+    pub power_of_two: bool,
+    /// Store GPU blocks (Bc5, Bc7 or Astc4x4) instead of the image file. Needs the bcn feature.
+    pub compress_to: Option<TextureFormat>,
+    /// Drop EXIF, XMP, text and other metadata from png and jpeg files.
+    pub strip_metadata: bool,
+    /// Keep the ICC profile and color chunks when stripping metadata.
+    pub keep_icc: bool,
+    /// Extra encodings stored next to the main texture, e.g. Bc7 with a Rgba8 fallback.
+    /// load_asset picks one by its preferred formats.
+    pub variants: Vec<TextureFormat>,
+    /// Store halved levels down to 1x1 next to the texture. They are png, or float texels for
+    /// float images, even if the texture itself is compressed.
+    pub mipmaps: bool,
+    /// Used whenever the image is re-encoded. Jpeg re-encodes the image even if nothing else
+    /// would. Float images keep their texels.
+    pub encoding: ImageEncoding,
+}
 
-        let header_end = HEADER_BEGIN + size as usize;
-
-        match serde_json::from_slice::<BaseHeader>(&data[HEADER_BEGIN..header_end]) {
-            Ok(meta) => match meta.ctype {
-                HeaderType::Texture(texture_meta) => {
-                    let texture = data[(header_end + texture_meta.offset as usize)..].to_vec();
-                    Ok(Asset::Texture(TextureData {
-                        width: texture_meta.width,
-                        height: texture_meta.height,
-                        format: texture_meta.format,
-                        data: texture,
-                    }))
-                }
-                HeaderType::TextureArray(texarray_meta) => {
-                    let mut textures = Vec::<Vec<u8>>::new();
-                    let mut keys = Vec::<String>::new();
-                    for (i, entry) in texarray_meta.data.iter().enumerate() {
-                        let end_offset = if i + 1 >= texarray_meta.data.len() {
-                            data.len()
-                        } else {
-                            header_end + texarray_meta.data[i + 1].offset as usize
-                        };
+/// Settings for convert_gltf_with. The default matches convert_gltf.
+#[derive(Clone, Debug, Default)]
+pub struct GltfOptions {
+    /// Store buffers and images as entries next to the JSON instead of the file as it is.
+    /// External files are packed as well. Entries can be read alone with load_gltf_entry.
+    pub split: bool,
+}
 
-                        textures
-                            .push(data[(header_end + entry.offset as usize)..end_offset].to_vec());
+/// Settings for convert_shader_with. The default matches convert_shader.
+#[derive(Clone, Debug)]
+pub struct ShaderOptions {
+    /// Compact the module: drop the types, constants and functions no entry point uses, and
+    /// strip debug labels. naga has no other passes, so the code itself is not optimized.
+    pub optimize: bool,
+    /// What the target GPU supports. Shaders using anything else fail to convert.
+    pub capabilities: naga::valid::Capabilities,
+    pub validation: naga::valid::ValidationFlags,
+    /// Names and values for the preprocessor. Names are tested by #ifdef and replaced by
+    /// their value in the code.
+    pub defines: Vec<(String, String)>,
+}
 
-                        keys.push(entry.key.clone());
-                    }
-                    Ok(Asset::TextureArray(TextureArrayData {
-                        size: texarray_meta.size,
-                        format: texarray_meta.format,
-                        keys,
-                        data: textures,
-                    }))
-                }
-                HeaderType::Shader(shader_meta) => {
-                    let shader = data[(header_end + shader_meta.offset as usize)..].to_vec();
-                    let mut read = Cursor::new(shader);
-                    let mut shader = Vec::<u32>::new();
+impl Default for ShaderOptions {
+    fn default() -> Self {
+        ShaderOptions {
+            optimize: false,
+            capabilities: naga::valid::Capabilities::all(),
+            validation: naga::valid::ValidationFlags::all(),
+            defines: Vec::new(),
+        }
+    }
+}
 
-                    while let Ok(value) = read.read_u32::<byteorder::LittleEndian>() {
-                        shader.push(value);
-                    }
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TextureArrayData {
+    pub size: u32,
+    pub format: Option<String>,
+    pub keys: Vec<String>,
+    #[serde(with = "utils::base64_layers")]
+    pub data: Vec<Vec<u8>>,
+}
 
-                    Ok(Asset::Shader(ShaderData {
-                        data: shader,
-                        stages: shader_meta.stages,
-                    }))
-                }
-                HeaderType::Gltf(gltf_meta) => {
-                    let slice = &data[(header_end + gltf_meta.offset as usize)..];
-                    let base = match &self.location {
-                        Some(Location::File(path)) => Some(path.clone()),
-                        _ => None,
-                    };
+/// What a convert or write call produced on disk.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ConvertReport {
+    pub output: PathBuf,
+    pub bytes_written: u64,
+    /// Skipped by an incremental build, because sources and settings didn't change.
+    pub up_to_date: bool,
+}
 
-                    return gltf::import_slice(slice, base.as_deref(), |_, path| {
-                        let res = self.load_file(path, priority);
+/// Time spent in loads since timing was enabled with What::set_timing.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct TimingReport {
+    /// Files read through the backend. Cache hits aren't counted.
+    pub reads: u64,
+    pub bytes_read: u64,
+    pub read_time: Duration,
+    /// Assets parsed by load_asset, including decryption.
+    pub decodes: u64,
+    pub decode_time: Duration,
+}
 
-                        match res {
-                            Err(Error::Io(err)) => Err(gltf::Error::Io(err)),
-                            _ => Ok(res.unwrap()),
-                        }
-                    })
-                    .map_err(Error::GltfError)
-                    .map(|(document, buffers, images)| Asset::Gltf(document, buffers, images));
-                }
-            },
-            Err(err) => Err(Error::JsonError(err)),
+/// Volume texture. `data` holds the raw texels, slice after slice.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Texture3DData {
+    pub width: u32,
+    pub height: u32,
+    pub depth: u32,
+    pub format: Option<String>,
+    #[serde(with = "utils::base64_bytes")]
+    pub data: Vec<u8>,
+}
+
+/// The type of asset a .fur file holds, matching the Asset variants.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AssetKind {
+    Texture,
+    TextureArray,
+    Texture3D,
+    Shader,
+    ShaderProgram,
+    Gltf,
+}
+
+impl AssetKind {
+    /// Name used in the manifest.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AssetKind::Texture => "texture",
+            AssetKind::TextureArray => "texture_array",
+            AssetKind::Texture3D => "texture_3d",
+            AssetKind::Shader => "shader",
+            AssetKind::ShaderProgram => "shader_program",
+            AssetKind::Gltf => "gltf",
         }
     }
+}
 
-    fn write_asset<P: AsRef<Path>, S: AsRef<str>>(
-        &self,
-        output: P,
-        header: S,
-        content: &[u8],
-        overwrite: bool,
-    ) -> Result<(), String> {
-        let output = output.as_ref();
-
-        let output = if let Some(Location::File(path)) = &self.location {
-            path.join(output)
-        } else {
-            output.to_path_buf()
-        };
+/// Fixed size trailer at the end of files written with What::set_footer. Tells where the
+/// header starts and how long the payload is, without parsing the header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Footer {
+    pub header_offset: u64,
+    pub payload_length: u64,
+}
 
-        if output.exists() {
-            if overwrite {
-                log::warn!("Overwrite flag set. Overwriting file {}", output.display());
-            } else {
-                return Err(format!("File {} already exists.", output.display()));
-            }
-        }
+impl Footer {
+    /// Magic, header offset and payload length.
+    pub const SIZE: usize = 24;
+    const MAGIC: &'static [u8; 8] = b"FURFOOT1";
 
-        if output.parent().is_none() {
-            return Err(format!("{} has no parent folder.", output.display()));
-        }
+    /// Read the footer from the last Footer::SIZE bytes of `data`. None if there is none.
+    pub fn parse(data: &[u8]) -> Option<Footer> {
+        let footer = data.get(data.len().checked_sub(Self::SIZE)?..)?;
 
-        if let Err(e) = std::fs::create_dir_all(output.parent().unwrap()) {
-            return Err(format!(
-                "Could not create parent folders of {}. Message: {}",
-                output.display(),
-                e
-            ));
+        if &footer[..8] != Self::MAGIC {
+            return None;
         }
 
-        let size: u64 = header.as_ref().as_bytes().len() as u64;
+        Some(Footer {
+            header_offset: u64::from_le_bytes(footer[8..16].try_into().ok()?),
+            payload_length: u64::from_le_bytes(footer[16..24].try_into().ok()?),
+        })
+    }
 
-        return std::fs::write(
-            output,
-            [&size.to_le_bytes(), header.as_ref().as_bytes(), content].concat(),
-        )
-        .map_err(|error| error.to_string());
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[..8].copy_from_slice(Self::MAGIC);
+        bytes[8..16].copy_from_slice(&self.header_offset.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.payload_length.to_le_bytes());
+        bytes
     }
+}
 
-    fn write_texture<P: AsRef<Path>>(
-        &self,
-        output: P,
-        texture: &TextureData,
-        overwrite: bool,
-    ) -> Result<(), String> {
-        let header = BaseHeader {
-            major: VERSION_MAJOR,
-            minor: VERSION_MINOR,
-            ctype: HeaderType::Texture(HeaderTexture {
-                width: texture.width,
-                height: texture.height,
-                format: texture.format.as_ref().map(String::from),
-                offset: 0,
-            }),
-        };
+/// What a .fur file declares about itself, read without loading or decrypting the payload.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AssetHeader {
+    /// Format version (major, minor) the file was written with, compare with format_version.
+    pub version: (u16, u16),
+    pub kind: AssetKind,
+    pub encrypted: bool,
+}
 
-        match serde_json::to_string(&header) {
-            Ok(header) => self.write_asset(output, header, texture.data.as_slice(), overwrite),
-            Err(err) => Err(format!(
-                "Could not serialize header of {}. Error: {}",
-                output.as_ref().display(),
-                err
-            )),
-        }
-    }
+/// An entry of a texture array or shader program, as returned by What::list_entries.
+/// Offsets count from the start of the payload.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EntryInfo {
+    pub key: String,
+    pub offset: u64,
+    pub length: u64,
+    /// Whether the entry is non-empty and lies within the payload.
+    pub valid: bool,
+}
 
-    fn write_texture_array<P: AsRef<Path>>(
-        &self,
-        output: P,
-        textures: &TextureArrayData,
-        overwrite: bool,
-    ) -> Result<(), String> {
-        if textures.keys.len() != textures.data.len() {
-            return Err(format!(
-                "Texture array keys and data must have the same length. Keys: {} Textures: {}",
-                textures.keys.len(),
-                textures.data.len()
-            ));
-        }
+#[derive(Clone)]
+pub enum Asset {
+    Texture(TextureData),
+    TextureArray(TextureArrayData),
+    Texture3D(Texture3DData),
+    Shader(ShaderData),
+    ShaderProgram(ShaderProgramData),
+    Gltf(
+        gltf::Document,
+        Vec<gltf::buffer::Data>,
+        Vec<gltf::image::Data>,
+        Vec<String>,
+    ),
+}
 
-        let mut entries = Vec::<HeaderEntry>::new();
-        let mut offset = 0;
+/// Contents of Asset::Gltf: document, buffers, images and validation warnings.
+pub type GltfData = (
+    gltf::Document,
+    Vec<gltf::buffer::Data>,
+    Vec<gltf::image::Data>,
+    Vec<String>,
+);
 
-        for i in 0..textures.keys.len() {
-            entries.push(HeaderEntry {
-                key: textures.keys[i].to_string(),
-                offset,
-            });
+/// Transform run on loaded assets, see What::add_post_processor.
+pub type PostProcessor = Box<dyn Fn(&mut Asset) + Send>;
+
+/// Called with path, bytes read so far and total bytes while reading, see
+/// What::set_read_progress.
+pub type ReadProgress = Box<dyn FnMut(&str, u64, Option<u64>) + Send>;
 
-            offset += textures.data[i].len() as u64;
+impl Asset {
+    pub fn kind(&self) -> AssetKind {
+        match self {
+            Asset::Texture(_) => AssetKind::Texture,
+            Asset::TextureArray(_) => AssetKind::TextureArray,
+            Asset::Texture3D(_) => AssetKind::Texture3D,
+            Asset::Shader(_) => AssetKind::Shader,
+            Asset::ShaderProgram(_) => AssetKind::ShaderProgram,
+            Asset::Gltf(..) => AssetKind::Gltf,
         }
+    }
+}
 
-        let header = BaseHeader {
-            major: VERSION_MAJOR,
-            minor: VERSION_MINOR,
-            ctype: HeaderType::TextureArray(HeaderTextureArray {
-                size: textures.size,
-                format: textures.format.as_ref().map(String::from),
-                data: entries,
-            }),
-        };
+/// Data that can be written as an asset, e.g. to plan with What::estimated_size.
+#[derive(Clone, Copy)]
+pub enum AssetInput<'a> {
+    Texture(&'a TextureData),
+    TextureArray(&'a TextureArrayData),
+    Texture3D(&'a Texture3DData),
+    Shader(&'a ShaderData),
+    ShaderProgram(&'a ShaderProgramData),
+    /// Contents of a .gltf or .glb file.
+    Gltf(&'a [u8]),
+}
 
-        //Gather all the data.
-        let content = textures
-            .data
-            .iter()
-            .flat_map(|a| a.iter())
-            .cloned()
-            .collect::<Vec<u8>>();
+//Payloads can be megabytes, so Debug prints their size instead of their contents.
+impl std::fmt::Debug for ShaderData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShaderData")
+            .field("data", &format_args!("<{} words>", self.data.len()))
+            .field("stages", &self.stages)
+            .field("optimized", &self.optimized)
+            .field("reflection", &self.reflection)
+            .field("defines", &self.defines)
+            .field("workgroup_size", &self.workgroup_size)
+            .finish()
+    }
+}
 
-        match serde_json::to_string(&header) {
-            Ok(header) => self.write_asset(output, header, content.as_slice(), overwrite),
-            Err(err) => Err(format!(
-                "Could not serialize header of {}. Error: {}",
-                output.as_ref().display(),
-                err
-            )),
-        }
+impl std::fmt::Debug for TextureData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureData")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("format", &self.format)
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .field("regions", &self.regions)
+            .field("color_space", &self.color_space)
+            .field("alpha_mode", &self.alpha_mode)
+            .field("sampler", &self.sampler)
+            .field("variants", &self.variants)
+            .field("mips", &self.mips)
+            .finish()
     }
+}
 
-    pub fn write_shader<P: AsRef<Path>>(
-        &self,
-        output: P,
-        shader: &ShaderData,
-        overwrite: bool,
-    ) -> Result<(), String> {
-        let header = BaseHeader {
-            major: VERSION_MAJOR,
-            minor: VERSION_MINOR,
-            ctype: HeaderType::Shader(HeaderShader {
-                offset: 0,
-                stages: shader.stages,
-            }),
-        };
+impl std::fmt::Debug for TextureVariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureVariant")
+            .field("format", &self.format)
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for TextureMip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureMip")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("format", &self.format)
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .finish()
+    }
+}
+
+impl std::fmt::Debug for TextureArrayData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes = self.data.iter().map(Vec::len).sum::<usize>();
+
+        f.debug_struct("TextureArrayData")
+            .field("size", &self.size)
+            .field("format", &self.format)
+            .field("keys", &self.keys)
+            .field(
+                "data",
+                &format_args!("<{} layers, {} bytes>", self.data.len(), bytes),
+            )
+            .finish()
+    }
+}
 
-        let mut raw_shader = Vec::new();
+impl std::fmt::Debug for Texture3DData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Texture3DData")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("depth", &self.depth)
+            .field("format", &self.format)
+            .field("data", &format_args!("<{} bytes>", self.data.len()))
+            .finish()
+    }
+}
 
-        for value in &shader.data {
-            raw_shader.extend_from_slice(&value.to_le_bytes());
+impl std::fmt::Debug for Asset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Asset::Texture(texture) => f.debug_tuple("Texture").field(texture).finish(),
+            Asset::TextureArray(array) => f.debug_tuple("TextureArray").field(array).finish(),
+            Asset::Texture3D(volume) => f.debug_tuple("Texture3D").field(volume).finish(),
+            Asset::Shader(shader) => f.debug_tuple("Shader").field(shader).finish(),
+            Asset::ShaderProgram(program) => f.debug_tuple("ShaderProgram").field(program).finish(),
+            Asset::Gltf(document, buffers, images, warnings) => f
+                .debug_struct("Gltf")
+                .field("nodes", &document.nodes().len())
+                .field("meshes", &document.meshes().len())
+                .field("buffers", &buffers.len())
+                .field("images", &images.len())
+                .field("warnings", warnings)
+                .finish(),
         }
+    }
+}
 
-        match serde_json::to_string(&header) {
-            Ok(header) => self.write_asset(output, header, &raw_shader, overwrite),
-            Err(err) => Err(format!(
-                "Could not serialize header of {}. Error: {}",
-                output.as_ref().display(),
-                err
-            )),
+/// How long a loaded file stays cached compared to others. Lower priorities are evicted
+/// first, files of equal priority by the eviction policy.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Never evicted until unpinned with What::unpin. Loading fails with CacheFull instead
+    /// if all pinned files together wouldn't fit into the cache.
+    Pinned,
+}
+
+impl Priority {
+    //Order in the cache, higher is kept longer.
+    fn rank(self) -> usize {
+        match self {
+            Priority::Low => 0,
+            Priority::Normal => 1,
+            Priority::High => 2,
+            Priority::Pinned => usize::MAX,
         }
     }
+}
 
-    pub fn convert_texture<P: AsRef<Path>>(
-        &self,
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GltfValidation {
+    /// Skip the semantic checks.
+    Off,
+    /// Report issues as warnings next to the loaded document.
+    #[default]
+    Warn,
+    /// Fail the load on any issue.
+    Strict,
+}
+
+pub enum Location {
+    File(PathBuf),
+    Http(String),
+    /// Zip archive. Paths are resolved as entries inside the archive.
+    Archive(PathBuf),
+    /// Files embedded in the binary, e.g. with include_bytes!, as name and content. Paths are
+    /// resolved as names. Read only.
+    Static(&'static [(&'static str, &'static [u8])]),
+}
+
+pub struct What {
+    guid_generator: GuidGenerator,
+    paths: HashMap<String, Guid>,
+    cache: LfuCache<Guid, Bytes>,
+    location: Option<Location>,
+    backend: Box<dyn Backend>,
+    gltf_validation: GltfValidation,
+    mmap_threshold: Option<u64>,
+    encryption_key: Option<[u8; 32]>,
+    manifest: Mutex<Manifest>,
+    dry_run: bool,
+    incremental: bool,
+    //Paths the backend didn't find and when, so they aren't requested again right away.
+    not_found: HashMap<String, Instant>,
+    not_found_ttl: Option<Duration>,
+    //Registered paths whose last load failed, swept by gc.
+    failed: HashSet<String>,
+    content_dedup: bool,
+    //Hash of loaded files and the Guid they were cached under, see set_content_dedup.
+    content_hashes: HashMap<u64, Guid>,
+    extension: String,
+    footer: bool,
+    verify: bool,
+    strict_version: bool,
+    timing: Option<TimingReport>,
+    post_processors: HashMap<AssetKind, Vec<PostProcessor>>,
+    read_progress: Option<ReadProgress>,
+    //Pool for parallel conversions. None uses rayon's global pool with all cores.
+    #[cfg(feature = "parallel")]
+    thread_pool: Option<rayon::ThreadPool>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Implementations
+//--------------------------------------------------------------------------------------------------
+
+impl TextureArrayData {
+    /// The layer stored under `key`, e.g. the "+z" face of a cubemap.
+    pub fn layer(&self, key: &str) -> Option<&[u8]> {
+        self.keys
+            .iter()
+            .position(|other| other == key)
+            .and_then(|index| self.data.get(index))
+            .map(Vec::as_slice)
+    }
+
+    /// Keys and layers in the order they were stored.
+    pub fn layers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.keys
+            .iter()
+            .map(String::as_str)
+            .zip(self.data.iter().map(Vec::as_slice))
+    }
+
+    /// Re-encode the decoded images of a glTF as PNG layers keyed by image name or index.
+    pub fn from_gltf_images(
+        document: &gltf::Document,
+        images: &[gltf::image::Data],
+    ) -> Result<TextureArrayData, Error> {
+        let mut size = 0;
+        let mut keys = Vec::<String>::with_capacity(images.len());
+        let mut textures = Vec::<Vec<u8>>::with_capacity(images.len());
+
+        for (image, data) in document.images().zip(images) {
+            let key = image
+                .name()
+                .map(String::from)
+                .unwrap_or_else(|| image.index().to_string());
+
+            if data.width != data.height {
+                return Err(Error::Conversion(format!(
+                    "Texture array layers need to be quadratic. Image: {}",
+                    key
+                )));
+            }
+
+            if size == 0 {
+                size = data.width;
+            } else if data.width != size {
+                return Err(Error::Conversion(format!(
+                    "All textures must have the same size. Image: {}",
+                    key
+                )));
+            }
+
+            let mut texture = Vec::new();
+
+            Self::decode_gltf_image(data)
+                .ok_or_else(|| {
+                    Error::Conversion(format!("Unsupported pixel layout of image {}.", key))
+                })?
+                .write_to(
+                    &mut Cursor::new(&mut texture),
+                    image::ImageOutputFormat::Png,
+                )
+                .map_err(|err| {
+                    Error::Conversion(format!("Failed to encode image {}. Error: {}", key, err))
+                })?;
+
+            keys.push(key);
+            textures.push(texture);
+        }
+
+        Ok(TextureArrayData {
+            size,
+            format: Some("png".to_string()),
+            keys,
+            data: textures,
+        })
+    }
+
+    fn decode_gltf_image(data: &gltf::image::Data) -> Option<image::DynamicImage> {
+        use gltf::image::Format;
+        use image::{DynamicImage, ImageBuffer};
+
+        let (width, height) = (data.width, data.height);
+        let pixels = data.pixels.clone();
+        let wide = || {
+            data.pixels
+                .chunks_exact(2)
+                .map(|a| u16::from_ne_bytes([a[0], a[1]]))
+                .collect::<Vec<u16>>()
+        };
+        let float = || {
+            data.pixels
+                .chunks_exact(4)
+                .map(|a| f32::from_ne_bytes([a[0], a[1], a[2], a[3]]))
+                .collect::<Vec<f32>>()
+        };
+
+        match data.format {
+            Format::R8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+            }
+            Format::R8G8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8)
+            }
+            Format::R8G8B8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+            }
+            Format::R8G8B8A8 => {
+                ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+            }
+            Format::R16 => {
+                ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageLuma16)
+            }
+            Format::R16G16 => {
+                ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageLumaA16)
+            }
+            Format::R16G16B16 => {
+                ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageRgb16)
+            }
+            Format::R16G16B16A16 => {
+                ImageBuffer::from_raw(width, height, wide()).map(DynamicImage::ImageRgba16)
+            }
+            //PNG has no float support, so fall back to 16 bit.
+            Format::R32G32B32FLOAT => ImageBuffer::from_raw(width, height, float())
+                .map(|image| DynamicImage::ImageRgb16(DynamicImage::ImageRgb32F(image).to_rgb16())),
+            Format::R32G32B32A32FLOAT => {
+                ImageBuffer::from_raw(width, height, float()).map(|image| {
+                    DynamicImage::ImageRgba16(DynamicImage::ImageRgba32F(image).to_rgba16())
+                })
+            }
+        }
+    }
+}
+
+impl What {
+    pub fn new(max_size: usize, location: Option<Location>) -> What {
+        Self::with_capacity(max_size, 0, location)
+    }
+
+    /// Like new, but sizes the cache and path maps for `entries` files up front.
+    pub fn with_capacity(max_size: usize, entries: usize, location: Option<Location>) -> What {
+        Self::with_eviction_policy(max_size, entries, location, EvictionPolicy::default())
+    }
+
+    /// Like with_capacity, but the cache drops files according to `policy`.
+    pub fn with_eviction_policy(
+        max_size: usize,
+        entries: usize,
+        location: Option<Location>,
+        policy: EvictionPolicy,
+    ) -> What {
+        What {
+            guid_generator: GuidGenerator::new(),
+            paths: HashMap::with_capacity(entries),
+            cache: LfuCache::with_policy(max_size, entries, policy),
+            location,
+            backend: Box::new(DefaultBackend::new()),
+            gltf_validation: GltfValidation::default(),
+            mmap_threshold: None,
+            encryption_key: None,
+            manifest: Mutex::new(Manifest::default()),
+            dry_run: false,
+            incremental: false,
+            not_found: HashMap::new(),
+            not_found_ttl: None,
+            failed: HashSet::new(),
+            content_dedup: false,
+            content_hashes: HashMap::new(),
+            extension: DEFAULT_EXTENSION.to_string(),
+            footer: false,
+            verify: false,
+            strict_version: false,
+            timing: None,
+            post_processors: HashMap::new(),
+            read_progress: None,
+            #[cfg(feature = "parallel")]
+            thread_pool: None,
+        }
+    }
+
+    /// Every asset written so far, together with the inputs it was converted from.
+    pub fn manifest(&self) -> Manifest {
+        self.manifest
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn write_manifest<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+
+        let manifest = self.manifest.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match serde_json::to_string_pretty(&*manifest) {
+            Ok(manifest) => std::fs::write(path, manifest).map_err(|err| {
+                format!(
+                    "Failed to write manifest {}. Error: {}",
+                    path.display(),
+                    err
+                )
+            }),
+            Err(err) => Err(format!("Could not serialize manifest. Error: {}", err)),
+        }
+    }
+
+    /// Replace where files are read from and written to. Drops everything cached so far.
+    pub fn set_backend(&mut self, backend: Box<dyn Backend>) {
+        self.backend = backend;
+        self.cache.clear();
+    }
+
+    /// Encrypt written payloads and decrypt loaded ones with this key. Disabled by default.
+    pub fn set_encryption_key(&mut self, key: Option<[u8; 32]>) {
+        self.encryption_key = key;
+    }
+
+    /// Memory map files of at least `threshold` bytes instead of reading them. Disabled by default.
+    pub fn set_mmap_threshold(&mut self, threshold: Option<u64>) {
+        self.mmap_threshold = threshold;
+    }
+
+    /// Report what conversions would write without touching the disk.
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Read every written asset back and fail the conversion if it doesn't match what was
+    /// meant to be written. Catches broken backends and format bugs early.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Refuse files of an older minor version with UnsupportedVersion instead of loading them.
+    /// Off by default. Old files can be brought up to date with migrate_file.
+    pub fn set_strict_version(&mut self, strict: bool) {
+        self.strict_version = strict;
+    }
+
+    /// Let paths whose files have identical bytes share the memory of their cache entries.
+    /// Every read file is hashed, so this only pays off if duplicates are common.
+    pub fn set_content_dedup(&mut self, dedup: bool) {
+        self.content_dedup = dedup;
+
+        if !dedup {
+            self.content_hashes.clear();
+        }
+    }
+
+    /// Skip conversions whose output is up to date with its sources and settings.
+    /// Outputs record a fingerprint of both in their header.
+    pub fn set_incremental(&mut self, incremental: bool) {
+        self.incremental = incremental;
+    }
+
+    /// Append a Footer to written files, so readers can find the header and payload from the
+    /// end of the file. Older versions read the footer as part of the payload.
+    pub fn set_footer(&mut self, footer: bool) {
+        self.footer = footer;
+    }
+
+    /// Answer loads of files the backend didn't find with NotFound for `ttl`, without asking
+    /// the backend again. Disabled by default.
+    pub fn set_not_found_ttl(&mut self, ttl: Option<Duration>) {
+        self.not_found_ttl = ttl;
+        self.not_found.clear();
+    }
+
+    /// Use `extension` instead of .fur when inferring output names and finding assets.
+    pub fn set_extension<S: AsRef<str>>(&mut self, extension: S) {
+        self.extension = extension.as_ref().trim_start_matches('.').to_string();
+    }
+
+    pub fn extension(&self) -> &str {
+        &self.extension
+    }
+
+    /// The output file name for `input`: its name up to the first dot, with the asset extension.
+    pub fn output_name<P: AsRef<Path>>(&self, input: P) -> Option<PathBuf> {
+        let name = input.as_ref().file_name()?.to_str()?;
+        let stem = name.split('.').next().unwrap_or_default();
+
+        Some(PathBuf::from(format!("{}.{}", stem, self.extension)))
+    }
+
+    /// Sum up how long loads spend reading and decoding, see timing_report. Each load is
+    /// also logged at debug level, which works without this. Disabled by default.
+    pub fn set_timing(&mut self, enabled: bool) {
+        self.timing = enabled.then(TimingReport::default);
+    }
+
+    /// Totals since set_timing(true). Empty if timing is disabled.
+    pub fn timing_report(&self) -> TimingReport {
+        self.timing.clone().unwrap_or_default()
+    }
+
+    /// Run parallel conversions on at most `threads` threads. None uses all cores.
+    /// Without the parallel feature conversions always run on one thread.
+    pub fn set_threads(&mut self, threads: Option<usize>) -> Result<(), Error> {
+        if threads == Some(0) {
+            return Err(Error::Conversion(
+                "Conversions need at least one thread.".to_string(),
+            ));
+        }
+
+        #[cfg(feature = "parallel")]
+        {
+            self.thread_pool = match threads {
+                Some(threads) => Some(
+                    rayon::ThreadPoolBuilder::new()
+                        .num_threads(threads)
+                        .build()
+                        .map_err(|err| {
+                            Error::Conversion(format!(
+                                "Failed to create a pool of {} threads. Error: {}",
+                                threads, err
+                            ))
+                        })?,
+                ),
+                None => None,
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Number of threads parallel conversions run on.
+    pub fn threads(&self) -> usize {
+        #[cfg(feature = "parallel")]
+        return match &self.thread_pool {
+            Some(pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        };
+
+        #[cfg(not(feature = "parallel"))]
+        1
+    }
+
+    /// Choose which files the cache drops first. Drops everything cached so far.
+    pub fn set_eviction_policy(&mut self, policy: EvictionPolicy) {
+        self.cache.set_policy(policy);
+    }
+
+    pub fn eviction_policy(&self) -> EvictionPolicy {
+        self.cache.policy()
+    }
+
+    pub fn set_gltf_validation(&mut self, validation: GltfValidation) {
+        self.gltf_validation = validation;
+    }
+
+    /// Run `processor` on every asset of `kind` that load_asset decodes, e.g. to flip textures.
+    /// Processors run in the order they were added.
+    pub fn add_post_processor(
+        &mut self,
+        kind: AssetKind,
+        processor: impl Fn(&mut Asset) + Send + 'static,
+    ) {
+        self.post_processors
+            .entry(kind)
+            .or_default()
+            .push(Box::new(processor));
+    }
+
+    pub fn clear_post_processors(&mut self) {
+        self.post_processors.clear();
+    }
+
+    /// Call `progress` with the path, the bytes read so far and the total, if known, while the
+    /// backend reads a file. Good for download bars. Sleeping in it throttles the read.
+    pub fn set_read_progress(
+        &mut self,
+        progress: impl FnMut(&str, u64, Option<u64>) + Send + 'static,
+    ) {
+        self.read_progress = Some(Box::new(progress));
+    }
+
+    pub fn clear_read_progress(&mut self) {
+        self.read_progress = None;
+    }
+
+    pub fn shrink_to_fit(&mut self, max_size: usize) {
+        self.cache.shrink_to_fit(max_size);
+        self.drop_stale_hashes();
+    }
+
+    /// Evict every cached file loaded with a priority below `threshold`, e.g. all Low files
+    /// between game states. Pinned files stay. Returns how many files were evicted.
+    pub fn evict_priority_below(&mut self, threshold: Priority) -> usize {
+        let evicted = self.cache.evict_priority_below(threshold.rank());
+        self.drop_stale_hashes();
+        evicted
+    }
+
+    /// Make room for `additional` more files before a batch load.
+    pub fn reserve(&mut self, additional: usize) {
+        self.paths.reserve(additional);
+        self.cache.reserve(additional);
+    }
+
+    /// Let a file loaded with Priority::Pinned be evicted again.
+    pub fn unpin<S: AsRef<str>>(&mut self, path: S) {
+        if let Some(guid) = self.paths.get(&self.normalize(path.as_ref())) {
+            self.cache.unpin(guid);
+        }
+    }
+
+    /// Remove the registration of `path` and its cached file. A later load registers it again
+    /// under a new Guid.
+    pub fn forget_path<S: AsRef<str>>(&mut self, path: S) {
+        let path = self.normalize(path.as_ref());
+
+        if let Some(guid) = self.paths.remove(&path) {
+            self.cache.remove(&guid);
+            self.guid_generator.release(guid);
+            self.drop_stale_hashes();
+        }
+
+        self.not_found.remove(&path);
+        self.failed.remove(&path);
+    }
+
+    /// Forget every path whose last load failed and that has nothing cached. Keeps long running
+    /// loaders from piling up registrations. Returns how many paths were removed.
+    pub fn gc(&mut self) -> usize {
+        let stale = self
+            .failed
+            .iter()
+            .filter(|path| {
+                !self
+                    .paths
+                    .get(*path)
+                    .is_some_and(|guid| self.cache.contains_key(guid))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for path in &stale {
+            self.forget_path(path);
+        }
+
+        stale.len()
+    }
+
+    /// Drop every cached file. Paths keep their Guid, so they are re-read under the same id.
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+        self.not_found.clear();
+        self.content_hashes.clear();
+    }
+
+    /// Paths whose files are currently held in the cache.
+    pub fn cached_paths(&self) -> Vec<String> {
+        let resident = self.cache.keys().collect::<HashSet<&Guid>>();
+
+        self.paths
+            .iter()
+            .filter(|(_, guid)| resident.contains(guid))
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    /// Write every cached file to `path`, so a later session can start warm with load_cache.
+    pub fn save_cache<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let files = self
+            .paths
+            .iter()
+            .map(|(file, guid)| (guid, file))
+            .collect::<HashMap<&Guid, &String>>();
+
+        let mut entries = Vec::new();
+        let mut content = Vec::new();
+
+        for (guid, bytes, frequency, priority) in self.cache.entries() {
+            if let Some(file) = files.get(guid) {
+                entries.push(CacheSnapshotEntry {
+                    guid: *guid,
+                    path: file.to_string(),
+                    frequency,
+                    priority,
+                    offset: content.len() as u64,
+                    length: bytes.len() as u64,
+                    source: self.source_stamp(file),
+                });
+                content.extend_from_slice(bytes);
+            }
+        }
+
+        let header = serde_json::to_string(&CacheSnapshot {
+            major: VERSION_MAJOR,
+            minor: VERSION_MINOR,
+            entries,
+        })
+        .map_err(Error::JsonError)?;
+
+        let size: u64 = header.len() as u64;
+        std::fs::write(
+            path,
+            [&size.to_le_bytes(), header.as_bytes(), &content].concat(),
+        )
+        .map_err(Error::Io)
+    }
+
+    /// Refill the cache from a file written by save_cache. Files that are cached already stay
+    /// as they are, local files that changed since are skipped. Returns how many files were
+    /// restored.
+    pub fn load_cache<P: AsRef<Path>>(&mut self, path: P) -> Result<usize, Error> {
+        const HEADER_BEGIN: usize = 8;
+
+        let path = path.as_ref();
+        let data = std::fs::read(path).map_err(|err| Error::Read(path.to_path_buf(), err))?;
+
+        let truncated = || Error::Io(std::io::ErrorKind::UnexpectedEof.into());
+
+        let mut size_buf = [0u8; HEADER_BEGIN];
+        size_buf.copy_from_slice(data.get(..HEADER_BEGIN).ok_or_else(truncated)?);
+        let header_end = Self::header_end(
+            &path.to_string_lossy(),
+            u64::from_le_bytes(size_buf),
+            data.len(),
+        )?;
+
+        let snapshot = serde_json::from_slice::<CacheSnapshot>(&data[HEADER_BEGIN..header_end])
+            .map_err(Error::JsonError)?;
+
+        if snapshot.major != VERSION_MAJOR {
+            return Err(Error::UnsupportedVersion(snapshot.major, snapshot.minor));
+        }
+
+        let payload = &data[header_end..];
+        let mut restored = 0;
+
+        for entry in snapshot.entries {
+            let bytes = payload
+                .get(entry.offset as usize..)
+                .and_then(|rest| rest.get(..entry.length as usize))
+                .ok_or_else(truncated)?;
+
+            let file = self.normalize(&entry.path);
+
+            if self.source_stamp(&file) != entry.source {
+                log::debug!("{} changed since the cache was saved. Skipping it.", file);
+                continue;
+            }
+
+            //Paths known to this session keep their Guid. Otherwise reuse the saved one if free.
+            let guid = match self.paths.get(&file) {
+                Some(guid) => *guid,
+                None => {
+                    let guid = if self.guid_generator.reserve(entry.guid) {
+                        entry.guid
+                    } else {
+                        self.guid_generator.generate()
+                    };
+                    self.paths.insert(file, guid);
+                    guid
+                }
+            };
+
+            if !self.cache.contains_key(&guid)
+                && self.cache.restore(
+                    &guid,
+                    Bytes::from(bytes.to_vec()),
+                    entry.frequency,
+                    entry.priority,
+                )
+            {
+                restored += 1;
+            }
+        }
+
+        Ok(restored)
+    }
+
+    pub fn guid<S: AsRef<str>>(&self, path: S) -> Option<Guid> {
+        self.paths.get(&self.normalize(path.as_ref())).copied()
+    }
+
+    pub fn load_file<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+    ) -> Result<Vec<u8>, Error> {
+        self.load_bytes(path, priority).map(|bytes| bytes.to_vec())
+    }
+
+    /// Like load_file, but shares the cached bytes instead of copying them.
+    pub fn load_bytes<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+    ) -> Result<Bytes, Error> {
+        let path = self.normalize(path.as_ref());
+        let path = path.as_str();
+
+        if let (Some(ttl), Some(since)) = (self.not_found_ttl, self.not_found.get(path)) {
+            if since.elapsed() < ttl {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("File {} not found. (Cached)", path),
+                )));
+            }
+
+            self.not_found.remove(path);
+        }
+
+        let (key, registered) = match self.paths.get(path) {
+            Some(guid) => (*guid, false),
+            None => {
+                let guid = self.guid_generator.generate();
+                self.paths.insert(path.to_string(), guid);
+                (guid, true)
+            }
+        };
+
+        if let Some(data) = self.cache.get(&key) {
+            let data = data.clone();
+
+            if priority == Priority::Pinned {
+                self.cache.pin(&key)?;
+            }
+
+            return Ok(data);
+        }
+
+        if self.cache.recently_evicted(&key) {
+            log::warn!(
+                "{} was evicted from the cache shortly before. The cache may be too small.",
+                path
+            );
+        }
+
+        let started = self.start_timing();
+
+        let cache = &mut self.cache;
+        let read = match &mut self.read_progress {
+            Some(progress) => self.backend.read_file_with_progress(
+                &self.location,
+                path,
+                self.mmap_threshold,
+                &mut |size| cache.make_room(size as usize),
+                &mut |read, total| progress(path, read, total),
+            ),
+            None => self.backend.read_file_with_hint(
+                &self.location,
+                path,
+                self.mmap_threshold,
+                &mut |size| cache.make_room(size as usize),
+            ),
+        };
+
+        let (data, other) = match read {
+            Ok(read) => read,
+            Err(err) => {
+                //Don't keep a Guid around for a file that never loaded.
+                if registered {
+                    self.paths.remove(path);
+                    self.guid_generator.release(key);
+                } else {
+                    self.failed.insert(path.to_string());
+                }
+
+                let missing = match &err {
+                    Error::Io(err) | Error::Read(_, err) => {
+                        err.kind() == std::io::ErrorKind::NotFound
+                    }
+                    _ => false,
+                };
+
+                if missing && self.not_found_ttl.is_some() {
+                    self.not_found.insert(path.to_string(), Instant::now());
+                }
+
+                return Err(err);
+            }
+        };
+
+        if let Some(started) = started {
+            let elapsed = started.elapsed();
+            log::debug!("Read {} ({} bytes) in {:?}", path, data.len(), elapsed);
+
+            if let Some(timing) = &mut self.timing {
+                timing.reads += 1;
+                timing.bytes_read += data.len() as u64;
+                timing.read_time += elapsed;
+            }
+        }
+
+        self.failed.remove(path);
+
+        let data = if self.content_dedup {
+            self.share_duplicate(key, data)
+        } else {
+            data
+        };
+
+        if priority == Priority::Pinned {
+            self.cache
+                .insert_pinned(&key, data.clone(), priority.rank())?;
+        } else {
+            self.cache.insert(&key, data.clone(), priority.rank());
+        }
+
+        if let Some(other) = other {
+            for (key, data) in other {
+                let guid = self.guid_generator.generate();
+                self.paths.insert(self.normalize(&key), guid);
+                self.cache.insert(&guid, data.into(), priority.rank());
+            }
+        }
+        Ok(data)
+    }
+
+    /// Load the given files into the cache, calling progress(current, total) after each one.
+    pub fn warm<S: AsRef<str>>(
+        &mut self,
+        paths: &[S],
+        priority: Priority,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(), Error> {
+        self.reserve(paths.len());
+
+        for (i, path) in paths.iter().enumerate() {
+            self.load_bytes(path, priority)?;
+            progress(i + 1, paths.len());
+        }
+
+        Ok(())
+    }
+
+    /// Open a texture array for reading individual layers without loading the whole file.
+    pub fn open_texture_array<S: AsRef<str>>(
+        &self,
+        path: S,
+    ) -> Result<TextureArrayReader<'_>, Error> {
+        TextureArrayReader::open(self, path.as_ref())
+    }
+
+    /// Read only the header of a .fur file. Doesn't touch the cache.
+    pub fn load_header<S: AsRef<str>>(&mut self, path: S) -> Result<AssetHeader, Error> {
+        let path = self.normalize(path.as_ref());
+        let meta = self.read_header(&self.location, &path)?;
+
+        Ok(AssetHeader {
+            version: (meta.major, meta.minor),
+            kind: meta.ctype.kind(),
+            encrypted: meta.encryption.is_some(),
+        })
+    }
+
+    /// Width, height and format of the texture at `path`, read from the header alone. Cheaper
+    /// than load_asset when only the size is needed, e.g. to allocate a GPU texture early.
+    /// Texture arrays report the size of their layers.
+    pub fn texture_info<S: AsRef<str>>(
+        &mut self,
+        path: S,
+    ) -> Result<(u32, u32, Option<String>), Error> {
+        let path = self.normalize(path.as_ref());
+
+        match self.read_header(&self.location, &path)?.ctype {
+            HeaderType::Texture(texture) => Ok((texture.width, texture.height, texture.format)),
+            HeaderType::TextureArray(textures) => {
+                Ok((textures.size, textures.size, textures.format))
+            }
+            other => Err(Error::WrongAssetType {
+                expected: AssetKind::Texture,
+                found: other.kind(),
+            }),
+        }
+    }
+
+    /// The type of asset stored at `path`, read from the header alone.
+    pub fn asset_kind<S: AsRef<str>>(&mut self, path: S) -> Result<AssetKind, Error> {
+        self.load_header(path).map(|header| header.kind)
+    }
+
+    /// Every layer of a texture array, stage of a shader program or resource of a split glTF
+    /// at `path`, checked against the payload without decoding anything. Keys of shader
+    /// programs are their stages.
+    pub fn list_entries<S: AsRef<str>>(&mut self, path: S) -> Result<Vec<EntryInfo>, Error> {
+        let path = path.as_ref();
+        let data = self.load_bytes(path, Priority::Normal)?;
+        let (meta, payload) = self.split_asset(path, &data)?;
+
+        //Entries without a length end where the next one begins, like decode_asset reads them.
+        let entries: Vec<(String, u64, Option<u64>)> = match meta.ctype {
+            HeaderType::TextureArray(array) => array
+                .data
+                .into_iter()
+                .map(|entry| (entry.key, entry.offset, entry.length))
+                .collect(),
+            HeaderType::Gltf(gltf) => gltf
+                .entries
+                .into_iter()
+                .map(|entry| (entry.key, entry.offset, entry.length))
+                .collect(),
+            HeaderType::ShaderProgram(program) => program
+                .shaders
+                .iter()
+                .map(|shader| {
+                    let mut key = String::new();
+                    let _ = bitflags::parser::to_writer(&shader.stages, &mut key);
+                    (key, shader.offset, None)
+                })
+                .collect(),
+            other => {
+                return Err(Error::WrongAssetType {
+                    expected: AssetKind::TextureArray,
+                    found: other.kind(),
+                })
+            }
+        };
+
+        let size = payload.len() as u64;
+
+        Ok(entries
+            .iter()
+            .enumerate()
+            .map(|(i, (key, offset, length))| {
+                let end = match length {
+                    Some(length) => offset.saturating_add(*length),
+                    None => entries.get(i + 1).map_or(size, |(_, next, _)| *next),
+                };
+                let length = end.saturating_sub(*offset);
+
+                EntryInfo {
+                    key: key.clone(),
+                    offset: *offset,
+                    length,
+                    valid: length > 0 && end <= size,
+                }
+            })
+            .collect())
+    }
+
+    /// Rewrite the .fur file at `path` in the current format version, keeping its payload, so
+    /// it also loads with set_strict_version. Returns false if the file is current already.
+    /// Files of another major version, or written by a newer version, fail with
+    /// UnsupportedVersion.
+    pub fn migrate_file<P: AsRef<Path>>(&self, path: P) -> Result<bool, Error> {
+        const HEADER_BEGIN: usize = 8;
+
+        let path = self.resolve(path);
+        let name = path.to_string_lossy();
+        let (data, _) = self.backend.read_file(&None, &name, None)?;
+
+        let truncated = || Error::Io(std::io::ErrorKind::UnexpectedEof.into());
+
+        let mut size_buf = [0u8; HEADER_BEGIN];
+        size_buf.copy_from_slice(data.get(..HEADER_BEGIN).ok_or_else(truncated)?);
+        let header_end = Self::header_end(&name, u64::from_le_bytes(size_buf), data.len())?;
+
+        let header = &data[HEADER_BEGIN..header_end];
+
+        if header.first() != Some(&b'{') {
+            return Err(Error::NotAFurFile(name.to_string()));
+        }
+
+        //Not parse_header, which refuses old versions in strict mode.
+        let mut meta = serde_json::from_slice::<BaseHeader>(header).map_err(Error::JsonError)?;
+
+        if (meta.major, meta.minor) == (VERSION_MAJOR, VERSION_MINOR) {
+            return Ok(false);
+        }
+
+        if meta.major != VERSION_MAJOR || meta.minor > VERSION_MINOR {
+            return Err(Error::UnsupportedVersion(meta.major, meta.minor));
+        }
+
+        //Minor versions only add fields, so the header is current once it says so. The
+        //footer stays valid, since the header still starts at the same offset.
+        meta.major = VERSION_MAJOR;
+        meta.minor = VERSION_MINOR;
+
+        let json = serde_json::to_string(&meta).map_err(Error::JsonError)?;
+        let size = json.len() as u64;
+        let bytes = [&size.to_le_bytes(), json.as_bytes(), &data[header_end..]].concat();
+
+        if self.dry_run {
+            log::info!("Dry run. Would migrate {}", path.display());
+        } else {
+            self.backend.write_file(&path, &bytes, true)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Migrate every .fur file below `dir`, see migrate_file. Returns each file with whether
+    /// it was rewritten, in path order.
+    pub fn migrate_dir<P: AsRef<Path>>(
+        &self,
+        dir: P,
+    ) -> Result<Vec<(PathBuf, Result<bool, Error>)>, Error> {
+        let files = utils::find_files(&self.resolve(dir), &self.extension).map_err(Error::Io)?;
+
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                //Resolved again by migrate_file, so strip the folder of the location.
+                let path = match &self.location {
+                    Some(Location::File(base)) => file.strip_prefix(base).unwrap_or(&file),
+                    _ => &file,
+                };
+                let result = self.migrate_file(path);
+                (file, result)
+            })
+            .collect())
+    }
+
+    /// Check every .fur file below `dir` by reading its header, or loading it fully if `deep`.
+    /// Returns each file with the outcome, in path order.
+    pub fn verify_dir<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        deep: bool,
+    ) -> Result<Vec<(PathBuf, Result<(), Error>)>, Error> {
+        let files = utils::find_files(&self.resolve(dir), &self.extension).map_err(Error::Io)?;
+
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                //Load through the location again, so strip its folder.
+                let path = match &self.location {
+                    Some(Location::File(base)) => file.strip_prefix(base).unwrap_or(&file),
+                    _ => &file,
+                };
+                let result = utils::path_text(path, path.as_os_str())
+                    .map(str::to_string)
+                    .and_then(|path| {
+                        if deep {
+                            self.load_asset(&path, Priority::Low, &[]).map(|_| ())
+                        } else {
+                            self.load_header(&path).map(|_| ())
+                        }
+                    });
+
+                (file, result)
+            })
+            .collect())
+    }
+
+    //If a cached file has the same bytes as `data`, return its buffer so both entries share
+    //the memory. Otherwise remember `data` under `key` for later loads.
+    fn share_duplicate(&mut self, key: Guid, data: Bytes) -> Bytes {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        data.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        //Inserting evicts without telling, so entries may have gone since the last read.
+        self.drop_stale_hashes();
+
+        //Hashes may collide, so the bytes are compared as well.
+        let shared = self
+            .content_hashes
+            .get(&hash)
+            .copied()
+            .and_then(|shared| self.cache.get(&shared).cloned())
+            .filter(|cached| cached[..] == data[..]);
+
+        match shared {
+            Some(cached) => cached,
+            None => {
+                self.content_hashes.insert(hash, key);
+                data
+            }
+        }
+    }
+
+    //Forget hashes whose entry is no longer cached.
+    fn drop_stale_hashes(&mut self) {
+        let cache = &self.cache;
+        self.content_hashes
+            .retain(|_, guid| cache.contains_key(guid));
+    }
+
+    fn read_header(&self, base: &Option<Location>, path: &str) -> Result<BaseHeader, Error> {
+        self.read_header_sized(base, path).map(|(meta, _)| meta)
+    }
+
+    //Like read_header, but also returns where the payload begins.
+    fn read_header_sized(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+    ) -> Result<(BaseHeader, u64), Error> {
+        const HEADER_BEGIN: u64 = 8;
+
+        if self.footer {
+            if let Some(found) = self.read_header_from_footer(base, path)? {
+                return Ok(found);
+            }
+        }
+
+        //One more byte than the size, so files without a header are told apart before the
+        //garbage size is used.
+        let begin = match self
+            .backend
+            .read_range(base, path, 0, Some(HEADER_BEGIN + 1))
+        {
+            Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => Vec::new(),
+            begin => begin?,
+        };
+
+        //Shorter files can't even hold the size. Not every backend fails on short reads.
+        if begin.len() <= HEADER_BEGIN as usize || begin[HEADER_BEGIN as usize] != b'{' {
+            return Err(Error::NotAFurFile(path.to_string()));
+        }
+
+        let mut size_buf = [0u8; HEADER_BEGIN as usize];
+        size_buf.copy_from_slice(&begin[..HEADER_BEGIN as usize]);
+        let size = u64::from_le_bytes(size_buf);
+
+        //The header has to fit into the file where its length is known cheaply, and into
+        //memory in any case.
+        let length = self
+            .backend
+            .read_tail(base, path, 0)?
+            .map(|(_, length)| length);
+        let past_end = length.is_some_and(|length| size > length.saturating_sub(HEADER_BEGIN));
+
+        if past_end || usize::try_from(size).is_err() {
+            return Err(Error::InvalidHeader(path.to_string(), size));
+        }
+
+        let header = self
+            .backend
+            .read_range(base, path, HEADER_BEGIN, Some(size))?;
+        Ok((self.parse_header(path, &header)?, HEADER_BEGIN + size))
+    }
+
+    //Locate the header through the footer at the end of the file. None if the file has no
+    //footer or the backend can't read from the end.
+    fn read_header_from_footer(
+        &self,
+        base: &Option<Location>,
+        path: &str,
+    ) -> Result<Option<(BaseHeader, u64)>, Error> {
+        let Some((tail, length)) = self.backend.read_tail(base, path, Footer::SIZE as u64)? else {
+            return Ok(None);
+        };
+
+        let Some(footer) = Footer::parse(&tail) else {
+            return Ok(None);
+        };
+
+        //The payload ends at the footer and the header ends where the payload begins.
+        let payload_begin = length
+            .checked_sub(Footer::SIZE as u64)
+            .and_then(|end| end.checked_sub(footer.payload_length))
+            .filter(|&begin| begin >= footer.header_offset)
+            .ok_or_else(|| Error::InvalidHeader(path.to_string(), footer.header_offset))?;
+
+        let header = self.backend.read_range(
+            base,
+            path,
+            footer.header_offset,
+            Some(payload_begin - footer.header_offset),
+        )?;
+
+        Ok(Some((self.parse_header(path, &header)?, payload_begin)))
+    }
+
+    //The header size comes from the file, so it may be garbage. It must neither truncate on 32
+    //bit targets nor wrap around when added to the header start.
+    pub(crate) fn header_end(path: &str, size: u64, length: usize) -> Result<usize, Error> {
+        const HEADER_BEGIN: usize = 8;
+
+        usize::try_from(size)
+            .ok()
+            .filter(|&size| size <= length.saturating_sub(HEADER_BEGIN))
+            .map(|size| HEADER_BEGIN + size)
+            .ok_or_else(|| Error::InvalidHeader(path.to_string(), size))
+    }
+
+    fn parse_header(&self, path: &str, header: &[u8]) -> Result<BaseHeader, Error> {
+        //Headers are JSON objects. Anything else is most likely a file that wasn't converted.
+        if header.first() != Some(&b'{') {
+            return Err(Error::NotAFurFile(path.to_string()));
+        }
+
+        let meta = serde_json::from_slice::<BaseHeader>(header).map_err(Error::JsonError)?;
+
+        if meta.major != VERSION_MAJOR || (self.strict_version && meta.minor != VERSION_MINOR) {
+            return Err(Error::UnsupportedVersion(meta.major, meta.minor));
+        }
+
+        Ok(meta)
+    }
+
+    //Parse the header of a whole .fur file and decrypt its payload if needed.
+    fn split_asset<'a>(
+        &self,
+        path: &str,
+        data: &'a [u8],
+    ) -> Result<(BaseHeader, Cow<'a, [u8]>), Error> {
+        const HEADER_BEGIN: usize = 8;
+
+        let truncated = || Error::Io(std::io::ErrorKind::UnexpectedEof.into());
+
+        //Checked before the size, which is garbage for files without a header.
+        if data.get(HEADER_BEGIN).is_some_and(|&begin| begin != b'{') {
+            return Err(Error::NotAFurFile(path.to_string()));
+        }
+
+        let mut size_buf = [0u8; HEADER_BEGIN];
+        size_buf.copy_from_slice(data.get(..HEADER_BEGIN).ok_or_else(truncated)?);
+        let size = u64::from_le_bytes(size_buf);
+
+        let header_end = Self::header_end(path, size, data.len())?;
+        let meta = self.parse_header(path, &data[HEADER_BEGIN..header_end])?;
+
+        let payload_end = if meta.footer {
+            data.len()
+                .checked_sub(Footer::SIZE)
+                .filter(|&end| end >= header_end)
+                .ok_or_else(truncated)?
+        } else {
+            data.len()
+        };
+
+        let payload = match &meta.encryption {
+            Some(encryption) => Cow::Owned(crypto::decrypt(
+                &self.encryption_key,
+                encryption,
+                &data[header_end..payload_end],
+            )?),
+            None => Cow::Borrowed(&data[header_end..payload_end]),
+        };
+
+        Ok((meta, payload))
+    }
+
+    /// Files a glTF asset pulls in on load_asset, resolved the same way. Only parses the
+    /// glTF JSON, nothing referenced is loaded. Embedded data URIs are left out.
+    pub fn gltf_resources<S: AsRef<str>>(&mut self, path: S) -> Result<Vec<String>, Error> {
+        let path = path.as_ref();
+        let data = self.load_bytes(path, Priority::Normal)?;
+        let (meta, payload) = self.split_asset(path, &data)?;
+
+        let (offset, entries) = match meta.ctype {
+            HeaderType::Gltf(gltf_meta) => (gltf_meta.offset as usize, gltf_meta.entries),
+            _ => return Err(Error::Unknown(format!("{} is not a glTF.", path))),
+        };
+
+        let slice = payload
+            .get(offset..)
+            .ok_or_else(|| Self::out_of_range(path, "glTF data"))?;
+
+        let gltf = gltf::Gltf::from_slice_without_validation(slice).map_err(Error::GltfError)?;
+
+        let buffers = gltf.buffers().filter_map(|buffer| match buffer.source() {
+            gltf::buffer::Source::Uri(uri) => Some(uri),
+            gltf::buffer::Source::Bin => None,
+        });
+        let images = gltf.images().filter_map(|image| match image.source() {
+            gltf::image::Source::Uri { uri, .. } => Some(uri),
+            gltf::image::Source::View { .. } => None,
+        });
+
+        buffers
+            .chain(images)
+            .filter(|uri| !uri.starts_with("data:"))
+            .filter(|uri| !entries.iter().any(|entry| entry.key == *uri))
+            .map(|uri| self.resolve_uri(path, uri))
+            .collect()
+    }
+
+    /// Like load_asset, but loads images that weren't converted as textures with default
+    /// settings instead of failing with NotAFurFile.
+    pub fn load_asset_or_raw<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+    ) -> Result<Asset, Error> {
+        let path = path.as_ref();
+
+        match self.load_asset(path, priority, &[]) {
+            Err(Error::NotAFurFile(_)) => {}
+            result => return result,
+        }
+
+        let data = self.load_bytes(path, priority)?;
+
+        let (width, height) = image::io::Reader::new(Cursor::new(&*data))
+            .with_guessed_format()
+            .map_err(|err| Error::Read(PathBuf::from(path), err))?
+            .into_dimensions()
+            .map_err(|err| Error::Image(PathBuf::from(path), err))?;
+
+        Ok(Asset::Texture(TextureData {
+            width,
+            height,
+            format: Path::new(path)
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase()),
+            data: data.to_vec(),
+            regions: Vec::new(),
+            color_space: ColorSpace::default(),
+            alpha_mode: AlphaMode::default(),
+            sampler: None,
+            variants: Vec::new(),
+            mips: Vec::new(),
+        }))
+    }
+
+    /// Load and parse the asset at `path`. Textures with variants return the first of
+    /// `preferred_formats` they hold, or their main data if they hold none of them.
+    pub fn load_asset<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+        preferred_formats: &[TextureFormat],
+    ) -> Result<Asset, Error> {
+        let path = path.as_ref();
+        let data = self.load_bytes(path, priority)?;
+
+        let started = self.start_timing();
+        let mut asset = self.decode_asset(path, &data, priority, preferred_formats);
+
+        if let Ok(asset) = &mut asset {
+            for processor in self
+                .post_processors
+                .get(&asset.kind())
+                .into_iter()
+                .flatten()
+            {
+                processor(asset);
+            }
+        }
+
+        if let Some(started) = started {
+            let elapsed = started.elapsed();
+            log::debug!("Decoded {} ({} bytes) in {:?}", path, data.len(), elapsed);
+
+            if let Some(timing) = &mut self.timing {
+                timing.decodes += 1;
+                timing.decode_time += elapsed;
+            }
+        }
+
+        asset
+    }
+
+    /// Like load_asset, but fails with WrongAssetType unless `path` holds a texture.
+    pub fn load_texture<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+        preferred_formats: &[TextureFormat],
+    ) -> Result<TextureData, Error> {
+        match self.load_asset(path, priority, preferred_formats)? {
+            Asset::Texture(texture) => Ok(texture),
+            other => Err(Self::wrong_kind(AssetKind::Texture, &other)),
+        }
+    }
+
+    /// Load a single level of a texture converted with mipmaps. Level 0 is the texture itself.
+    /// Other levels are read on their own, without the larger levels, unless the file is
+    /// encrypted.
+    pub fn load_texture_mip<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        level: u32,
+    ) -> Result<TextureData, Error> {
+        let path = self.normalize(path.as_ref());
+
+        if level == 0 {
+            return self.load_texture(&path, Priority::Normal, &[]);
+        }
+
+        let (meta, payload_start) = self.read_header_sized(&self.location, &path)?;
+
+        let mut texture = match meta.ctype {
+            HeaderType::Texture(texture) => texture,
+            other => {
+                return Err(Error::WrongAssetType {
+                    expected: AssetKind::Texture,
+                    found: other.kind(),
+                })
+            }
+        };
+
+        let count = texture.mips.len();
+        let mip = texture.mips.get_mut(level as usize - 1).ok_or_else(|| {
+            Error::Unknown(format!(
+                "Texture {} has no mip level {}, only {} below the texture.",
+                path, level, count
+            ))
+        })?;
+
+        let encrypted = meta.encryption.is_some();
+        let data =
+            self.read_payload_range(&path, encrypted, payload_start, mip.offset, mip.length)?;
+
+        Ok(TextureData {
+            width: mip.width,
+            height: mip.height,
+            format: mip.format.take(),
+            data,
+            regions: Vec::new(),
+            color_space: texture.color_space,
+            alpha_mode: texture.alpha_mode,
+            sampler: texture.sampler,
+            variants: Vec::new(),
+            mips: Vec::new(),
+        })
+    }
+
+    /// Like load_asset, but fails with WrongAssetType unless `path` holds a texture array.
+    pub fn load_texture_array<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+    ) -> Result<TextureArrayData, Error> {
+        match self.load_asset(path, priority, &[])? {
+            Asset::TextureArray(textures) => Ok(textures),
+            other => Err(Self::wrong_kind(AssetKind::TextureArray, &other)),
+        }
+    }
+
+    /// Like load_asset, but fails with WrongAssetType unless `path` holds a shader.
+    pub fn load_shader<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+    ) -> Result<ShaderData, Error> {
+        match self.load_asset(path, priority, &[])? {
+            Asset::Shader(shader) => Ok(shader),
+            other => Err(Self::wrong_kind(AssetKind::Shader, &other)),
+        }
+    }
+
+    /// Read a single buffer or image of a glTF converted with GltfOptions::split, e.g.
+    /// `buffers/0`. Only the entry itself is read, unless the file is encrypted.
+    pub fn load_gltf_entry<S: AsRef<str>>(&mut self, path: S, key: &str) -> Result<Vec<u8>, Error> {
+        let path = self.normalize(path.as_ref());
+        let (meta, payload_start) = self.read_header_sized(&self.location, &path)?;
+
+        let gltf = match meta.ctype {
+            HeaderType::Gltf(gltf) => gltf,
+            other => {
+                return Err(Error::WrongAssetType {
+                    expected: AssetKind::Gltf,
+                    found: other.kind(),
+                })
+            }
+        };
+
+        let entry = gltf
+            .entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .ok_or_else(|| Error::Unknown(format!("glTF {} has no entry {}.", path, key)))?;
+        let length = match entry.length {
+            Some(length) => Some(length),
+            None => gltf.offset.checked_sub(entry.offset),
+        }
+        .ok_or_else(|| {
+            Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("glTF entry {} of {} lies outside the file.", key, path),
+            ))
+        })?;
+
+        let encrypted = meta.encryption.is_some();
+        self.read_payload_range(&path, encrypted, payload_start, entry.offset, length)
+    }
+
+    //Read `length` bytes at `offset` into the payload, which starts at `payload_start`.
+    //Encrypted payloads only decrypt as a whole, so those files are loaded completely.
+    fn read_payload_range(
+        &mut self,
+        path: &str,
+        encrypted: bool,
+        payload_start: u64,
+        offset: u64,
+        length: u64,
+    ) -> Result<Vec<u8>, Error> {
+        if !encrypted {
+            let start = payload_start
+                .checked_add(offset)
+                .ok_or_else(|| Error::Io(std::io::ErrorKind::UnexpectedEof.into()))?;
+
+            return self
+                .backend
+                .read_range(&self.location, path, start, Some(length));
+        }
+
+        let bytes = self.load_bytes(path, Priority::Normal)?;
+        let (_, payload) = self.split_asset(path, &bytes)?;
+
+        let start = offset as usize;
+        payload
+            .get(start..start.saturating_add(length as usize))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| Error::Io(std::io::ErrorKind::UnexpectedEof.into()))
+    }
+
+    /// Like load_asset, but fails with WrongAssetType unless `path` holds a glTF.
+    pub fn load_gltf<S: AsRef<str>>(
+        &mut self,
+        path: S,
+        priority: Priority,
+    ) -> Result<GltfData, Error> {
+        match self.load_asset(path, priority, &[])? {
+            Asset::Gltf(document, buffers, images, warnings) => {
+                Ok((document, buffers, images, warnings))
+            }
+            other => Err(Self::wrong_kind(AssetKind::Gltf, &other)),
+        }
+    }
+
+    //Run the semantic checks as configured. Strict validation turns warnings into an error.
+    fn check_gltf(
+        &self,
+        path: &str,
+        document: &gltf::Document,
+        buffers: &[gltf::buffer::Data],
+    ) -> Result<Vec<String>, Error> {
+        let warnings = match self.gltf_validation {
+            GltfValidation::Off => Vec::new(),
+            _ => validation::validate_gltf(document, buffers),
+        };
+
+        if self.gltf_validation == GltfValidation::Strict && !warnings.is_empty() {
+            return Err(Error::GltfValidation(warnings));
+        }
+
+        for warning in &warnings {
+            log::warn!("{}: {}", path, warning);
+        }
+
+        Ok(warnings)
+    }
+
+    //Offsets and lengths come from the header, so they may point anywhere.
+    fn out_of_range(path: &str, part: &str) -> Error {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!("{} of {} lies outside the file.", part, path),
+        ))
+    }
+
+    fn wrong_kind(expected: AssetKind, found: &Asset) -> Error {
+        Error::WrongAssetType {
+            expected,
+            found: found.kind(),
+        }
+    }
+
+    //Only takes the time if someone is interested, so loads stay free of it otherwise.
+    fn start_timing(&self) -> Option<Instant> {
+        (self.timing.is_some() || log::log_enabled!(log::Level::Debug)).then(Instant::now)
+    }
+
+    fn decode_asset(
+        &mut self,
+        path: &str,
+        data: &[u8],
+        priority: Priority,
+        preferred_formats: &[TextureFormat],
+    ) -> Result<Asset, Error> {
+        let (meta, payload) = self.split_asset(path, data)?;
+
+        match meta.ctype {
+            HeaderType::Texture(texture_meta) => {
+                let main_format = texture_meta
+                    .format
+                    .as_deref()
+                    .and_then(|format| format.parse::<TextureFormat>().ok());
+
+                //None picks the main data, which is also the fallback.
+                let variant = preferred_formats
+                    .iter()
+                    .take_while(|&&format| Some(format) != main_format)
+                    .find_map(|&format| {
+                        texture_meta
+                            .variants
+                            .iter()
+                            .find(|variant| variant.format == format)
+                    });
+
+                let out_of_range = || Self::out_of_range(path, "Texture data");
+
+                let (format, texture) = match variant {
+                    Some(variant) => {
+                        let end = variant
+                            .offset
+                            .checked_add(variant.length)
+                            .ok_or_else(out_of_range)?;
+                        let data = payload
+                            .get((variant.offset as usize)..(end as usize))
+                            .ok_or_else(out_of_range)?;
+
+                        (Some(variant.format.as_str().to_string()), data.to_vec())
+                    }
+                    None => (
+                        texture_meta.format,
+                        payload
+                            .get((texture_meta.offset as usize)..)
+                            .ok_or_else(out_of_range)?
+                            .to_vec(),
+                    ),
+                };
+
+                Ok(Asset::Texture(TextureData {
+                    width: texture_meta.width,
+                    height: texture_meta.height,
+                    format,
+                    data: texture,
+                    regions: texture_meta.regions,
+                    color_space: texture_meta.color_space,
+                    alpha_mode: texture_meta.alpha_mode,
+                    sampler: texture_meta.sampler,
+                    variants: Vec::new(),
+                    mips: Vec::new(),
+                }))
+            }
+            HeaderType::TextureArray(texarray_meta) => {
+                //Arrays without entries load as empty arrays.
+                let mut textures = Vec::<Vec<u8>>::new();
+                let mut keys = Vec::<String>::new();
+                for (i, entry) in texarray_meta.data.iter().enumerate() {
+                    let end_offset = if let Some(length) = entry.length {
+                        entry.offset.checked_add(length)
+                    } else if i + 1 >= texarray_meta.data.len() {
+                        Some(payload.len() as u64)
+                    } else {
+                        Some(texarray_meta.data[i + 1].offset)
+                    };
+
+                    let layer = end_offset
+                        .and_then(|end| payload.get((entry.offset as usize)..(end as usize)))
+                        .ok_or_else(|| Self::out_of_range(path, "Texture layer"))?;
+
+                    textures.push(layer.to_vec());
+
+                    keys.push(entry.key.clone());
+                }
+                Ok(Asset::TextureArray(TextureArrayData {
+                    size: texarray_meta.size,
+                    format: texarray_meta.format,
+                    keys,
+                    data: textures,
+                }))
+            }
+            HeaderType::Texture3D(volume_meta) => Ok(Asset::Texture3D(Texture3DData {
+                width: volume_meta.width,
+                height: volume_meta.height,
+                depth: volume_meta.depth,
+                format: volume_meta.format,
+                data: payload
+                    .get((volume_meta.offset as usize)..)
+                    .ok_or_else(|| Self::out_of_range(path, "Volume data"))?
+                    .to_vec(),
+            })),
+            HeaderType::Shader(shader_meta) => {
+                let shader = payload
+                    .get((shader_meta.offset as usize)..)
+                    .ok_or_else(|| Self::out_of_range(path, "Shader code"))?;
+
+                Ok(Asset::Shader(ShaderData {
+                    data: Self::read_spirv(shader),
+                    stages: shader_meta.stages,
+                    optimized: shader_meta.optimized,
+                    defines: shader_meta.defines,
+                    reflection: shader_meta.reflection,
+                    workgroup_size: shader_meta.workgroup_size,
+                }))
+            }
+            HeaderType::ShaderProgram(program_meta) => {
+                let mut stages = HashMap::new();
+
+                for (i, shader_meta) in program_meta.shaders.iter().enumerate() {
+                    let end_offset = if i + 1 >= program_meta.shaders.len() {
+                        payload.len()
+                    } else {
+                        program_meta.shaders[i + 1].offset as usize
+                    };
+
+                    let shader = payload
+                        .get((shader_meta.offset as usize)..end_offset)
+                        .ok_or_else(|| Self::out_of_range(path, "Shader code"))?;
+
+                    stages.insert(
+                        shader_meta.stages,
+                        ShaderData {
+                            data: Self::read_spirv(shader),
+                            stages: shader_meta.stages,
+                            optimized: shader_meta.optimized,
+                            defines: shader_meta.defines.clone(),
+                            reflection: shader_meta.reflection.clone(),
+                            workgroup_size: shader_meta.workgroup_size,
+                        },
+                    );
+                }
+
+                Ok(Asset::ShaderProgram(ShaderProgramData { stages }))
+            }
+            HeaderType::Gltf(gltf_meta) => {
+                let slice = payload
+                    .get((gltf_meta.offset as usize)..)
+                    .ok_or_else(|| Self::out_of_range(path, "glTF data"))?;
+
+                //Relative URIs start at the glTF's own folder, not at the location.
+                let base = match &self.location {
+                    Some(Location::File(root)) => root.join(path).parent().map(Path::to_path_buf),
+                    _ => None,
+                };
+
+                let (document, buffers, images) =
+                    gltf::import_slice(slice, base.as_deref(), |_, uri| {
+                        //Split files carry their resources as entries.
+                        if let Some(entry) = gltf_meta.entries.iter().find(|entry| entry.key == uri)
+                        {
+                            let start = entry.offset as usize;
+                            let end = entry.length.map_or(Some(gltf_meta.offset), |length| {
+                                entry.offset.checked_add(length)
+                            });
+
+                            return end
+                                .and_then(|end| payload.get(start..end as usize))
+                                .map(<[u8]>::to_vec)
+                                .ok_or_else(|| {
+                                    gltf::Error::Io(std::io::ErrorKind::UnexpectedEof.into())
+                                });
+                        }
+
+                        //Embedded resources never reach the backend.
+                        let res = if uri.starts_with("data:") {
+                            utils::decode_data_uri(uri)
+                        } else {
+                            self.resolve_uri(path, uri)
+                                .and_then(|uri| self.load_file(&uri, priority))
+                        };
+
+                        res.map_err(|err| match err {
+                            Error::Io(err) | Error::Read(_, err) => gltf::Error::Io(err),
+                            //gltf can only carry io errors, keep at least the message.
+                            err => gltf::Error::Io(std::io::Error::other(format!(
+                                "Failed to load {}. Error: {}",
+                                uri, err
+                            ))),
+                        })
+                    })
+                    .map_err(Error::GltfError)?;
+
+                let warnings = self.check_gltf(path, &document, &buffers)?;
+
+                Ok(Asset::Gltf(document, buffers, images, warnings))
+            }
+        }
+    }
+
+    fn write_asset<P: AsRef<Path>>(
+        &self,
+        output: P,
+        mut header: BaseHeader,
+        content: &[u8],
+        sources: &[PathBuf],
+        fingerprint: Option<String>,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let output = output.as_ref();
+
+        let output = if let Some(Location::File(path)) = &self.location {
+            path.join(output)
+        } else {
+            output.to_path_buf()
+        };
+
+        header.fingerprint = fingerprint;
+        header.footer = self.footer;
+
+        let plain = content;
+
+        let encrypted = match &self.encryption_key {
+            Some(key) => {
+                let (encryption, content) =
+                    crypto::encrypt(key, content).map_err(Error::Conversion)?;
+                header.encryption = Some(encryption);
+                Some(content)
+            }
+            None => None,
+        };
+
+        let content = encrypted.as_deref().unwrap_or(content);
+
+        let json = match serde_json::to_string(&header) {
+            Ok(json) => json,
+            Err(err) => {
+                return Err(Error::Conversion(format!(
+                    "Could not serialize header of {}. Error: {}",
+                    output.display(),
+                    err
+                )))
+            }
+        };
+
+        let size: u64 = json.len() as u64;
+        let mut bytes = [&size.to_le_bytes(), json.as_bytes(), content].concat();
+
+        if self.footer {
+            let footer = Footer {
+                header_offset: std::mem::size_of::<u64>() as u64,
+                payload_length: content.len() as u64,
+            };
+            bytes.extend_from_slice(&footer.to_bytes());
+        }
+
+        if self.dry_run {
+            log::info!(
+                "Dry run. Would write {} ({} bytes)",
+                output.display(),
+                bytes.len()
+            );
+        } else {
+            self.backend.write_file(&output, &bytes, overwrite)?;
+
+            if self.verify {
+                self.verify_written(&output, &json, plain)?;
+            }
+        }
+
+        self.record(output.clone(), &header, sources, bytes.len() as u64);
+
+        Ok(ConvertReport {
+            output,
+            bytes_written: bytes.len() as u64,
+            up_to_date: false,
+        })
+    }
+
+    //Read a written asset back and compare it with what was meant to be written.
+    fn verify_written(&self, output: &Path, json: &str, content: &[u8]) -> Result<(), Error> {
+        let path = utils::path_text(output, output.as_os_str())?;
+        let (data, _) = self.backend.read_file(&None, path, None)?;
+
+        let failed = |reason: &str| {
+            Error::Conversion(format!(
+                "Verification of {} failed. {}",
+                output.display(),
+                reason
+            ))
+        };
+
+        let header_end = std::mem::size_of::<u64>() + json.len();
+        if data.get(std::mem::size_of::<u64>()..header_end) != Some(json.as_bytes()) {
+            return Err(failed("The header differs."));
+        }
+
+        let (_, payload) = self
+            .split_asset(path, &data)
+            .map_err(|err| failed(&err.to_string()))?;
+
+        if *payload != *content {
+            return Err(failed("The payload differs."));
+        }
+
+        Ok(())
+    }
+
+    //Add a written asset to the manifest, together with the files it was converted from.
+    fn record(&self, output: PathBuf, header: &BaseHeader, sources: &[PathBuf], bytes: u64) {
+        let (width, height, format) = match &header.ctype {
+            HeaderType::Texture(texture) => (
+                Some(texture.width),
+                Some(texture.height),
+                texture.format.clone(),
+            ),
+            HeaderType::TextureArray(textures) => (
+                Some(textures.size),
+                Some(textures.size),
+                textures.format.clone(),
+            ),
+            HeaderType::Texture3D(volume) => (
+                Some(volume.width),
+                Some(volume.height),
+                volume.format.clone(),
+            ),
+            HeaderType::Shader(_) | HeaderType::ShaderProgram(_) | HeaderType::Gltf(_) => {
+                (None, None, None)
+            }
+        };
+
+        let mut manifest = self.manifest.lock().unwrap_or_else(PoisonError::into_inner);
+
+        manifest.assets.push(ManifestEntry {
+            output,
+            kind: header.ctype.kind().as_str().to_string(),
+            sources: sources.to_vec(),
+            width,
+            height,
+            format,
+            bytes,
+        });
+    }
+
+    //In incremental mode, the fingerprint of `sources` and `settings` that outputs store in
+    //their header. Covers the encryption key, so outputs are rewritten when it changes.
+    fn fingerprint(&self, sources: &[PathBuf], settings: &str) -> Option<String> {
+        if !self.incremental {
+            return None;
+        }
+
+        let encryption = match &self.encryption_key {
+            Some(key) => crypto::key_id(key),
+            None => "none".to_string(),
+        };
+
+        //Unreadable sources fail the conversion itself with a better error.
+        utils::fingerprint(sources, &format!("{} encryption {}", settings, encryption)).ok()
+    }
+
+    //Skip a conversion if `output` was written with the same fingerprint.
+    fn unchanged(
+        &self,
+        output: &Path,
+        sources: &[PathBuf],
+        fingerprint: &Option<String>,
+    ) -> Option<ConvertReport> {
+        let fingerprint = fingerprint.as_ref()?;
+
+        let output = self.resolve(output);
+        let path = utils::path_text(&output, output.as_os_str()).ok()?;
+        let header = self.read_header(&None, path).ok()?;
+
+        if header.fingerprint.as_ref() != Some(fingerprint) {
+            return None;
+        }
+
+        let bytes = std::fs::metadata(&output).map_or(0, |meta| meta.len());
+        self.record(output.clone(), &header, sources, bytes);
+
+        Some(ConvertReport {
+            output,
+            bytes_written: 0,
+            up_to_date: true,
+        })
+    }
+
+    /// Size in bytes the asset would have on disk, including its header and the encryption
+    /// overhead if a key is set. Incremental builds add a fingerprint of a few bytes on top.
+    pub fn estimated_size(&self, asset: &AssetInput) -> Result<u64, Error> {
+        let (mut header, content) = Self::encode(asset)?;
+        let mut payload = content.len();
+
+        if self.encryption_key.is_some() {
+            header.encryption = Some(crypto::placeholder());
+            payload += crypto::TAG_SIZE;
+        }
+
+        if self.footer {
+            header.footer = true;
+            payload += Footer::SIZE;
+        }
+
+        let json = serde_json::to_string(&header).map_err(Error::JsonError)?;
+
+        Ok((std::mem::size_of::<u64>() + json.len() + payload) as u64)
+    }
+
+    //Header and payload of an asset, as written before encryption.
+    fn encode<'a>(asset: &AssetInput<'a>) -> Result<(BaseHeader, Cow<'a, [u8]>), Error> {
+        Ok(match *asset {
+            AssetInput::Texture(texture) => {
+                let mut mips = Vec::new();
+                let mut variants = Vec::new();
+                let mut content = Vec::new();
+
+                for mip in &texture.mips {
+                    mips.push(HeaderTextureMip {
+                        width: mip.width,
+                        height: mip.height,
+                        format: mip.format.clone(),
+                        offset: content.len() as u64,
+                        length: mip.data.len() as u64,
+                    });
+                    content.extend_from_slice(&mip.data);
+                }
+
+                for variant in &texture.variants {
+                    variants.push(HeaderTextureVariant {
+                        format: variant.format,
+                        offset: content.len() as u64,
+                        length: variant.data.len() as u64,
+                    });
+                    content.extend_from_slice(&variant.data);
+                }
+
+                let header = BaseHeader {
+                    major: VERSION_MAJOR,
+                    minor: VERSION_MINOR,
+                    encryption: None,
+                    fingerprint: None,
+                    footer: false,
+                    ctype: HeaderType::Texture(HeaderTexture {
+                        width: texture.width,
+                        height: texture.height,
+                        format: texture.format.as_ref().map(String::from),
+                        offset: content.len() as u64,
+                        regions: texture.regions.clone(),
+                        color_space: texture.color_space,
+                        alpha_mode: texture.alpha_mode,
+                        sampler: texture.sampler,
+                        variants,
+                        mips,
+                    }),
+                };
+
+                if content.is_empty() {
+                    (header, Cow::Borrowed(texture.data.as_slice()))
+                } else {
+                    content.extend_from_slice(&texture.data);
+                    (header, Cow::Owned(content))
+                }
+            }
+            AssetInput::TextureArray(textures) => {
+                if textures.keys.len() != textures.data.len() {
+                    return Err(Error::Conversion(format!(
+                        "Texture array keys and data must have the same length. Keys: {} Textures: {}",
+                        textures.keys.len(),
+                        textures.data.len()
+                    )));
+                }
+
+                let mut entries = Vec::<HeaderEntry>::new();
+                let mut content = Vec::<u8>::new();
+                //Identical layers (e.g. repeated cubemap faces) are stored once and share an offset.
+                let mut stored = HashMap::<&[u8], u64>::new();
+
+                for i in 0..textures.keys.len() {
+                    let data = textures.data[i].as_slice();
+
+                    let offset = *stored.entry(data).or_insert_with(|| {
+                        content.extend_from_slice(data);
+                        (content.len() - data.len()) as u64
+                    });
+
+                    entries.push(HeaderEntry {
+                        key: textures.keys[i].to_string(),
+                        offset,
+                        length: Some(data.len() as u64),
+                    });
+                }
+
+                let header = BaseHeader {
+                    major: VERSION_MAJOR,
+                    minor: VERSION_MINOR,
+                    encryption: None,
+                    fingerprint: None,
+                    footer: false,
+                    ctype: HeaderType::TextureArray(HeaderTextureArray {
+                        size: textures.size,
+                        format: textures.format.as_ref().map(String::from),
+                        data: entries,
+                    }),
+                };
+
+                (header, Cow::Owned(content))
+            }
+            AssetInput::Texture3D(volume) => {
+                let texels = volume.width as u64 * volume.height as u64 * volume.depth as u64;
+
+                //None for zero texels, which never fit.
+                let fits = (volume.data.len() as u64).checked_rem(texels) == Some(0);
+
+                if !fits {
+                    return Err(Error::Conversion(format!(
+                        "Volume data of {} bytes doesn't fit {}x{}x{} texels.",
+                        volume.data.len(),
+                        volume.width,
+                        volume.height,
+                        volume.depth
+                    )));
+                }
+
+                let header = BaseHeader {
+                    major: VERSION_MAJOR,
+                    minor: VERSION_MINOR,
+                    encryption: None,
+                    fingerprint: None,
+                    footer: false,
+                    ctype: HeaderType::Texture3D(HeaderTexture3D {
+                        width: volume.width,
+                        height: volume.height,
+                        depth: volume.depth,
+                        format: volume.format.as_ref().map(String::from),
+                        offset: 0,
+                    }),
+                };
+
+                (header, Cow::Borrowed(volume.data.as_slice()))
+            }
+            AssetInput::Shader(shader) => {
+                let header = BaseHeader {
+                    major: VERSION_MAJOR,
+                    minor: VERSION_MINOR,
+                    encryption: None,
+                    fingerprint: None,
+                    footer: false,
+                    ctype: HeaderType::Shader(HeaderShader {
+                        defines: shader.defines.clone(),
+                        offset: 0,
+                        stages: shader.stages,
+                        optimized: shader.optimized,
+                        reflection: shader.reflection.clone(),
+                        workgroup_size: shader.workgroup_size,
+                    }),
+                };
+
+                let mut raw_shader = Vec::new();
+
+                for value in &shader.data {
+                    raw_shader.extend_from_slice(&value.to_le_bytes());
+                }
+
+                (header, Cow::Owned(raw_shader))
+            }
+            AssetInput::ShaderProgram(program) => {
+                //Keep the stage order stable so identical programs produce identical files.
+                let mut stages = program.stages.values().collect::<Vec<&ShaderData>>();
+                stages.sort_by_key(|shader| shader.stages.bits());
+
+                let mut shaders = Vec::<HeaderShader>::new();
+                let mut raw_program = Vec::new();
+
+                for shader in stages {
+                    shaders.push(HeaderShader {
+                        defines: shader.defines.clone(),
+                        offset: raw_program.len() as u64,
+                        stages: shader.stages,
+                        optimized: shader.optimized,
+                        reflection: shader.reflection.clone(),
+                        workgroup_size: shader.workgroup_size,
+                    });
+
+                    for value in &shader.data {
+                        raw_program.extend_from_slice(&value.to_le_bytes());
+                    }
+                }
+
+                let header = BaseHeader {
+                    major: VERSION_MAJOR,
+                    minor: VERSION_MINOR,
+                    encryption: None,
+                    fingerprint: None,
+                    footer: false,
+                    ctype: HeaderType::ShaderProgram(HeaderShaderProgram { shaders }),
+                };
+
+                (header, Cow::Owned(raw_program))
+            }
+            AssetInput::Gltf(gltf) => {
+                let header = BaseHeader {
+                    major: VERSION_MAJOR,
+                    minor: VERSION_MINOR,
+                    encryption: None,
+                    fingerprint: None,
+                    footer: false,
+                    ctype: HeaderType::Gltf(HeaderGltf {
+                        offset: 0,
+                        entries: Vec::new(),
+                    }),
+                };
+
+                (header, Cow::Borrowed(gltf))
+            }
+        })
+    }
+
+    //Encode and write `input`, recording `sources` as its inputs in the manifest.
+    fn write_input<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: &AssetInput,
+        sources: &[PathBuf],
+        fingerprint: Option<String>,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let (header, content) = Self::encode(input)?;
+        self.write_asset(output, header, &content, sources, fingerprint, overwrite)
+    }
+
+    pub fn write_texture_array<P: AsRef<Path>>(
+        &self,
+        output: P,
+        textures: &TextureArrayData,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.write_input(
+            output,
+            &AssetInput::TextureArray(textures),
+            &[],
+            None,
+            overwrite,
+        )
+    }
+
+    /// Add the layer `key` read from `image_path` to the texture array at `path`, or replace
+    /// the layer if the key exists, and rewrite the file. The image has to match the size and
+    /// format of the other layers.
+    pub fn append_texture_array_layer<S: AsRef<str>, P: AsRef<Path>>(
+        &mut self,
+        path: S,
+        key: &str,
+        image_path: P,
+    ) -> Result<ConvertReport, Error> {
+        let path = path.as_ref();
+
+        let mut array = self.load_texture_array(path, Priority::Normal)?;
+
+        let input = match &self.location {
+            Some(Location::File(base)) => base.join(image_path),
+            _ => image_path.as_ref().to_path_buf(),
+        };
+
+        let (dimension, texture) = Self::read_array_layer(&input)?;
+
+        if dimension.0 != dimension.1 || dimension.0 != array.size {
+            return Err(Error::Conversion(format!(
+                "Layer {} is {}x{}, but the texture array {} holds {}x{} layers.",
+                input.display(),
+                dimension.0,
+                dimension.1,
+                path,
+                array.size,
+                array.size
+            )));
+        }
+
+        let format = input
+            .extension()
+            .map(|ext| utils::path_text(&input, ext).map(String::from))
+            .transpose()?;
+
+        if array.format.is_some() && format != array.format {
+            return Err(Error::Conversion(format!(
+                "Layer {} is {}, but the texture array {} holds {} layers.",
+                input.display(),
+                format.as_deref().unwrap_or("unknown"),
+                path,
+                array.format.as_deref().unwrap_or("unknown")
+            )));
+        }
+
+        match array.keys.iter().position(|layer| layer == key) {
+            Some(index) => array.data[index] = texture,
+            None => {
+                array.keys.push(key.to_string());
+                array.data.push(texture);
+            }
+        }
+
+        let report = self.write_input(
+            path,
+            &AssetInput::TextureArray(&array),
+            std::slice::from_ref(&input),
+            None,
+            true,
+        )?;
+
+        //The cached copy is outdated now.
+        if let Some(guid) = self.paths.get(&self.normalize(path)) {
+            self.cache.remove(guid);
+            self.drop_stale_hashes();
+        }
+
+        Ok(report)
+    }
+
+    pub fn write_texture_3d<P: AsRef<Path>>(
+        &self,
+        output: P,
+        volume: &Texture3DData,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.write_input(output, &AssetInput::Texture3D(volume), &[], None, overwrite)
+    }
+
+    pub fn write_shader<P: AsRef<Path>>(
+        &self,
+        output: P,
+        shader: &ShaderData,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.write_input(output, &AssetInput::Shader(shader), &[], None, overwrite)
+    }
+
+    pub fn write_shader_program<P: AsRef<Path>>(
+        &self,
+        output: P,
+        program: &ShaderProgramData,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.write_input(
+            output,
+            &AssetInput::ShaderProgram(program),
+            &[],
+            None,
+            overwrite,
+        )
+    }
+
+    pub fn convert_gltf<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.convert_gltf_with(output, input, &GltfOptions::default(), overwrite)
+    }
+
+    pub fn convert_gltf_with<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        options: &GltfOptions,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let input = input.as_ref();
+
+        let input = if let Some(Location::File(path)) = &self.location {
+            path.join(input)
+        } else {
+            input.to_path_buf()
+        };
+
+        let input = input.as_path();
+
+        if options.split {
+            return self.convert_gltf_split(output.as_ref(), input, overwrite);
+        }
+
+        let fingerprint = self.fingerprint(&[input.to_path_buf()], "");
+        if let Some(report) = self.unchanged(output.as_ref(), &[input.to_path_buf()], &fingerprint)
+        {
+            return Ok(report);
+        }
+
+        let gltf = std::fs::read(input).map_err(|err| Error::Read(input.to_path_buf(), err))?;
+
+        //External buffers and images stay references and are resolved on load.
+        if let Err(err) = gltf::Gltf::from_slice_without_validation(&gltf) {
+            return Err(Error::Conversion(format!(
+                "Failed to parse glTF {}. Error: {}",
+                input.display(),
+                err
+            )));
+        }
+
+        self.write_input(
+            output,
+            &AssetInput::Gltf(&gltf),
+            &[input.to_path_buf()],
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    fn convert_gltf_split(
+        &self,
+        output: &Path,
+        input: &Path,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let gltf = std::fs::read(input).map_err(|err| Error::Read(input.to_path_buf(), err))?;
+
+        let invalid = |err: String| {
+            Error::Conversion(format!(
+                "Failed to parse glTF {}. Error: {}",
+                input.display(),
+                err
+            ))
+        };
+
+        //All resources are at hand, so the document is checked like a load would.
+        let document = gltf::Gltf::from_slice(&gltf)
+            .map_err(|err| invalid(err.to_string()))?
+            .document;
+
+        let (json, mut bin) = if gltf.starts_with(b"glTF") {
+            let glb =
+                gltf::binary::Glb::from_slice(&gltf).map_err(|err| invalid(err.to_string()))?;
+            (glb.json.into_owned(), glb.bin.map(Cow::into_owned))
+        } else {
+            (gltf.clone(), None)
+        };
+
+        let mut json = serde_json::from_slice::<serde_json::Value>(&json)
+            .map_err(|err| invalid(err.to_string()))?;
+
+        //External files are resolved first, so unchanged inputs are skipped before reading them.
+        let mut resources = Vec::new();
+        let mut sources = vec![input.to_path_buf()];
+
+        for kind in ["buffers", "images"] {
+            let Some(items) = json.get(kind).and_then(serde_json::Value::as_array) else {
+                continue;
+            };
+
+            for (i, item) in items.iter().enumerate() {
+                let path = match item.get("uri").and_then(serde_json::Value::as_str) {
+                    Some(uri) if !uri.starts_with("data:") => {
+                        let uri =
+                            urlencoding::decode(uri).map_err(|err| invalid(err.to_string()))?;
+                        let base = utils::path_text(input, input.as_os_str())?;
+                        let path = PathBuf::from(utils::join_relative(base, &uri));
+
+                        sources.push(path.clone());
+                        Some(path)
+                    }
+                    _ => None,
+                };
+
+                resources.push((kind, i, path));
+            }
+        }
+
+        let fingerprint = self.fingerprint(&sources, "split");
+        if let Some(report) = self.unchanged(output, &sources, &fingerprint) {
+            return Ok(report);
+        }
+
+        let mut entries = Vec::new();
+        let mut content = Vec::new();
+        let mut buffers = Vec::new();
+
+        for (kind, i, path) in resources {
+            let item = &mut json[kind][i];
+
+            let data = match (path, item.get("uri").and_then(serde_json::Value::as_str)) {
+                (Some(path), _) => {
+                    std::fs::read(&path).map_err(|err| Error::Read(path.clone(), err))?
+                }
+                (None, Some(uri)) => utils::decode_data_uri(uri)?,
+                //The binary chunk of a .glb belongs to the buffer without URI.
+                (None, None) if kind == "buffers" => match bin.take() {
+                    Some(bin) => bin,
+                    None => {
+                        buffers.push(gltf::buffer::Data(Vec::new()));
+                        continue;
+                    }
+                },
+                //Images in buffer views are stored with their buffer.
+                (None, None) => continue,
+            };
+
+            if kind == "buffers" {
+                buffers.push(gltf::buffer::Data(data.clone()));
+            }
+
+            let key = format!("{}/{}", kind, i);
+
+            entries.push(HeaderEntry {
+                key: key.clone(),
+                offset: content.len() as u64,
+                length: Some(data.len() as u64),
+            });
+            content.extend_from_slice(&data);
+            item["uri"] = key.into();
+        }
+
+        self.check_gltf(&input.display().to_string(), &document, &buffers)?;
+
+        let offset = content.len() as u64;
+        content.extend(serde_json::to_vec(&json).map_err(Error::JsonError)?);
+
+        let header = BaseHeader {
+            major: VERSION_MAJOR,
+            minor: VERSION_MINOR,
+            encryption: None,
+            fingerprint: None,
+            footer: false,
+            ctype: HeaderType::Gltf(HeaderGltf { offset, entries }),
+        };
+
+        self.write_asset(output, header, &content, &sources, fingerprint, overwrite)
+    }
+
+    pub fn convert_texture<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.convert_texture_with(output, input, &TextureOptions::default(), overwrite)
+    }
+
+    /// Store the bytes of `input` as they are, described by the given size and format.
+    /// Nothing is decoded or checked, so the caller is responsible for the metadata.
+    pub fn convert_texture_raw<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        width: u32,
+        height: u32,
+        format: Option<&str>,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let output = output.as_ref();
+        let input = self.resolve(input);
+
+        let settings = format!("{}x{} {:?}", width, height, format);
+        let fingerprint = self.fingerprint(std::slice::from_ref(&input), &settings);
+        if let Some(report) = self.unchanged(output, std::slice::from_ref(&input), &fingerprint) {
+            return Ok(report);
+        }
+
+        let data = std::fs::read(&input).map_err(|err| Error::Read(input.clone(), err))?;
+
+        let texture = TextureData {
+            width,
+            height,
+            format: format.map(String::from),
+            data,
+            regions: Vec::new(),
+            color_space: ColorSpace::default(),
+            alpha_mode: AlphaMode::default(),
+            sampler: None,
+            variants: Vec::new(),
+            mips: Vec::new(),
+        };
+
+        self.write_input(
+            output,
+            &AssetInput::Texture(&texture),
+            std::slice::from_ref(&input),
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    /// Store `pixels` as a rgba8 texture, e.g. one generated at runtime. Rows are tightly
+    /// packed, so it needs to hold exactly `width * height * 4` bytes.
+    pub fn write_texture_rgba<P: AsRef<Path>>(
+        &self,
+        output: P,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let expected = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|texels| texels.checked_mul(4));
+
+        if expected != Some(pixels.len()) {
+            return Err(Error::Conversion(format!(
+                "A {}x{} rgba8 texture needs {} bytes, but got {}.",
+                width,
+                height,
+                expected.map_or("more".to_string(), |expected| expected.to_string()),
+                pixels.len()
+            )));
+        }
+
+        let texture = TextureData {
+            width,
+            height,
+            format: Some(TextureFormat::Rgba8.as_str().to_string()),
+            data: pixels.to_vec(),
+            regions: Vec::new(),
+            color_space: ColorSpace::default(),
+            alpha_mode: AlphaMode::default(),
+            sampler: None,
+            variants: Vec::new(),
+            mips: Vec::new(),
+        };
+
+        self.write_input(output, &AssetInput::Texture(&texture), &[], None, overwrite)
+    }
+
+    pub fn convert_texture_with<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        options: &TextureOptions,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let output = output.as_ref();
+        let input = input.as_ref();
+
+        let input = if let Some(Location::File(path)) = &self.location {
+            path.join(input)
+        } else {
+            input.to_path_buf()
+        };
+
+        let input = input.as_path();
+
+        let settings = format!("{:?}", options);
+        let fingerprint = self.fingerprint(&[input.to_path_buf()], &settings);
+        if let Some(report) = self.unchanged(output, &[input.to_path_buf()], &fingerprint) {
+            return Ok(report);
+        }
+
+        let dimension = image::image_dimensions(input).map_err(|err| match err {
+            image::ImageError::Unsupported(_) => Error::Conversion(format!(
+                "Unsupported image format {} for {}. Formats such as webp and avif need the matching cargo feature.",
+                input
+                    .extension()
+                    .map_or("(none)".into(), |ext| ext.to_string_lossy()),
+                input.display()
+            )),
+            err => Self::image_error(input, err),
+        })?;
+
+        Self::check_image(input, dimension)?;
+
+        if let ImageEncoding::Jpeg(quality) = options.encoding {
+            if !(1..=100).contains(&quality) {
+                return Err(Error::Conversion(format!(
+                    "Jpeg quality {} for {} isn't between 1 and 100.",
+                    quality,
+                    input.display()
+                )));
+            }
+        }
+
+        for region in &options.regions {
+            if region.x as u64 + region.w as u64 > dimension.0 as u64
+                || region.y as u64 + region.h as u64 > dimension.1 as u64
+            {
+                return Err(Error::Conversion(format!(
+                    "Region {} exceeds the {}x{} image {}.",
+                    region.name,
+                    dimension.0,
+                    dimension.1,
+                    input.display()
+                )));
+            }
+        }
+
+        let mut data = std::fs::read(input).map_err(|err| Error::Read(input.to_path_buf(), err))?;
+
+        if options.strip_metadata {
+            match metadata::strip(&data, options.keep_icc) {
+                Some(stripped) => data = stripped,
+                None => log::warn!(
+                    "Can't strip metadata of {}. Only png and jpeg are supported.",
+                    input.display()
+                ),
+            }
+        }
+
+        let mut texture = TextureData {
+            width: dimension.0,
+            height: dimension.1,
+            format: input
+                .extension()
+                .map(|ext| utils::path_text(input, ext).map(String::from))
+                .transpose()?,
+            data,
+            regions: options.regions.clone(),
+            color_space: options.color_space,
+            alpha_mode: AlphaMode::Straight,
+            sampler: options.sampler,
+            variants: Vec::new(),
+            mips: Vec::new(),
+        };
+
+        let float = Self::float_format(input);
+
+        if let Some(format) = float {
+            Self::decode_float(input, format, &mut texture, options.alpha_mode)?;
+        }
+
+        let size = Self::clamp_size(dimension, options);
+        let resized = size != dimension;
+
+        if resized {
+            Self::resize_texture(
+                input,
+                &mut texture,
+                size,
+                options.resize_filter,
+                options.encoding,
+            )?;
+        }
+
+        let premultiplied = float.is_none() && options.alpha_mode == AlphaMode::Premultiplied;
+
+        if premultiplied {
+            Self::premultiply_alpha(input, &mut texture, options.encoding)?;
+        }
+
+        if float.is_none()
+            && !resized
+            && !premultiplied
+            && matches!(options.encoding, ImageEncoding::Jpeg(_))
+        {
+            let image = image::load_from_memory(&texture.data)
+                .map_err(|err| Error::Image(input.to_path_buf(), err))?;
+
+            texture.data = options
+                .encoding
+                .encode(&image)
+                .map_err(|err| Error::Image(input.to_path_buf(), err))?;
+            texture.format = Some(options.encoding.format().to_string());
+        }
+
+        //Variants are encoded from the image before it's compressed.
+        let variants = options
+            .variants
+            .iter()
+            .map(|&format| Self::encode_variant(input, &texture, format))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mips = if options.mipmaps {
+            Self::generate_mips(input, &texture, options.resize_filter)?
+        } else {
+            Vec::new()
+        };
+
+        if let Some(format) = options.compress_to {
+            Self::compress_texture(input, &mut texture, format)?;
+        }
+
+        texture.variants = variants;
+        texture.mips = mips;
+
+        self.write_input(
+            output,
+            &AssetInput::Texture(&texture),
+            &[input.to_path_buf()],
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    /// Derive an rgba8 normal map from a grayscale height map. Higher strength gives steeper normals.
+    pub fn convert_normal_from_height<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        strength: f32,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let input = self.resolve(input);
+
+        let settings = strength.to_string();
+        let fingerprint = self.fingerprint(std::slice::from_ref(&input), &settings);
+        if let Some(report) =
+            self.unchanged(output.as_ref(), std::slice::from_ref(&input), &fingerprint)
+        {
+            return Ok(report);
+        }
+
+        let height = image::open(&input)
+            .map_err(|err| Self::image_error(&input, err))?
+            .to_luma16();
+
+        let texture = TextureData {
+            width: height.width(),
+            height: height.height(),
+            format: Some(TextureFormat::Rgba8.as_str().to_string()),
+            data: normal::from_height(&height, strength).into_raw(),
+            regions: Vec::new(),
+            color_space: ColorSpace::Linear,
+            alpha_mode: AlphaMode::Straight,
+            sampler: None,
+            variants: Vec::new(),
+            mips: Vec::new(),
+        };
+
+        self.write_input(
+            output,
+            &AssetInput::Texture(&texture),
+            std::slice::from_ref(&input),
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    pub fn convert_texture_array<P: AsRef<Path>, S: Into<String> + Clone>(
+        &self,
+        output: P,
+        keys: Option<&[S]>,
+        inputs: &[P],
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.convert_texture_array_with_progress(output, keys, inputs, overwrite, |_, _| {})
+    }
+
+    /// Like convert_texture_array, but calls progress(current, total) after each layer.
+    pub fn convert_texture_array_with_progress<P: AsRef<Path>, S: Into<String> + Clone>(
+        &self,
+        output: P,
+        keys: Option<&[S]>,
+        inputs: &[P],
+        overwrite: bool,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<ConvertReport, Error> {
+        let output = output.as_ref();
+
+        if inputs.is_empty() {
+            return Err(Error::Conversion(format!(
+                "A texture array needs at least one texture. Output: {}",
+                output.display()
+            )));
+        }
+
+        let inputs = inputs
+            .iter()
+            .map(|a| {
+                if let Some(Location::File(path)) = &self.location {
+                    path.join(a)
+                } else {
+                    a.as_ref().to_path_buf()
+                }
+            })
+            .collect::<Vec<PathBuf>>();
+        let keys = keys.map(|a| {
+            a.iter()
+                .map(|b| (*b).clone().into())
+                .collect::<Vec<String>>()
+        });
+
+        let mut textures = Vec::<Vec<u8>>::with_capacity(inputs.len());
+
+        let keys = match keys {
+            Some(keys) => keys,
+            None => inputs
+                .iter()
+                .map(|a| {
+                    let stem = a.file_stem().unwrap_or(std::ffi::OsStr::new("Unknown"));
+                    utils::path_text(a, stem).map(String::from)
+                })
+                .collect::<Result<Vec<String>, Error>>()?,
+        };
+
+        let fingerprint = self.fingerprint(&inputs, &format!("{:?}", keys));
+        if let Some(report) = self.unchanged(output, &inputs, &fingerprint) {
+            return Ok(report);
+        }
+
+        let mut size = 0;
+        let mut format = None;
+
+        //Only image headers are read here, so checking in input order is cheap and fails on
+        //the first bad layer before any of them is read in full.
+        for input in &inputs {
+            let dimension = Self::probe_array_layer(input)?;
+
+            if dimension.0 != dimension.1 {
+                return Err(Error::Conversion(format!(
+                    "Cubemap textures need to be quadratic. File: {}, ",
+                    input.display()
+                )));
+            }
+
+            if size == 0 {
+                size = dimension.0;
+            } else if dimension.0 != size {
+                return Err(Error::Conversion(format!(
+                    "All textures must have the same size. File: {}",
+                    input.display()
+                )));
+            }
+
+            if format.is_none() {
+                format = input
+                    .extension()
+                    .map(|ext| utils::path_text(input, ext).map(String::from))
+                    .transpose()?;
+            }
+        }
+
+        let read = |input: &PathBuf| {
+            std::fs::read(input).map_err(|err| Error::Read(input.to_path_buf(), err))
+        };
+
+        //Reading the layers is the expensive part, so it's the part that runs in parallel.
+        //The first failed read stops the others.
+        let layers = self.map_inputs(&inputs, read)?;
+
+        for (i, layer) in layers.into_iter().enumerate() {
+            textures.push(layer);
+            progress(i + 1, inputs.len());
+        }
+
+        let textures = TextureArrayData {
+            size,
+            format,
+            keys,
+            data: textures,
+        };
+
+        self.write_input(
+            output,
+            &AssetInput::TextureArray(&textures),
+            &inputs,
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    //Map `inputs` in order, stopping at the first error. Runs on the thread pool if there is
+    //more than one thread.
+    #[cfg(feature = "parallel")]
+    fn map_inputs<I: Sync, T: Send>(
+        &self,
+        inputs: &[I],
+        map: impl Fn(&I) -> Result<T, Error> + Sync,
+    ) -> Result<Vec<T>, Error> {
+        use rayon::prelude::*;
+
+        if self.threads() <= 1 {
+            return inputs.iter().map(map).collect();
+        }
+
+        let map_all = || inputs.par_iter().map(&map).collect::<Result<Vec<_>, _>>();
+
+        match &self.thread_pool {
+            Some(pool) => pool.install(map_all),
+            None => map_all(),
+        }
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn map_inputs<I, T>(
+        &self,
+        inputs: &[I],
+        map: impl Fn(&I) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        inputs.iter().map(map).collect()
+    }
+
+    fn read_array_layer(input: &Path) -> Result<((u32, u32), Vec<u8>), Error> {
+        let dimension = Self::probe_array_layer(input)?;
+        let texture = std::fs::read(input).map_err(|err| Error::Read(input.to_path_buf(), err))?;
+
+        Ok((dimension, texture))
+    }
+
+    fn probe_array_layer(input: &Path) -> Result<(u32, u32), Error> {
+        let dimension =
+            image::image_dimensions(input).map_err(|err| Self::image_error(input, err))?;
+
+        Self::check_image(input, dimension)?;
+
+        Ok(dimension)
+    }
+
+    //Failing to read an image is an Error::Read, so callers can tell it apart from bad content.
+    fn image_error(input: &Path, err: image::ImageError) -> Error {
+        match err {
+            image::ImageError::IoError(err) => Error::Read(input.to_path_buf(), err),
+            err => Error::Image(input.to_path_buf(), err),
+        }
+    }
+
+    //image_dimensions only looks at the first frame. Reject what a texture can't describe.
+    fn check_image(input: &Path, dimension: (u32, u32)) -> Result<(), Error> {
+        if dimension.0 == 0 || dimension.1 == 0 {
+            return Err(Error::Conversion(format!(
+                "Image {} has no pixels ({}x{}).",
+                input.display(),
+                dimension.0,
+                dimension.1
+            )));
+        }
+
+        if Self::is_animated(input) {
+            return Err(Error::Conversion(format!(
+                "Image {} is animated. Only single frame images can be converted to textures.",
+                input.display()
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn float_format(input: &Path) -> Option<image::ImageFormat> {
+        image::io::Reader::open(input)
+            .and_then(|reader| reader.with_guessed_format())
+            .ok()
+            .and_then(|reader| reader.format())
+            .filter(|format| {
+                matches!(
+                    format,
+                    image::ImageFormat::Hdr | image::ImageFormat::OpenExr
+                )
+            })
+    }
+
+    //GPUs can't sample hdr/exr files, so store the decoded float texels instead.
+    fn decode_float(
+        input: &Path,
+        format: image::ImageFormat,
+        texture: &mut TextureData,
+        alpha_mode: AlphaMode,
+    ) -> Result<(), Error> {
+        let mut image = Self::decode_float_image(
+            input,
+            format,
+            &texture.data,
+            (texture.width, texture.height),
+        )?;
+
+        if alpha_mode == AlphaMode::Premultiplied {
+            for pixel in image.pixels_mut() {
+                let alpha = pixel.0[3];
+                pixel.0[..3]
+                    .iter_mut()
+                    .for_each(|channel| *channel *= alpha);
+            }
+        }
+
+        texture.data = image
+            .as_raw()
+            .iter()
+            .flat_map(|channel| channel.to_le_bytes())
+            .collect();
+        texture.format = Some(TextureFormat::Rgba32F.as_str().to_string());
+        texture.alpha_mode = alpha_mode;
+
+        Ok(())
+    }
+
+    fn decode_float_image(
+        input: &Path,
+        format: image::ImageFormat,
+        data: &[u8],
+        (width, height): (u32, u32),
+    ) -> Result<image::Rgba32FImage, Error> {
+        let decode_error = |err| Error::Image(input.to_path_buf(), err);
+
+        if format == image::ImageFormat::Hdr {
+            //The generic hdr decoder tone maps to 8 bit, so read the float texels directly.
+            let pixels = image::codecs::hdr::HdrDecoder::new(Cursor::new(data))
+                .and_then(|decoder| decoder.read_image_hdr())
+                .map_err(decode_error)?;
+
+            Ok(image::Rgba32FImage::from_fn(width, height, |x, y| {
+                let [r, g, b] = pixels[(y * width + x) as usize].0;
+                image::Rgba([r, g, b, 1.0])
+            }))
+        } else {
+            Ok(image::load_from_memory_with_format(data, format)
+                .map_err(decode_error)?
+                .to_rgba32f())
+        }
+    }
+
+    fn clamp_size((width, height): (u32, u32), options: &TextureOptions) -> (u32, u32) {
+        let (mut width, mut height) = (width, height);
+
+        if let Some(max_size) = options.max_size {
+            let larger = width.max(height);
+
+            if larger > max_size {
+                let scale = |side: u32| {
+                    ((side as u64 * max_size as u64 + larger as u64 / 2) / larger as u64).max(1)
+                        as u32
+                };
+                (width, height) = (scale(width), scale(height));
+            }
+        }
+
+        if options.power_of_two {
+            let round = |side: u32| 1 << (u32::BITS - 1 - side.leading_zeros());
+            (width, height) = (round(width), round(height));
+        }
+
+        (width, height)
+    }
+
+    //Float textures are already decoded, everything else is re-encoded as png.
+    fn resize_texture(
+        input: &Path,
+        texture: &mut TextureData,
+        (width, height): (u32, u32),
+        filter: ResizeFilter,
+        encoding: ImageEncoding,
+    ) -> Result<(), Error> {
+        if texture.texture_format() == Some(TextureFormat::Rgba32F) {
+            let texels = texture
+                .data
+                .chunks_exact(4)
+                .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                .collect();
+            let image = image::Rgba32FImage::from_raw(texture.width, texture.height, texels)
+                .ok_or_else(|| {
+                    Error::Conversion(format!("Decoded {} has the wrong size.", input.display()))
+                })?;
+
+            texture.data = image::imageops::resize(&image, width, height, filter.filter_type())
+                .as_raw()
+                .iter()
+                .flat_map(|channel| channel.to_le_bytes())
+                .collect();
+        } else {
+            let image = image::load_from_memory(&texture.data)
+                .map_err(|err| Error::Image(input.to_path_buf(), err))?
+                .resize_exact(width, height, filter.filter_type());
+
+            texture.data = encoding
+                .encode(&image)
+                .map_err(|err| Error::Image(input.to_path_buf(), err))?;
+            texture.format = Some(encoding.format().to_string());
+        }
+
+        //Regions were given in source pixels.
+        let scale =
+            |value: u32, from: u32, to: u32| (value as u64 * to as u64 / from as u64) as u32;
+
+        for region in &mut texture.regions {
+            region.x = scale(region.x, texture.width, width);
+            region.y = scale(region.y, texture.height, height);
+            region.w = scale(region.w, texture.width, width);
+            region.h = scale(region.h, texture.height, height);
+        }
+
+        texture.width = width;
+        texture.height = height;
+
+        Ok(())
+    }
+
+    fn generate_mips(
+        input: &Path,
+        texture: &TextureData,
+        filter: ResizeFilter,
+    ) -> Result<Vec<TextureMip>, Error> {
+        let mut level = texture.clone();
+        level.regions.clear();
+
+        let mut mips = Vec::new();
+
+        //Each level is scaled from the one before, which is cheaper and looks the same.
+        while level.width > 1 || level.height > 1 {
+            let size = ((level.width / 2).max(1), (level.height / 2).max(1));
+            Self::resize_texture(input, &mut level, size, filter, ImageEncoding::default())?;
+
+            mips.push(TextureMip {
+                width: level.width,
+                height: level.height,
+                format: level.format.clone(),
+                data: level.data.clone(),
+            });
+        }
+
+        Ok(mips)
+    }
+
+    fn encode_variant(
+        input: &Path,
+        texture: &TextureData,
+        format: TextureFormat,
+    ) -> Result<TextureVariant, Error> {
+        let data = match texture.texture_format() {
+            Some(current) if current == format => texture.data.clone(),
+            Some(current) => {
+                return Err(Error::Conversion(format!(
+                    "{} holds {} texels, which can't be stored as {}.",
+                    input.display(),
+                    current.as_str(),
+                    format.as_str()
+                )))
+            }
+            None => {
+                let image = image::load_from_memory(&texture.data)
+                    .map_err(|err| Error::Image(input.to_path_buf(), err))?;
+
+                match format {
+                    TextureFormat::Rgba8 => image.to_rgba8().into_raw(),
+                    TextureFormat::Rgba32F => image
+                        .to_rgba32f()
+                        .into_raw()
+                        .iter()
+                        .flat_map(|value| value.to_le_bytes())
+                        .collect(),
+                    TextureFormat::Bc5 | TextureFormat::Bc7 | TextureFormat::Astc4x4 => {
+                        let mut variant = texture.clone();
+                        Self::compress_texture(input, &mut variant, format)?;
+                        variant.data
+                    }
+                }
+            }
+        };
+
+        Ok(TextureVariant { format, data })
+    }
+
+    #[cfg(feature = "bcn")]
+    fn compress_texture(
+        input: &Path,
+        texture: &mut TextureData,
+        format: TextureFormat,
+    ) -> Result<(), Error> {
+        if texture.texture_format() == Some(TextureFormat::Rgba32F) {
+            return Err(Error::Conversion(format!(
+                "{} holds float texels, which {} can't store.",
+                input.display(),
+                format.as_str()
+            )));
+        }
+
+        let image = image::load_from_memory(&texture.data)
+            .map_err(|err| Error::Image(input.to_path_buf(), err))?
+            .to_rgba8();
+
+        texture.data = bcn::compress(&image, format).ok_or_else(|| {
+            Error::Conversion(format!(
+                "{} is not a block compressed format.",
+                format.as_str()
+            ))
+        })?;
+        texture.format = Some(format.as_str().to_string());
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "bcn"))]
+    fn compress_texture(
+        input: &Path,
+        _texture: &mut TextureData,
+        format: TextureFormat,
+    ) -> Result<(), Error> {
+        Err(Error::Conversion(format!(
+            "Compressing {} to {} needs the bcn cargo feature.",
+            input.display(),
+            format.as_str()
+        )))
+    }
+
+    fn premultiply_alpha(
+        input: &Path,
+        texture: &mut TextureData,
+        encoding: ImageEncoding,
+    ) -> Result<(), Error> {
+        let mut image = image::load_from_memory(&texture.data)
+            .map_err(|err| Error::Image(input.to_path_buf(), err))?
+            .to_rgba8();
+
+        for pixel in image.pixels_mut() {
+            let alpha = pixel.0[3] as u16;
+
+            for channel in &mut pixel.0[..3] {
+                *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+            }
+        }
+
+        texture.data = encoding
+            .encode(&image::DynamicImage::ImageRgba8(image))
+            .map_err(|err| Error::Image(input.to_path_buf(), err))?;
+        texture.format = Some(encoding.format().to_string());
+        texture.alpha_mode = AlphaMode::Premultiplied;
+
+        Ok(())
+    }
+
+    fn is_animated(input: &Path) -> bool {
+        let reader = match image::io::Reader::open(input).and_then(|r| r.with_guessed_format()) {
+            Ok(reader) => reader,
+            Err(_) => return false,
+        };
+
+        match reader.format() {
+            Some(image::ImageFormat::Gif) => {
+                image::codecs::gif::GifDecoder::new(reader.into_inner()).is_ok_and(|decoder| {
+                    image::AnimationDecoder::into_frames(decoder)
+                        .take(2)
+                        .count()
+                        > 1
+                })
+            }
+            Some(image::ImageFormat::Png) => {
+                image::codecs::png::PngDecoder::new(reader.into_inner())
+                    .is_ok_and(|decoder| decoder.is_apng())
+            }
+            #[cfg(feature = "webp")]
+            Some(image::ImageFormat::WebP) => {
+                image::codecs::webp::WebPDecoder::new(reader.into_inner())
+                    .is_ok_and(|decoder| decoder.has_animation())
+            }
+            _ => false,
+        }
+    }
+
+    /// Pack six face images into a cubemap. Faces are expected in the order
+    /// +x, -x, +y, -y, +z, -z and are stored under those keys.
+    pub fn convert_cubemap<P: AsRef<Path>>(
+        &self,
+        output: P,
+        inputs: &[P],
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        self.convert_cubemap_with_progress(output, inputs, overwrite, |_, _| {})
+    }
+
+    pub fn convert_cubemap_with_progress<P: AsRef<Path>>(
+        &self,
+        output: P,
+        inputs: &[P],
+        overwrite: bool,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<ConvertReport, Error> {
+        if inputs.len() != CUBE_FACES.len() {
+            return Err(Error::Conversion(format!(
+                "A cubemap needs exactly {} faces (+x, -x, +y, -y, +z, -z), got {}.",
+                CUBE_FACES.len(),
+                inputs.len()
+            )));
+        }
+
+        self.convert_texture_array_with_progress(
+            output,
+            Some(&CUBE_FACES),
+            inputs,
+            overwrite,
+            progress,
+        )
+    }
+
+    /// Sample an equirectangular panorama into a cubemap with `face_size` pixel faces.
+    /// HDR and EXR panoramas keep their range as rgba32f faces, everything else becomes png.
+    pub fn convert_cubemap_from_equirect<P: AsRef<Path>>(
+        &self,
         output: P,
         input: P,
+        face_size: u32,
         overwrite: bool,
-    ) -> Result<(), String> {
-        let output = output.as_ref();
-        let input = input.as_ref();
+    ) -> Result<ConvertReport, Error> {
+        if face_size == 0 {
+            return Err(Error::Conversion(
+                "Cubemap faces need at least one pixel.".to_string(),
+            ));
+        }
 
-        let input = if let Some(Location::File(path)) = &self.location {
-            path.join(input)
-        } else {
-            input.to_path_buf()
-        };
+        let input = self.resolve(input);
 
-        let input = input.as_path();
+        let settings = face_size.to_string();
+        let fingerprint = self.fingerprint(std::slice::from_ref(&input), &settings);
+        if let Some(report) =
+            self.unchanged(output.as_ref(), std::slice::from_ref(&input), &fingerprint)
+        {
+            return Ok(report);
+        }
 
-        if input.exists() {
-            if let Ok(dimension) = image::image_dimensions(input) {
-                if let Ok(texture) = std::fs::read(input) {
-                    let texture = TextureData {
-                        width: dimension.0,
-                        height: dimension.1,
-                        format: input
-                            .extension()
-                            .map(|s| s.to_os_string().into_string().unwrap_or("".to_string())),
-                        data: texture,
-                    };
+        let dimension =
+            image::image_dimensions(&input).map_err(|err| Self::image_error(&input, err))?;
+        Self::check_image(&input, dimension)?;
 
-                    return self.write_texture(output, &texture, overwrite);
-                }
-                return Err(format!("Failed to read file: {}", input.display()));
+        let float = Self::float_format(&input);
+
+        let panorama = match float {
+            Some(format) => {
+                let data = std::fs::read(&input).map_err(|err| Error::Read(input.clone(), err))?;
+                Self::decode_float_image(&input, format, &data, dimension)?
             }
-            return Err(format!(
-                "Failed to read image dimensions or file: {}",
-                input.display()
-            ));
-        }
+            None => image::open(&input)
+                .map_err(|err| Self::image_error(&input, err))?
+                .to_rgba32f(),
+        };
+
+        let data = equirect::to_cube_faces(&panorama, face_size)
+            .into_iter()
+            .map(|face| {
+                if float.is_some() {
+                    return Ok(face
+                        .as_raw()
+                        .iter()
+                        .flat_map(|channel| channel.to_le_bytes())
+                        .collect());
+                }
+
+                let mut data = Vec::new();
+                image::DynamicImage::ImageRgba32F(face)
+                    .to_rgba8()
+                    .write_to(&mut Cursor::new(&mut data), image::ImageOutputFormat::Png)
+                    .map_err(|err| Error::Image(input.clone(), err))?;
+                Ok(data)
+            })
+            .collect::<Result<Vec<Vec<u8>>, Error>>()?;
+
+        let format = match float {
+            Some(_) => TextureFormat::Rgba32F.as_str(),
+            None => "png",
+        };
+
+        let cubemap = TextureArrayData {
+            size: face_size,
+            format: Some(format.to_string()),
+            keys: CUBE_FACES.iter().map(|face| face.to_string()).collect(),
+            data,
+        };
 
-        Err(format!("File {} does not exist.", input.display()))
+        self.write_input(
+            output,
+            &AssetInput::TextureArray(&cubemap),
+            std::slice::from_ref(&input),
+            fingerprint,
+            overwrite,
+        )
     }
 
-    pub fn convert_texture_array<P: AsRef<Path>, S: Into<String> + Clone>(
+    /// Stack equally sized slice images into a volume. Slices are decoded to rgba8.
+    pub fn convert_texture_3d<P: AsRef<Path>>(
         &self,
         output: P,
-        keys: Option<&[S]>,
         inputs: &[P],
         overwrite: bool,
-    ) -> Result<(), String> {
-        let output = output.as_ref();
+    ) -> Result<ConvertReport, Error> {
         let inputs = inputs
             .iter()
-            .map(|a| {
-                if let Some(Location::File(path)) = &self.location {
-                    path.join(a)
-                } else {
-                    a.as_ref().to_path_buf()
-                }
-            })
+            .map(|input| self.resolve(input))
             .collect::<Vec<PathBuf>>();
-        let keys = keys.map(|a| {
-            a.iter()
-                .map(|b| (*b).clone().into())
-                .collect::<Vec<String>>()
-        });
 
-        let mut textures = Vec::<Vec<u8>>::with_capacity(inputs.len());
+        let fingerprint = self.fingerprint(&inputs, "");
+        if let Some(report) = self.unchanged(output.as_ref(), &inputs, &fingerprint) {
+            return Ok(report);
+        }
+
+        let mut volume = Texture3DData {
+            width: 0,
+            height: 0,
+            depth: inputs.len() as u32,
+            format: Some(TextureFormat::Rgba8.as_str().to_string()),
+            data: Vec::new(),
+        };
+
+        for (i, input) in inputs.iter().enumerate() {
+            let slice = image::open(input)
+                .map_err(|err| Self::image_error(input, err))?
+                .to_rgba8();
+
+            if i == 0 {
+                volume.width = slice.width();
+                volume.height = slice.height();
+            } else if slice.dimensions() != (volume.width, volume.height) {
+                return Err(Error::Conversion(format!(
+                    "Slice {} is {}x{} but the volume is {}x{}.",
+                    input.display(),
+                    slice.width(),
+                    slice.height(),
+                    volume.width,
+                    volume.height
+                )));
+            }
+
+            volume.data.extend_from_slice(slice.as_raw());
+        }
+
+        self.write_input(
+            output,
+            &AssetInput::Texture3D(&volume),
+            &inputs,
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    pub fn convert_shader<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        optimize: bool,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let options = ShaderOptions {
+            optimize,
+            ..Default::default()
+        };
+
+        self.convert_shader_with(output, input, &options, overwrite)
+    }
+
+    pub fn convert_shader_with<P: AsRef<Path>>(
+        &self,
+        output: P,
+        input: P,
+        options: &ShaderOptions,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let sources = [self.resolve(&input)];
+
+        let fingerprint = self.fingerprint(&sources, &format!("{:?}", options));
+        if let Some(report) = self.unchanged(output.as_ref(), &sources, &fingerprint) {
+            return Ok(report);
+        }
+
+        let shader = self.compile_shader(&input, options)?;
+        self.write_input(
+            output,
+            &AssetInput::Shader(&shader),
+            &sources,
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    pub fn convert_shader_program<P: AsRef<Path>>(
+        &self,
+        output: P,
+        inputs: &[(ShaderStages, P)],
+        optimize: bool,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let sources = inputs
+            .iter()
+            .map(|(_, input)| self.resolve(input))
+            .collect::<Vec<PathBuf>>();
+
+        let stages = inputs.iter().map(|(stage, _)| *stage).collect::<Vec<_>>();
+        let settings = format!("{:?} {}", stages, optimize);
+
+        let fingerprint = self.fingerprint(&sources, &settings);
+        if let Some(report) = self.unchanged(output.as_ref(), &sources, &fingerprint) {
+            return Ok(report);
+        }
+
+        let mut program = ShaderProgramData {
+            stages: HashMap::new(),
+        };
 
-        let keys = keys.unwrap_or_else(|| {
+        let options = ShaderOptions {
+            optimize,
+            ..Default::default()
+        };
+
+        for (stage, input) in inputs {
+            if stage.bits().count_ones() != 1 {
+                return Err(Error::Conversion(format!(
+                    "Each program input needs exactly one stage. File: {}",
+                    input.as_ref().display()
+                )));
+            }
+
+            if program.stages.contains_key(stage) {
+                return Err(Error::Conversion(format!(
+                    "Stage {:?} was provided more than once. File: {}",
+                    stage,
+                    input.as_ref().display()
+                )));
+            }
+
+            let mut shader = self.compile_shader(input, &options)?;
+
+            if !shader.stages.contains(*stage) {
+                return Err(Error::Conversion(format!(
+                    "Shader {} has no {:?} entry point.",
+                    input.as_ref().display(),
+                    stage
+                )));
+            }
+
+            shader.stages = *stage;
+            program.stages.insert(*stage, shader);
+        }
+
+        self.write_input(
+            output,
+            &AssetInput::ShaderProgram(&program),
+            &sources,
+            fingerprint,
+            overwrite,
+        )
+    }
+
+    /// Like convert_shader_program, but finds the stage files itself. `base` is either a folder
+    /// or a base name, e.g. `material` for `material.vert.wgsl` and `material.frag.wgsl`. The
+    /// stage comes from the file name: `.vert.wgsl`, `.frag.wgsl` or `.comp.wgsl`.
+    pub fn convert_shader_program_from<P: AsRef<Path>>(
+        &self,
+        output: P,
+        base: P,
+        optimize: bool,
+        overwrite: bool,
+    ) -> Result<ConvertReport, Error> {
+        let base = base.as_ref();
+        let folder = self.resolve(base);
+
+        let mut inputs = if folder.is_dir() {
+            let entries =
+                std::fs::read_dir(&folder).map_err(|err| Error::Read(folder.clone(), err))?;
+
+            let mut inputs = Vec::new();
+            for entry in entries {
+                let file_name = entry.map_err(Error::Io)?.file_name();
+                //Only used to match the suffix, the path keeps the original name.
+                let name = file_name.to_string_lossy();
+
+                if let Some((_, stage)) = SHADER_STAGE_SUFFIXES
+                    .iter()
+                    .find(|(suffix, _)| name.ends_with(suffix))
+                {
+                    inputs.push((*stage, base.join(&file_name)));
+                }
+            }
             inputs
+        } else {
+            SHADER_STAGE_SUFFIXES
                 .iter()
-                .map(|a| {
-                    a.file_stem()
-                        .unwrap_or(std::ffi::OsStr::new("Unknown"))
-                        .to_string_lossy()
-                        .to_string()
+                .map(|(suffix, stage)| {
+                    let mut input = base.as_os_str().to_os_string();
+                    input.push(suffix);
+
+                    (*stage, PathBuf::from(input))
                 })
-                .collect()
-        });
+                .filter(|(_, input)| self.resolve(input).is_file())
+                .collect::<Vec<_>>()
+        };
 
-        let mut size = 0;
-        let mut format = None;
+        if inputs.is_empty() {
+            return Err(Error::Conversion(format!(
+                "Found no .vert.wgsl, .frag.wgsl or .comp.wgsl files for {}.",
+                base.display()
+            )));
+        }
 
-        for input in &inputs {
-            if input.exists() {
-                if let Ok(dimension) = image::image_dimensions(input) {
-                    if dimension.0 != dimension.1 {
-                        return Err(format!(
-                            "Cubemap textures need to be quadratic. File: {}, ",
-                            input.display()
-                        ));
-                    }
+        //Directory order is arbitrary, keep the settings of incremental builds stable.
+        inputs.sort_by_key(|(stage, input)| (stage.bits(), input.clone()));
 
-                    if size == 0 {
-                        size = dimension.0;
-                    } else if dimension.0 != size {
-                        return Err(format!(
-                            "All textures must have the same size. File: {}",
-                            input.display()
-                        ));
-                    }
+        self.convert_shader_program(output.as_ref().to_path_buf(), &inputs, optimize, overwrite)
+    }
 
-                    if format.is_none() {
-                        format = input
-                            .extension()
-                            .map(|s| s.to_os_string().into_string().unwrap_or("".to_string()));
-                    }
+    /// Convert every texture, shader (.wgsl or .glsl) and glTF below `src` into the same folders below
+    /// `dst`, named like output_name does. Other files are skipped. Returns each converted
+    /// file with its result, in path order.
+    pub fn convert_dir<P: AsRef<Path>>(
+        &self,
+        src: P,
+        dst: P,
+        overwrite: bool,
+    ) -> Vec<(PathBuf, Result<(), Error>)> {
+        let src = self.resolve(src);
+        let dst = dst.as_ref();
 
-                    if let Ok(texture) = std::fs::read(input) {
-                        textures.push(texture);
-                    } else {
-                        return Err(format!("Failed to read file: {}", input.display()));
+        let files = match utils::walk_files(&src) {
+            Ok(files) => files,
+            Err(err) => return vec![(src.clone(), Err(Error::Read(src, err)))],
+        };
+
+        let mut results = Vec::new();
+
+        for file in files {
+            let ext = file
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+
+            let kind = match ext.as_str() {
+                "wgsl" | "glsl" => AssetKind::Shader,
+                "gltf" | "glb" => AssetKind::Gltf,
+                _ if image::ImageFormat::from_extension(&ext).is_some() => AssetKind::Texture,
+                _ => {
+                    log::info!(
+                        "Skipping {}, it's no texture, shader or glTF.",
+                        file.display()
+                    );
+                    continue;
+                }
+            };
+
+            //Converted through the location again, so strip its folder.
+            let input = match &self.location {
+                Some(Location::File(base)) => file.strip_prefix(base).unwrap_or(&file),
+                _ => &file,
+            };
+
+            let folder = file
+                .parent()
+                .and_then(|parent| parent.strip_prefix(&src).ok())
+                .unwrap_or(Path::new(""));
+
+            let result = match self.output_name(&file) {
+                Some(name) => {
+                    let output = dst.join(folder).join(name);
+
+                    match kind {
+                        AssetKind::Shader => {
+                            self.convert_shader(output.as_path(), input, false, overwrite)
+                        }
+                        AssetKind::Gltf => self.convert_gltf(output.as_path(), input, overwrite),
+                        _ => self.convert_texture(output.as_path(), input, overwrite),
                     }
-                } else {
-                    return Err(format!(
-                        "Failed to read image dimensions or file: {}",
+                    .map(|_| ())
+                }
+                None => Err(Error::Conversion(format!(
+                    "Failed to infer output file name of {}.",
+                    file.display()
+                ))),
+            };
+
+            if let Err(err) = &result {
+                log::error!("Failed to convert {}. Error: {}", file.display(), err);
+            }
+
+            results.push((file, result));
+        }
+
+        results
+    }
+
+    /// Convert `inputs` into `output_dir` whenever they change, once they settled for
+    /// `debounce`. Shaders (.wgsl) go through convert_shader, everything else through
+    /// convert_texture. `on_convert` sees every result and stops watching by breaking.
+    /// Failed conversions, e.g. of a half written file, are logged and retried on the next change.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn watch<P: AsRef<Path>>(
+        &self,
+        inputs: &[P],
+        output_dir: P,
+        debounce: Duration,
+        mut on_convert: impl FnMut(&Path, &Result<ConvertReport, Error>) -> ControlFlow<()>,
+    ) -> Result<(), Error> {
+        let output_dir = output_dir.as_ref();
+        let inputs = inputs
+            .iter()
+            .map(|input| input.as_ref().to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        let resolved = inputs
+            .iter()
+            .map(|input| self.resolve(input))
+            .collect::<Vec<PathBuf>>();
+
+        watch::watch_files(&resolved, debounce, |changed| {
+            for (input, _) in inputs
+                .iter()
+                .zip(&resolved)
+                .filter(|(_, resolved)| changed.contains(resolved))
+            {
+                let result = self.convert_watched(output_dir, input);
+
+                match &result {
+                    Ok(report) => log::info!(
+                        "Regenerated {} from {}",
+                        report.output.display(),
                         input.display()
-                    ));
+                    ),
+                    Err(err) => log::error!(
+                        "Failed to regenerate from {}. Error: {}",
+                        input.display(),
+                        err
+                    ),
                 }
-            } else {
-                return Err(format!("File {} does not exist.", input.display()));
+
+                on_convert(input, &result)?;
+            }
+
+            ControlFlow::Continue(())
+        })
+    }
+
+    fn convert_watched(&self, output_dir: &Path, input: &Path) -> Result<ConvertReport, Error> {
+        let output = output_dir.join(self.output_name(input).ok_or_else(|| {
+            Error::Conversion(format!(
+                "Failed to infer output file name of {}.",
+                input.display()
+            ))
+        })?);
+
+        if input
+            .extension()
+            .is_some_and(|ext| ext == "wgsl" || ext == "glsl")
+        {
+            self.convert_shader(output.as_path(), input, false, true)
+        } else {
+            self.convert_texture(output.as_path(), input, true)
+        }
+    }
+
+    //Equal spellings of a path share one Guid and cache slot. Urls are left untouched.
+    fn normalize(&self, path: &str) -> String {
+        if matches!(self.location, Some(Location::Http(_))) || path.contains("://") {
+            path.to_string()
+        } else {
+            utils::normalize_path(path)
+        }
+    }
+
+    //References inside an asset are relative to the asset itself, not to the location.
+    fn resolve_uri(&self, path: &str, uri: &str) -> Result<String, Error> {
+        match &self.location {
+            Some(Location::Http(base)) => {
+                let url = utils::join_url(base, path)?;
+                url.join(uri).map(String::from).map_err(|err| {
+                    Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("Can't resolve {} against {}: {}", uri, url, err),
+                    ))
+                })
             }
+            _ => Ok(utils::join_relative(path, uri)),
         }
+    }
 
-        let textures = TextureArrayData {
-            size,
-            format,
-            keys,
-            data: textures,
+    //Size and modification time of the local file `path` is read from. None for remote files.
+    fn source_stamp(&self, path: &str) -> Option<(u64, u64)> {
+        let source = match &self.location {
+            Some(Location::File(base)) => base.join(path),
+            Some(Location::Archive(archive)) => archive.clone(),
+            None => PathBuf::from(path),
+            _ => return None,
         };
 
-        self.write_texture_array(output, &textures, overwrite)
+        let metadata = std::fs::metadata(source).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+
+        Some((metadata.len(), modified.as_nanos() as u64))
     }
 
-    pub fn convert_cubemap<P: AsRef<Path>>(
-        &self,
-        output: P,
-        inputs: &[P],
-        overwrite: bool,
-    ) -> Result<(), String> {
-        let keys = vec!["+x", "-x", "+y", "-y", "+z", "-z"];
-        self.convert_texture_array(output, Some(&keys), inputs, overwrite)
+    fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        if let Some(Location::File(base)) = &self.location {
+            base.join(path)
+        } else {
+            path.as_ref().to_path_buf()
+        }
     }
 
-    pub fn convert_shader<P: AsRef<Path>>(
+    fn compile_shader<P: AsRef<Path>>(
         &self,
-        output: P,
         input: P,
-        overwrite: bool,
-    ) -> Result<(), String> {
+        options: &ShaderOptions,
+    ) -> Result<ShaderData, Error> {
+        let optimize = options.optimize;
+        let defines = options.defines.clone();
         let input = input.as_ref();
 
         let input = if let Some(Location::File(path)) = &self.location {
@@ -575,61 +4630,139 @@ impl What {
 
         let input = input.as_path();
 
-        if let Ok(shader) = std::fs::read(input) {
-            let module = if input
-                .extension()
-                .unwrap_or(std::ffi::OsStr::new(""))
-                .to_string_lossy()
-                == "wgsl"
-            {
-                naga::front::wgsl::parse_str(&String::from_utf8(shader.clone()).unwrap()).unwrap()
-            } else {
-                todo!("Support glsl shaders.")
-            };
+        let shader = std::fs::read(input).map_err(|err| Error::Read(input.to_path_buf(), err))?;
 
-            let stages = if input
-                .extension()
-                .unwrap_or(std::ffi::OsStr::new(""))
-                .to_string_lossy()
-                == "wgsl"
-            {
-                Self::detect_wgsl_stages(shader)?
-            } else {
-                todo!("Support glsl shaders.")
-            };
+        let ext = input
+            .extension()
+            .unwrap_or(std::ffi::OsStr::new(""))
+            .to_string_lossy()
+            .to_lowercase();
 
-            let mut info = naga::valid::Validator::new(
-                naga::valid::ValidationFlags::all(),
-                naga::valid::Capabilities::all(),
-            );
+        let source = || {
+            std::str::from_utf8(&shader).map_err(|err| {
+                Error::Shader(input.to_path_buf(), format!("Not valid UTF-8. {}", err))
+            })
+        };
 
-            if let Ok(info) = info.validate(&module) {
-                let spirv = naga::back::spv::write_vec(
-                    &module,
-                    &info,
-                    &naga::back::spv::Options::default(),
-                    None,
-                )
-                .unwrap();
-
-                self.write_shader(
-                    output,
-                    &ShaderData {
-                        data: spirv,
-                        stages,
-                    },
-                    overwrite,
-                )
-            } else {
-                Err(format!("Failed to validate shader: {}", input.display()))
+        let (mut module, stages) = match ext.as_str() {
+            "wgsl" => {
+                let source = preprocess::preprocess(source()?, &options.defines)
+                    .map_err(|err| Error::Shader(input.to_path_buf(), err))?;
+
+                let module = naga::front::wgsl::parse_str(&source).map_err(|err| {
+                    Error::Shader(input.to_path_buf(), err.emit_to_string(&source))
+                })?;
+
+                (module, Self::detect_wgsl_stages(source.as_bytes())?)
+            }
+            "glsl" => {
+                let (stage, stages) = Self::glsl_stage(input).ok_or_else(|| {
+                    Error::Shader(
+                        input.to_path_buf(),
+                        "Name glsl shaders .vert.glsl, .frag.glsl or .comp.glsl to pick the stage."
+                            .to_string(),
+                    )
+                })?;
+
+                let mut glsl = naga::front::glsl::Options::from(stage);
+                glsl.defines.extend(options.defines.iter().cloned());
+
+                let module = naga::front::glsl::Frontend::default()
+                    .parse(&glsl, source()?)
+                    .map_err(|errors| {
+                        let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                        Error::Shader(input.to_path_buf(), errors.join("\n"))
+                    })?;
+
+                (module, stages)
+            }
+            _ => {
+                return Err(Error::Shader(
+                    input.to_path_buf(),
+                    "Only wgsl and glsl shaders are supported.".to_string(),
+                ))
             }
+        };
+
+        let mut validator = naga::valid::Validator::new(options.validation, options.capabilities);
+
+        let validation_error = |err: naga::WithSpan<naga::valid::ValidationError>| {
+            Error::Shader(input.to_path_buf(), utils::error_chain(&err))
+        };
+
+        let info = validator.validate(&module).map_err(validation_error)?;
+
+        let workgroup_size = module
+            .entry_points
+            .iter()
+            .find(|entry| entry.stage == naga::ShaderStage::Compute)
+            .map(|entry| entry.workgroup_size);
+
+        //Renderers need the size to dispatch, a compute shader without one is useless.
+        if stages.contains(ShaderStages::COMPUTE)
+            && !workgroup_size.is_some_and(|size| size.iter().all(|&axis| axis > 0))
+        {
+            return Err(Error::Shader(
+                input.to_path_buf(),
+                "Compute shaders need a non-zero @workgroup_size.".to_string(),
+            ));
+        }
+
+        let mut options = naga::back::spv::Options::default();
+
+        let info = if optimize {
+            //Drop everything the entry points don't reference and strip debug labels. Release
+            //builds write no debug labels anyway, so there it's only the compaction.
+            naga::compact::compact(&mut module);
+            options.flags.remove(naga::back::spv::WriterFlags::DEBUG);
+
+            validator.validate(&module).map_err(validation_error)?
         } else {
-            Err(format!("Failed to read file: {}", input.display()))
+            info
+        };
+
+        let spirv = naga::back::spv::write_vec(&module, &info, &options, None).map_err(|err| {
+            Error::Shader(
+                input.to_path_buf(),
+                format!("Could not write SPIR-V. Error: {}", err),
+            )
+        })?;
+
+        Ok(ShaderData {
+            data: spirv,
+            stages,
+            optimized: optimize,
+            defines,
+            reflection: Some(ShaderReflection::from_module(&module)),
+            workgroup_size,
+        })
+    }
+
+    fn read_spirv(bytes: &[u8]) -> Vec<u32> {
+        let mut read = Cursor::new(bytes);
+        let mut shader = Vec::<u32>::with_capacity(bytes.len() / 4);
+
+        while let Ok(value) = read.read_u32::<byteorder::LittleEndian>() {
+            shader.push(value);
+        }
+
+        shader
+    }
+
+    //Glsl has no stage attributes, so the stage comes from the file name, e.g. water.frag.glsl.
+    fn glsl_stage(input: &Path) -> Option<(naga::ShaderStage, ShaderStages)> {
+        let stem = Path::new(input.file_stem()?);
+
+        match stem.extension()?.to_str()? {
+            "vert" => Some((naga::ShaderStage::Vertex, ShaderStages::VERTEX)),
+            "frag" => Some((naga::ShaderStage::Fragment, ShaderStages::FRAGMENT)),
+            "comp" => Some((naga::ShaderStage::Compute, ShaderStages::COMPUTE)),
+            _ => None,
         }
     }
 
-    fn detect_wgsl_stages(data: Vec<u8>) -> Result<ShaderStages, String> {
-        if let Ok(data) = String::from_utf8(data) {
+    fn detect_wgsl_stages(data: &[u8]) -> Result<ShaderStages, Error> {
+        if let Ok(data) = std::str::from_utf8(data) {
             let mut stages = ShaderStages::empty();
 
             if data.contains("@vertex") {
@@ -646,7 +4779,50 @@ impl What {
 
             Ok(stages)
         } else {
-            Err("Could not determine shader stages. Did you provide an WGSL shader?".to_string())
+            Err(Error::Conversion(
+                "Could not determine shader stages. Did you provide an WGSL shader?".to_string(),
+            ))
         }
     }
 }
+
+#[cfg(all(test, feature = "parallel"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map_inputs_on_pool() {
+        let mut what = What::new(1e8 as usize, None);
+        what.set_threads(Some(3)).unwrap();
+
+        //Every item has to run on one of the three pool threads, not the global pool.
+        let seen = what
+            .map_inputs(&[0; 64], |_| {
+                Ok((rayon::current_num_threads(), rayon::current_thread_index()))
+            })
+            .unwrap();
+
+        assert_eq!(seen.len(), 64);
+        assert!(seen
+            .iter()
+            .all(|(threads, index)| *threads == 3 && index.is_some_and(|index| index < 3)));
+
+        //A single thread stays on the caller.
+        what.set_threads(Some(1)).unwrap();
+        let seen = what
+            .map_inputs(&[0; 4], |_| Ok(std::thread::current().id()))
+            .unwrap();
+        assert!(seen.iter().all(|id| *id == std::thread::current().id()));
+
+        //A failed item stops the rest instead of running all of them.
+        what.set_threads(Some(2)).unwrap();
+        let ran = std::sync::atomic::AtomicUsize::new(0);
+        let result = what.map_inputs(&[0; 10_000], |_| {
+            ran.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            Err::<(), _>(Error::Conversion(String::from("Failed.")))
+        });
+
+        assert!(matches!(result, Err(Error::Conversion(_))));
+        assert!(ran.load(std::sync::atomic::Ordering::Relaxed) < 10_000);
+    }
+}
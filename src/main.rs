@@ -1,21 +1,41 @@
-use std::{env, path::Path};
+use std::{
+    env,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use clap::{Args, Parser, Subcommand};
 use log::{Level, LevelFilter};
 use simplelog::{Color, ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
 
-use what::What;
+use what::{
+    AlphaMode, ConvertReport, Error, GltfOptions, ImageEncoding, PngCompression, ShaderOptions,
+    TextureOptions, What,
+};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Extension of asset files, used for inferred output names and when finding assets.
+    #[arg(long, global = true, default_value = what::DEFAULT_EXTENSION)]
+    ext: String,
+
+    /// Threads parallel conversions may use. Defaults to all cores.
+    #[arg(long, global = true)]
+    threads: Option<usize>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Convert(ConvertArgs),
+    TextureArray(TextureArrayArgs),
+    Verify(VerifyArgs),
+    Migrate(MigrateArgs),
+    Watch(WatchArgs),
 }
 
 #[derive(Args)]
@@ -27,6 +47,127 @@ struct ConvertArgs {
 
     #[arg(long, default_value_t = false)]
     overwrite: bool,
+
+    /// Convert every input on its own. The output is a folder then.
+    #[arg(long, default_value_t = false)]
+    batch: bool,
+
+    /// Convert six images into a cubemap instead of a texture array.
+    #[arg(long, default_value_t = false)]
+    cubemap: bool,
+
+    /// Pack the stage files of a folder or base name into one shader program.
+    #[arg(long, default_value_t = false)]
+    program: bool,
+
+    /// Drop unused items and debug labels from shaders.
+    #[arg(long, default_value_t = false)]
+    optimize: bool,
+
+    /// Capabilities a shader may use: webgl2 or desktop.
+    #[arg(long)]
+    caps: Option<String>,
+
+    /// Shader preprocessor define as NAME or NAME=VALUE. Can be repeated.
+    #[arg(long = "define")]
+    defines: Vec<String>,
+
+    #[arg(long)]
+    manifest: Option<String>,
+
+    #[arg(long)]
+    regions: Option<String>,
+
+    #[arg(long, default_value_t = false)]
+    premultiply: bool,
+
+    #[arg(long)]
+    max_size: Option<u32>,
+
+    #[arg(long, default_value_t = false)]
+    power_of_two: bool,
+
+    #[arg(long)]
+    compress: Option<String>,
+
+    /// Also store the texture in this format. Can be repeated.
+    #[arg(long = "variant")]
+    variants: Vec<String>,
+
+    #[arg(long, default_value_t = false)]
+    mipmaps: bool,
+
+    /// Store glTF buffers and images as separate entries.
+    #[arg(long, default_value_t = false)]
+    split_gltf: bool,
+
+    /// Re-encode the image as jpeg with this quality.
+    #[arg(long)]
+    jpeg_quality: Option<u8>,
+
+    /// Compression of re-encoded png images: fast, default or best.
+    #[arg(long)]
+    png_compression: Option<String>,
+
+    #[arg(long, default_value_t = false)]
+    strip_metadata: bool,
+
+    /// Keep the ICC profile when stripping metadata.
+    #[arg(long, default_value_t = false)]
+    keep_icc: bool,
+
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    #[arg(long, default_value_t = false)]
+    incremental: bool,
+
+    /// Read every output back and fail if it doesn't match.
+    #[arg(long, default_value_t = false)]
+    verify: bool,
+}
+
+#[derive(Args)]
+struct TextureArrayArgs {
+    input: Vec<String>,
+
+    #[arg(short, long)]
+    output: String,
+
+    /// Comma separated layer keys, one per input. Defaults to the file stems.
+    #[arg(long, value_delimiter = ',')]
+    keys: Option<Vec<String>>,
+
+    #[arg(long, default_value_t = false)]
+    overwrite: bool,
+
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    dir: String,
+
+    #[arg(long, default_value_t = false)]
+    deep: bool,
+}
+
+#[derive(Args)]
+struct MigrateArgs {
+    dir: String,
+}
+
+#[derive(Args)]
+struct WatchArgs {
+    input: Vec<String>,
+
+    #[arg(long)]
+    output_dir: String,
+
+    /// Wait this long after the last change before converting.
+    #[arg(long, default_value_t = 200)]
+    debounce_ms: u64,
 }
 
 fn main() {
@@ -51,74 +192,451 @@ fn main() {
         env::current_dir().unwrap().display()
     );
 
-    let what = What::new(1e8 as usize, None);
+    let mut what = What::new(1e8 as usize, None);
+    what.set_extension(&cli.ext);
+
+    if let Err(e) = what.set_threads(cli.threads) {
+        log::error!("{}", e);
+        std::process::exit(exit_code(&e));
+    }
 
     match &cli.command {
         Commands::Convert(args) => {
+            what.set_dry_run(args.dry_run);
+            what.set_incremental(args.incremental);
+            what.set_verify(args.verify);
+
             let inputs = args.input.iter().map(Path::new).collect::<Vec<&Path>>();
+            let mut summary = Summary::new();
 
-            match 1.cmp(&args.input.len()) {
-                std::cmp::Ordering::Less => {
+            if args.batch {
+                for input in &inputs {
                     let output = args
                         .output
-                        .clone()
-                        .expect("Cannot infer output file name of a cube map. Please provide one.");
-
-                    if let Err(e) =
-                        what.convert_cubemap(Path::new(&output), &inputs, args.overwrite)
-                    {
-                        log::error!("{}", e);
-                    } else {
-                        log::info!("Successfully created file {}", output);
-                    }
+                        .as_ref()
+                        .zip(what.output_name(input))
+                        .map(|(dir, name)| Path::new(dir).join(name));
+
+                    summary.add(convert(&what, args, &[input], output));
                 }
-                std::cmp::Ordering::Equal => {
-                    let output = if args.output.is_none() {
-                        let file_name_os = inputs[0]
-                            .file_name()
-                            .expect("Failed to infer output file name. Please provide one.");
-                        let file_name = file_name_os
-                            .to_os_string()
-                            .into_string()
-                            .expect("Failed to infer output file name. Please provide one.");
-
-                        let index = file_name.find('.').unwrap_or(usize::MAX);
-                        let mut new_file = file_name[0..index].to_string();
-                        new_file.push_str(".fur");
-                        new_file
-                    } else {
-                        args.output.clone().unwrap()
-                    };
-
-                    match inputs[0].extension() {
-                        Some(ext) => {
-                            if ext == "wgsl" {
-                                if let Err(e) = what.convert_shader(
-                                    Path::new(&output),
-                                    inputs[0],
-                                    args.overwrite,
-                                ) {
-                                    log::error!("{}", e);
-                                } else {
-                                    log::info!("Successfully created file {}", output);
-                                }
-                            } else if let Err(e) =
-                                what.convert_texture(Path::new(&output), inputs[0], args.overwrite)
-                            {
-                                log::error!("{}", e);
-                            } else {
-                                log::info!("Successfully created file {}", output);
-                            }
-                        }
-                        None => {
-                            log::error!("Cannot infer input file extension.");
-                        }
-                    }
+            } else {
+                let output = args.output.as_ref().map(PathBuf::from);
+                summary.add(convert(&what, args, &inputs, output));
+            }
+
+            if let Some(manifest) = &args.manifest {
+                if args.dry_run {
+                    log::info!("Dry run. Would write manifest {}", manifest);
+                } else if let Err(e) = what.write_manifest(manifest) {
+                    log::error!("{}", e);
+                } else {
+                    log::info!("Wrote manifest {}", manifest);
                 }
-                std::cmp::Ordering::Greater => {
-                    log::error!("Please provide an input file path.");
+            }
+
+            summary.log();
+            std::process::exit(summary.exit_code);
+        }
+        Commands::TextureArray(args) => std::process::exit(texture_array(&mut what, args)),
+        Commands::Verify(args) => std::process::exit(verify(&mut what, args)),
+        Commands::Migrate(args) => std::process::exit(migrate(&what, args)),
+        Commands::Watch(args) => {
+            let inputs = args.input.iter().map(Path::new).collect::<Vec<&Path>>();
+
+            log::info!("Watching {} files. Press Ctrl+C to stop.", inputs.len());
+
+            if let Err(e) = what.watch(
+                &inputs,
+                Path::new(&args.output_dir),
+                Duration::from_millis(args.debounce_ms),
+                |_, _| ControlFlow::Continue(()),
+            ) {
+                log::error!("{}", e);
+                std::process::exit(exit_code(&e));
+            }
+        }
+    }
+}
+
+enum InputKind {
+    Shader,
+    Gltf,
+    Image,
+}
+
+fn input_kind(input: &Path) -> InputKind {
+    let ext = input
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match ext.as_str() {
+        "wgsl" | "glsl" => InputKind::Shader,
+        "gltf" | "glb" => InputKind::Gltf,
+        _ => InputKind::Image,
+    }
+}
+
+//Shaders and glTFs are converted one at a time, several images become an array or cubemap.
+fn convert(
+    what: &What,
+    args: &ConvertArgs,
+    inputs: &[&Path],
+    output: Option<PathBuf>,
+) -> Result<ConvertReport, Error> {
+    if inputs.is_empty() {
+        return Err(Error::Conversion(
+            "Please provide an input file path.".to_string(),
+        ));
+    }
+
+    let output = match (output, inputs) {
+        (Some(output), _) => output,
+        (None, [input]) => what.output_name(input).ok_or_else(|| {
+            Error::Conversion("Failed to infer output file name. Please provide one.".to_string())
+        })?,
+        (None, _) => {
+            return Err(Error::Conversion(
+                "Cannot infer the output file name of several inputs. Please provide one."
+                    .to_string(),
+            ))
+        }
+    };
+
+    if args.cubemap {
+        return what.convert_cubemap_with_progress(
+            output.as_path(),
+            inputs,
+            args.overwrite,
+            |current, total| log::info!("Processed face {}/{}", current, total),
+        );
+    }
+
+    if args.program {
+        return match inputs {
+            [input] => what.convert_shader_program_from(
+                output.as_path(),
+                input,
+                args.optimize,
+                args.overwrite,
+            ),
+            _ => Err(Error::Conversion(
+                "A shader program is converted from one folder or base name.".to_string(),
+            )),
+        };
+    }
+
+    match (inputs, input_kind(inputs[0])) {
+        ([input], InputKind::Shader) => what.convert_shader_with(
+            output.as_path(),
+            input,
+            &read_shader_options(args).map_err(Error::Conversion)?,
+            args.overwrite,
+        ),
+        ([input], InputKind::Gltf) => what.convert_gltf_with(
+            output.as_path(),
+            input,
+            &GltfOptions {
+                split: args.split_gltf,
+            },
+            args.overwrite,
+        ),
+        ([input], InputKind::Image) => what.convert_texture_with(
+            output.as_path(),
+            input,
+            &read_texture_options(args).map_err(Error::Conversion)?,
+            args.overwrite,
+        ),
+        _ => {
+            if let Some(input) = inputs
+                .iter()
+                .find(|input| !matches!(input_kind(input), InputKind::Image))
+            {
+                return Err(Error::Conversion(format!(
+                    "{} can't be combined with other inputs. Only images are converted together.",
+                    input.display()
+                )));
+            }
+
+            what.convert_texture_array_with_progress::<_, String>(
+                output.as_path(),
+                None,
+                inputs,
+                args.overwrite,
+                |current, total| log::info!("Processed layer {}/{}", current, total),
+            )
+        }
+    }
+}
+
+//Process exit codes, see the table in the README.
+const EXIT_FAILURE: i32 = 1;
+const EXIT_NOT_FOUND: i32 = 2;
+const EXIT_ALREADY_EXISTS: i32 = 3;
+const EXIT_DECODE: i32 = 4;
+const EXIT_VALIDATION: i32 = 5;
+
+fn exit_code(err: &Error) -> i32 {
+    match err {
+        Error::Io(err) | Error::Read(_, err) if err.kind() == std::io::ErrorKind::NotFound => {
+            EXIT_NOT_FOUND
+        }
+        Error::AlreadyExists(_) => EXIT_ALREADY_EXISTS,
+        Error::Image(_, _)
+        | Error::GltfError(_)
+        | Error::JsonError(_)
+        | Error::UnsupportedVersion(_, _)
+        | Error::NotAFurFile(_)
+        | Error::Decryption(_) => EXIT_DECODE,
+        Error::Shader(_, _) | Error::GltfValidation(_) => EXIT_VALIDATION,
+        _ => EXIT_FAILURE,
+    }
+}
+
+fn texture_array(what: &mut What, args: &TextureArrayArgs) -> i32 {
+    if args.input.is_empty() {
+        log::error!("Please provide an input file path.");
+        return EXIT_FAILURE;
+    }
+
+    if let Some(keys) = &args.keys {
+        if keys.len() != args.input.len() {
+            log::error!(
+                "Got {} keys for {} inputs. Please provide one key per input.",
+                keys.len(),
+                args.input.len()
+            );
+            return EXIT_FAILURE;
+        }
+    }
+
+    what.set_dry_run(args.dry_run);
+
+    let inputs = args.input.iter().map(Path::new).collect::<Vec<&Path>>();
+    let result = what.convert_texture_array_with_progress(
+        Path::new(&args.output),
+        args.keys.as_deref(),
+        &inputs,
+        args.overwrite,
+        |current, total| log::info!("Processed layer {}/{}", current, total),
+    );
+
+    let code = result.as_ref().err().map_or(0, exit_code);
+    log_report(result);
+
+    code
+}
+
+//Exit code of the first file that fails.
+fn verify(what: &mut What, args: &VerifyArgs) -> i32 {
+    let results = match what.verify_dir(&args.dir, args.deep) {
+        Ok(results) => results,
+        Err(e) => {
+            log::error!("Failed to read {}. Error: {}", args.dir, e);
+            return exit_code(&e);
+        }
+    };
+
+    let mut failed = 0;
+    let mut code = 0;
+
+    for (path, result) in &results {
+        match result {
+            Ok(()) => log::info!("PASS {}", path.display()),
+            Err(e) => {
+                failed += 1;
+                log::error!("FAIL {}: {}", path.display(), e);
+
+                if code == 0 {
+                    code = exit_code(e);
+                }
+            }
+        }
+    }
+
+    log::info!("{}/{} files passed.", results.len() - failed, results.len());
+
+    code
+}
+
+//Exit code of the first file that fails.
+fn migrate(what: &What, args: &MigrateArgs) -> i32 {
+    let results = match what.migrate_dir(&args.dir) {
+        Ok(results) => results,
+        Err(e) => {
+            log::error!("Failed to read {}. Error: {}", args.dir, e);
+            return exit_code(&e);
+        }
+    };
+
+    let mut migrated = 0;
+    let mut failed = 0;
+    let mut code = 0;
+
+    for (path, result) in &results {
+        match result {
+            Ok(true) => {
+                migrated += 1;
+                log::info!("MIGRATED {}", path.display());
+            }
+            Ok(false) => log::info!("CURRENT {}", path.display()),
+            Err(e) => {
+                failed += 1;
+                log::error!("FAIL {}: {}", path.display(), e);
+
+                if code == 0 {
+                    code = exit_code(e);
                 }
             }
         }
     }
+
+    log::info!(
+        "{} files migrated, {} current, {} failed.",
+        migrated,
+        results.len() - migrated - failed,
+        failed
+    );
+
+    code
+}
+
+//Totals of a convert run, logged once it's done.
+struct Summary {
+    converted: usize,
+    failed: usize,
+    bytes_written: u64,
+    started: Instant,
+    //Of the first failure, 0 if everything worked.
+    exit_code: i32,
+}
+
+impl Summary {
+    fn new() -> Summary {
+        Summary {
+            converted: 0,
+            failed: 0,
+            bytes_written: 0,
+            started: Instant::now(),
+            exit_code: 0,
+        }
+    }
+
+    fn add(&mut self, result: Result<ConvertReport, Error>) {
+        match &result {
+            Ok(report) => {
+                self.converted += 1;
+                self.bytes_written += report.bytes_written;
+            }
+            Err(err) => {
+                self.failed += 1;
+
+                if self.exit_code == 0 {
+                    self.exit_code = exit_code(err);
+                }
+            }
+        }
+
+        log_report(result);
+    }
+
+    fn log(&self) {
+        log::info!(
+            "Converted {} assets, {} failed, {:.1} MB written, {:.1}s",
+            self.converted,
+            self.failed,
+            self.bytes_written as f64 / 1e6,
+            self.started.elapsed().as_secs_f64()
+        );
+    }
+}
+
+fn log_report(result: Result<ConvertReport, Error>) {
+    match result {
+        Ok(report) if report.up_to_date => {
+            log::info!("{} is up to date", report.output.display())
+        }
+        Ok(report) => log::info!(
+            "Successfully created file {} ({} bytes)",
+            report.output.display(),
+            report.bytes_written
+        ),
+        Err(e) => log::error!("{}", e),
+    }
+}
+
+fn read_shader_options(args: &ConvertArgs) -> Result<ShaderOptions, String> {
+    let mut options = ShaderOptions {
+        optimize: args.optimize,
+        ..Default::default()
+    };
+
+    //Desktop allows everything naga can validate, WebGL2 none of the optional features.
+    options.capabilities = match args.caps.as_deref() {
+        None | Some("desktop") => naga::valid::Capabilities::all(),
+        Some("webgl2") => naga::valid::Capabilities::empty(),
+        Some(caps) => {
+            return Err(format!(
+                "Unknown capability preset {}. Expected webgl2 or desktop.",
+                caps
+            ))
+        }
+    };
+
+    options.defines = args
+        .defines
+        .iter()
+        .map(|define| match define.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => (define.clone(), "1".to_string()),
+        })
+        .collect();
+
+    Ok(options)
+}
+
+fn read_texture_options(args: &ConvertArgs) -> Result<TextureOptions, String> {
+    let mut options = TextureOptions::default();
+
+    if let Some(regions) = &args.regions {
+        let json = std::fs::read(regions)
+            .map_err(|e| format!("Failed to read regions {}. Error: {}", regions, e))?;
+        options.regions = serde_json::from_slice(&json)
+            .map_err(|e| format!("Failed to parse regions {}. Error: {}", regions, e))?;
+    }
+
+    if args.premultiply {
+        options.alpha_mode = AlphaMode::Premultiplied;
+    }
+
+    options.max_size = args.max_size;
+    options.power_of_two = args.power_of_two;
+    options.mipmaps = args.mipmaps;
+
+    if let Some(compression) = &args.png_compression {
+        options.encoding = ImageEncoding::Png(match compression.as_str() {
+            "fast" => PngCompression::Fast,
+            "default" => PngCompression::Default,
+            "best" => PngCompression::Best,
+            _ => return Err(format!("Unknown png compression {}.", compression)),
+        });
+    }
+
+    if let Some(quality) = args.jpeg_quality {
+        options.encoding = ImageEncoding::Jpeg(quality);
+    }
+    options.strip_metadata = args.strip_metadata;
+    options.keep_icc = args.keep_icc;
+
+    if let Some(format) = &args.compress {
+        options.compress_to = Some(format.parse().map_err(|e: Error| e.to_string())?);
+    }
+
+    for format in &args.variants {
+        options
+            .variants
+            .push(format.parse().map_err(|e: Error| e.to_string())?);
+    }
+
+    Ok(options)
 }
@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub output: PathBuf,
+    pub kind: String,
+    pub sources: Vec<PathBuf>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub format: Option<String>,
+    pub bytes: u64,
+}
+
+/// Record of every asset written by a What instance. Serialized as the sidecar manifest.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub assets: Vec<ManifestEntry>,
+}
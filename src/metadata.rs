@@ -0,0 +1,79 @@
+/// Remove metadata such as EXIF, XMP or text comments from a png or jpeg file without
+/// re-encoding it. With `keep_icc` the ICC profile and the other color chunks stay.
+/// Returns None for other formats and for files that don't parse.
+pub fn strip(data: &[u8], keep_icc: bool) -> Option<Vec<u8>> {
+    match image::guess_format(data).ok()? {
+        image::ImageFormat::Png => strip_png(data, keep_icc),
+        image::ImageFormat::Jpeg => strip_jpeg(data, keep_icc),
+        _ => None,
+    }
+}
+
+fn strip_png(data: &[u8], keep_icc: bool) -> Option<Vec<u8>> {
+    const SIGNATURE: usize = 8;
+
+    let mut output = data.get(..SIGNATURE)?.to_vec();
+    let mut rest = &data[SIGNATURE..];
+
+    while !rest.is_empty() {
+        //Length, type, data and crc.
+        let length = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let chunk = rest.get(..12 + length)?;
+        let kind = &chunk[4..8];
+
+        //Critical chunks have an uppercase first letter. tRNS holds transparency, so it's
+        //part of the pixels and not metadata.
+        let keep = kind[0].is_ascii_uppercase()
+            || kind == b"tRNS"
+            || (keep_icc && matches!(kind, b"iCCP" | b"sRGB" | b"gAMA" | b"cHRM"));
+
+        if keep {
+            output.extend_from_slice(chunk);
+        }
+
+        rest = &rest[chunk.len()..];
+    }
+
+    Some(output)
+}
+
+fn strip_jpeg(data: &[u8], keep_icc: bool) -> Option<Vec<u8>> {
+    const SOS: u8 = 0xDA;
+
+    let mut output = data.get(..2)?.to_vec();
+    let mut rest = &data[2..];
+
+    loop {
+        let marker = *rest.get(1)?;
+
+        //Fill bytes before a marker.
+        if rest[0] == 0xFF && marker == 0xFF {
+            rest = &rest[1..];
+            continue;
+        }
+
+        //Entropy coded data follows the scan header, copy everything from here on.
+        if rest[0] != 0xFF || marker == SOS {
+            output.extend_from_slice(rest);
+            return Some(output);
+        }
+
+        let length = u16::from_be_bytes(rest.get(2..4)?.try_into().ok()?) as usize;
+        let segment = rest.get(..2 + length)?;
+
+        //APP0 is JFIF and APP14 decides the color transform, both are needed for decoding.
+        //APP2 holds the ICC profile, everything else in APPn and COM is metadata.
+        let keep = match marker {
+            0xE0 | 0xEE => true,
+            0xE2 => keep_icc && segment.get(4..)?.starts_with(b"ICC_PROFILE\0"),
+            0xE1..=0xEF | 0xFE => false,
+            _ => true,
+        };
+
+        if keep {
+            output.extend_from_slice(segment);
+        }
+
+        rest = &rest[segment.len()..];
+    }
+}
@@ -0,0 +1,30 @@
+use image::{ImageBuffer, Luma, Rgba, RgbaImage};
+
+/// Derive a tangent space normal map from a height map using the Sobel operator.
+/// Normals are packed into RGB as `n * 0.5 + 0.5`, alpha is opaque.
+pub fn from_height(height: &ImageBuffer<Luma<u16>, Vec<u16>>, strength: f32) -> RgbaImage {
+    let (width, height_px) = height.dimensions();
+
+    //Clamp to the edge, so borders don't produce a slope.
+    let sample = |x: i64, y: i64| -> f32 {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height_px as i64 - 1) as u32;
+        height.get_pixel(x, y).0[0] as f32 / u16::MAX as f32
+    };
+
+    RgbaImage::from_fn(width, height_px, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+
+        let dx = (sample(x + 1, y - 1) + 2.0 * sample(x + 1, y) + sample(x + 1, y + 1))
+            - (sample(x - 1, y - 1) + 2.0 * sample(x - 1, y) + sample(x - 1, y + 1));
+        let dy = (sample(x - 1, y + 1) + 2.0 * sample(x, y + 1) + sample(x + 1, y + 1))
+            - (sample(x - 1, y - 1) + 2.0 * sample(x, y - 1) + sample(x + 1, y - 1));
+
+        let normal = [-dx * strength, -dy * strength, 1.0];
+        let length = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+
+        let pack = |n: f32| ((n / length * 0.5 + 0.5) * 255.0).round() as u8;
+
+        Rgba([pack(normal[0]), pack(normal[1]), pack(normal[2]), u8::MAX])
+    })
+}
@@ -0,0 +1,73 @@
+/// Apply `defines` to a shader source before parsing.
+///
+/// Supports `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` on their own lines.
+/// Defined names outside of directives are replaced by their value as whole words.
+/// Dropped lines stay as empty lines, so parse errors keep pointing at the right line.
+pub fn preprocess(source: &str, defines: &[(String, String)]) -> Result<String, String> {
+    //One entry per open #ifdef: whether its current branch is kept.
+    let mut branches = Vec::<bool>::new();
+    let mut output = String::with_capacity(source.len());
+
+    for (number, line) in source.lines().enumerate() {
+        let directive = line.trim_start();
+        let active = branches.iter().all(|&kept| kept);
+
+        if let Some(name) = directive.strip_prefix("#ifdef") {
+            branches.push(is_defined(defines, name.trim()));
+        } else if let Some(name) = directive.strip_prefix("#ifndef") {
+            branches.push(!is_defined(defines, name.trim()));
+        } else if directive.starts_with("#else") {
+            match branches.last_mut() {
+                Some(kept) => *kept = !*kept,
+                None => return Err(format!("#else without #ifdef on line {}.", number + 1)),
+            }
+        } else if directive.starts_with("#endif") {
+            if branches.pop().is_none() {
+                return Err(format!("#endif without #ifdef on line {}.", number + 1));
+            }
+        } else if active {
+            output.push_str(&substitute(line, defines));
+        }
+
+        output.push('\n');
+    }
+
+    if !branches.is_empty() {
+        return Err(format!("{} #ifdef without #endif.", branches.len()));
+    }
+
+    Ok(output)
+}
+
+fn is_defined(defines: &[(String, String)], name: &str) -> bool {
+    defines.iter().any(|(define, _)| define == name)
+}
+
+fn substitute(line: &str, defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return line.to_string();
+    }
+
+    let mut output = String::with_capacity(line.len());
+    let mut word = String::new();
+
+    let flush = |word: &mut String, output: &mut String| {
+        match defines.iter().find(|(name, _)| name == word) {
+            Some((_, value)) => output.push_str(value),
+            None => output.push_str(word),
+        }
+        word.clear();
+    };
+
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut output);
+            output.push(c);
+        }
+    }
+
+    flush(&mut word, &mut output);
+    output
+}
@@ -0,0 +1,88 @@
+use crate::{error::Error, HeaderType, What};
+
+/// Reads single layers of a texture array on demand instead of loading the whole file.
+pub struct TextureArrayReader<'a> {
+    what: &'a What,
+    path: String,
+    payload: u64,
+    size: u32,
+    format: Option<String>,
+    //Key, offset into the payload and length. No length means up to the end of the file.
+    layers: Vec<(String, u64, Option<u64>)>,
+}
+
+impl<'a> TextureArrayReader<'a> {
+    pub(crate) fn open(what: &'a What, path: &str) -> Result<Self, Error> {
+        let (meta, payload) = what.read_header_sized(&what.location, path)?;
+
+        if meta.encryption.is_some() {
+            return Err(Error::Decryption(format!(
+                "{} is encrypted and can't be read layer by layer. Use load_asset instead.",
+                path
+            )));
+        }
+
+        let array = match meta.ctype {
+            HeaderType::TextureArray(array) => array,
+            _ => return Err(Error::Unknown(format!("{} is not a texture array.", path))),
+        };
+
+        let layers = array
+            .data
+            .iter()
+            .map(|entry| {
+                //Older files don't store lengths, their layers end where the next one begins.
+                let length = entry.length.or_else(|| {
+                    array
+                        .data
+                        .iter()
+                        .map(|other| other.offset)
+                        .filter(|&offset| offset > entry.offset)
+                        .min()
+                        .map(|end| end - entry.offset)
+                });
+
+                (entry.key.clone(), entry.offset, length)
+            })
+            .collect();
+
+        Ok(TextureArrayReader {
+            what,
+            path: path.to_string(),
+            payload,
+            size: array.size,
+            format: array.format,
+            layers,
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn format(&self) -> Option<&str> {
+        self.format.as_deref()
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        self.layers.iter().map(|(key, _, _)| key.as_str()).collect()
+    }
+
+    /// Read the layer stored under `key`.
+    pub fn layer(&self, key: &str) -> Result<Vec<u8>, Error> {
+        let (_, offset, length) = self
+            .layers
+            .iter()
+            .find(|(layer, _, _)| layer == key)
+            .ok_or_else(|| {
+                Error::Unknown(format!("Texture array {} has no layer {}.", self.path, key))
+            })?;
+
+        self.what.backend.read_range(
+            &self.what.location,
+            &self.path,
+            self.payload + offset,
+            *length,
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ShaderStages;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum BindingKind {
+    UniformBuffer,
+    StorageBuffer { read_only: bool },
+    Texture,
+    StorageTexture,
+    Sampler,
+    Other,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ShaderBinding {
+    pub group: u32,
+    pub binding: u32,
+    pub name: Option<String>,
+    pub kind: BindingKind,
+    /// Size of the bound buffer in bytes. None for textures and samplers.
+    pub size: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ShaderIo {
+    pub name: Option<String>,
+    pub location: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ShaderEntryPoint {
+    pub name: String,
+    pub stage: ShaderStages,
+    pub inputs: Vec<ShaderIo>,
+    pub outputs: Vec<ShaderIo>,
+    /// Only present for compute entry points.
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct ShaderReflection {
+    pub bindings: Vec<ShaderBinding>,
+    pub push_constant_size: Option<u32>,
+    pub entry_points: Vec<ShaderEntryPoint>,
+}
+
+impl ShaderReflection {
+    /// Reflect a validated naga module.
+    pub(crate) fn from_module(module: &naga::Module) -> ShaderReflection {
+        let mut layouter = naga::proc::Layouter::default();
+        let layouter = layouter.update(module.to_ctx()).ok().map(|_| layouter);
+        let size_of = |ty| layouter.as_ref().map(|layouter| layouter[ty].size);
+
+        let mut reflection = ShaderReflection::default();
+
+        for (_, global) in module.global_variables.iter() {
+            if global.space == naga::AddressSpace::PushConstant {
+                reflection.push_constant_size = size_of(global.ty);
+                continue;
+            }
+
+            if let Some(binding) = &global.binding {
+                let kind = match global.space {
+                    naga::AddressSpace::Uniform => BindingKind::UniformBuffer,
+                    naga::AddressSpace::Storage { access } => BindingKind::StorageBuffer {
+                        read_only: !access.contains(naga::StorageAccess::STORE),
+                    },
+                    naga::AddressSpace::Handle => Self::handle_kind(module, global.ty),
+                    _ => BindingKind::Other,
+                };
+
+                let size = match kind {
+                    BindingKind::UniformBuffer | BindingKind::StorageBuffer { .. } => {
+                        size_of(global.ty)
+                    }
+                    _ => None,
+                };
+
+                reflection.bindings.push(ShaderBinding {
+                    group: binding.group,
+                    binding: binding.binding,
+                    name: global.name.clone(),
+                    kind,
+                    size,
+                });
+            }
+        }
+
+        reflection
+            .bindings
+            .sort_by_key(|binding| (binding.group, binding.binding));
+
+        for entry in &module.entry_points {
+            let mut inputs = Vec::new();
+            for argument in &entry.function.arguments {
+                Self::collect_io(
+                    module,
+                    &argument.name,
+                    argument.ty,
+                    &argument.binding,
+                    &mut inputs,
+                );
+            }
+
+            let mut outputs = Vec::new();
+            if let Some(result) = &entry.function.result {
+                Self::collect_io(module, &None, result.ty, &result.binding, &mut outputs);
+            }
+
+            let (stage, workgroup_size) = match entry.stage {
+                naga::ShaderStage::Vertex => (ShaderStages::VERTEX, None),
+                naga::ShaderStage::Fragment => (ShaderStages::FRAGMENT, None),
+                naga::ShaderStage::Compute => (ShaderStages::COMPUTE, Some(entry.workgroup_size)),
+            };
+
+            reflection.entry_points.push(ShaderEntryPoint {
+                name: entry.name.clone(),
+                stage,
+                inputs,
+                outputs,
+                workgroup_size,
+            });
+        }
+
+        reflection
+    }
+
+    fn handle_kind(module: &naga::Module, ty: naga::Handle<naga::Type>) -> BindingKind {
+        match &module.types[ty].inner {
+            naga::TypeInner::Image {
+                class: naga::ImageClass::Storage { .. },
+                ..
+            } => BindingKind::StorageTexture,
+            naga::TypeInner::Image { .. } => BindingKind::Texture,
+            naga::TypeInner::Sampler { .. } => BindingKind::Sampler,
+            naga::TypeInner::BindingArray { base, .. } => Self::handle_kind(module, *base),
+            _ => BindingKind::Other,
+        }
+    }
+
+    fn collect_io(
+        module: &naga::Module,
+        name: &Option<String>,
+        ty: naga::Handle<naga::Type>,
+        binding: &Option<naga::Binding>,
+        io: &mut Vec<ShaderIo>,
+    ) {
+        match binding {
+            Some(naga::Binding::Location { location, .. }) => io.push(ShaderIo {
+                name: name.clone(),
+                location: *location,
+            }),
+            Some(naga::Binding::BuiltIn(_)) => {}
+            None => {
+                //Structs carry the bindings on their members.
+                if let naga::TypeInner::Struct { members, .. } = &module.types[ty].inner {
+                    for member in members {
+                        Self::collect_io(module, &member.name, member.ty, &member.binding, io);
+                    }
+                }
+            }
+        }
+    }
+}
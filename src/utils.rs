@@ -1,8 +1,13 @@
-use std::collections::HashSet;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+use crate::{error::Error, lfu::ItemSize};
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub struct Guid {
     id: u32,
 }
@@ -13,6 +18,12 @@ impl Guid {
     }
 }
 
+impl ItemSize for Guid {
+    fn size(&self) -> usize {
+        std::mem::size_of::<Guid>()
+    }
+}
+
 pub struct GuidGenerator {
     used: HashSet<u32>,
 }
@@ -32,4 +43,226 @@ impl GuidGenerator {
         self.used.insert(id);
         Guid::new(id)
     }
+
+    /// Mark `guid` as taken, e.g. when it comes from a saved cache. False if it already was.
+    pub fn reserve(&mut self, guid: Guid) -> bool {
+        self.used.insert(guid.id)
+    }
+
+    /// Make `guid` available to generate again.
+    pub fn release(&mut self, guid: Guid) {
+        self.used.remove(&guid.id);
+    }
+}
+
+/// Stable FNV-1a hash over the contents of `sources`, `settings` and the crate version, as hex.
+pub fn fingerprint(sources: &[PathBuf], settings: &str) -> std::io::Result<String> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+
+    for source in sources {
+        feed(&std::fs::read(source)?);
+        //Separator, so moving bytes between sources changes the hash.
+        feed(&[0xff]);
+    }
+
+    feed(settings.as_bytes());
+    feed(env!("CARGO_PKG_VERSION").as_bytes());
+
+    Ok(format!("{:016x}", hash))
+}
+
+/// Lexically resolve `.` and `..` and unify separators, without touching the filesystem.
+pub fn normalize_path(path: &str) -> String {
+    let absolute = path.starts_with('/') || path.starts_with('\\');
+    let mut parts = Vec::<&str>::new();
+
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => match parts.last() {
+                Some(&last) if last != ".." => {
+                    parts.pop();
+                }
+                _ if absolute => {}
+                _ => parts.push(part),
+            },
+            part => parts.push(part),
+        }
+    }
+
+    let path = parts.join("/");
+
+    if absolute {
+        format!("/{}", path)
+    } else {
+        path
+    }
+}
+
+/// Resolve `uri` against the directory of the file at `path`, like a link inside that file.
+pub fn join_relative(path: &str, uri: &str) -> String {
+    if uri.contains("://") || uri.starts_with('/') {
+        return uri.to_string();
+    }
+
+    match path.rfind(['/', '\\']) {
+        Some(end) => normalize_path(&format!("{}/{}", &path[..end], uri)),
+        None => normalize_path(uri),
+    }
+}
+
+/// Join `path` onto the `base` url. The base always names a folder, absolute urls are kept as is.
+pub fn join_url(base: &str, path: &str) -> Result<url::Url, Error> {
+    let invalid = |err: url::ParseError| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Can't resolve {} against {}: {}", path, base, err),
+        ))
+    };
+
+    let base = if base.ends_with('/') {
+        url::Url::parse(base)
+    } else {
+        url::Url::parse(&format!("{}/", base))
+    }
+    .map_err(invalid)?;
+
+    base.join(path).map_err(invalid)
+}
+
+/// Decode the payload of a `data:[<media type>];base64,<data>` URI.
+pub fn decode_data_uri(uri: &str) -> Result<Vec<u8>, Error> {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let invalid = |message: String| {
+        Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            message,
+        ))
+    };
+
+    let (_, data) = uri
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .ok_or_else(|| invalid("Only base64 data URIs are supported.".to_string()))?;
+
+    STANDARD
+        .decode(data)
+        .map_err(|err| invalid(format!("Invalid base64 in data URI. {}", err)))
+}
+
+/// Render an error followed by every error that caused it.
+pub fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    message
+}
+
+/// `part` of `path` as text, e.g. its extension. Fails instead of substituting characters
+/// if it isn't valid UTF-8.
+pub fn path_text<'a>(path: &Path, part: &'a std::ffi::OsStr) -> Result<&'a str, Error> {
+    part.to_str()
+        .ok_or_else(|| Error::InvalidPath(path.to_path_buf()))
+}
+
+/// Every file below `dir` with the given extension, sorted.
+pub fn find_files(dir: &Path, extension: &str) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = walk_files(dir)?;
+    files.retain(|path| path.extension().is_some_and(|ext| ext == extension));
+
+    Ok(files)
+}
+
+/// Every file below `dir`, sorted.
+pub fn walk_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            files.extend(walk_files(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Serde adapter storing bytes as a base64 string. Use with `#[serde(with = "...")]`.
+pub mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(D::Error::custom)
+    }
+}
+
+/// Like base64_bytes, for a list of buffers.
+pub mod base64_layers {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(layers: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(layers.iter().map(|layer| STANDARD.encode(layer)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .into_iter()
+            .map(|layer| STANDARD.decode(layer).map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Like base64_bytes, for SPIR-V words stored little endian.
+pub mod base64_words {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(words: &[u32], serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes = words
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<u8>>();
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u32>, D::Error> {
+        let bytes = STANDARD
+            .decode(String::deserialize(deserializer)?)
+            .map_err(D::Error::custom)?;
+
+        if bytes.len() % 4 != 0 {
+            return Err(D::Error::custom("SPIR-V length is not a multiple of 4."));
+        }
+
+        Ok(bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect())
+    }
 }
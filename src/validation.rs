@@ -0,0 +1,97 @@
+/// Semantic checks the glTF importer doesn't do. Returns a human readable warning per issue.
+pub fn validate_gltf(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let mut broken_views = Vec::new();
+    let mut broken_accessors = Vec::new();
+
+    for view in document.views() {
+        let buffer_len = buffers
+            .get(view.buffer().index())
+            .map_or(0, |buffer| buffer.len());
+
+        if view.offset() + view.length() > buffer_len {
+            warnings.push(format!(
+                "Buffer view {} (offset {}, length {}) exceeds buffer {} of length {}.",
+                view.index(),
+                view.offset(),
+                view.length(),
+                view.buffer().index(),
+                buffer_len
+            ));
+            broken_views.push(view.index());
+        }
+    }
+
+    for accessor in document.accessors() {
+        if let Some(view) = accessor.view() {
+            if broken_views.contains(&view.index()) {
+                broken_accessors.push(accessor.index());
+                continue;
+            }
+
+            let stride = view.stride().unwrap_or(accessor.size());
+            let end = match accessor.count() {
+                0 => accessor.offset(),
+                count => accessor.offset() + (count - 1) * stride + accessor.size(),
+            };
+
+            if end > view.length() {
+                warnings.push(format!(
+                    "Accessor {} reads {} bytes but buffer view {} is only {} bytes long.",
+                    accessor.index(),
+                    end,
+                    view.index(),
+                    view.length()
+                ));
+                broken_accessors.push(accessor.index());
+            }
+        }
+    }
+
+    for mesh in document.meshes() {
+        for primitive in mesh.primitives() {
+            let name = format!("Mesh {} primitive {}", mesh.index(), primitive.index());
+
+            let vertices = match primitive.get(&gltf::Semantic::Positions) {
+                Some(positions) => positions.count(),
+                None => {
+                    warnings.push(format!("{} has no POSITION attribute.", name));
+                    continue;
+                }
+            };
+
+            for (semantic, accessor) in primitive.attributes() {
+                if accessor.count() != vertices {
+                    warnings.push(format!(
+                        "{} attribute {:?} has {} elements, expected {}.",
+                        name,
+                        semantic,
+                        accessor.count(),
+                        vertices
+                    ));
+                }
+            }
+
+            let indices = match primitive.indices() {
+                Some(indices) if !broken_accessors.contains(&indices.index()) => indices,
+                _ => continue,
+            };
+
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|a| &a.0[..]));
+
+            if let Some(read) = reader.read_indices() {
+                if let Some(index) = read.into_u32().find(|&index| index as usize >= vertices) {
+                    warnings.push(format!(
+                        "{} (accessor {}) references vertex {} but only has {} vertices.",
+                        name,
+                        indices.index(),
+                        index,
+                        vertices
+                    ));
+                }
+            }
+        }
+    }
+
+    warnings
+}
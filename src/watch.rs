@@ -0,0 +1,88 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::ControlFlow,
+    path::PathBuf,
+    sync::mpsc,
+    time::Duration,
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use crate::error::Error;
+
+/// Call `on_change` with the files of `paths` that changed, once they stayed untouched for
+/// `debounce`. Runs until `on_change` breaks.
+///
+/// The folders of the files are watched instead of the files, so editors that save by
+/// replacing the file are picked up as well.
+pub fn watch_files(
+    paths: &[PathBuf],
+    debounce: Duration,
+    mut on_change: impl FnMut(&[PathBuf]) -> ControlFlow<()>,
+) -> Result<(), Error> {
+    let notify_error = |err: notify::Error| Error::Io(std::io::Error::other(err.to_string()));
+
+    //Events carry absolute paths, map them back to the paths we were given.
+    let mut watched = HashMap::new();
+
+    for path in paths {
+        let absolute = path
+            .canonicalize()
+            .map_err(|err| Error::Read(path.clone(), err))?;
+        watched.insert(absolute, path.clone());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(notify_error)?;
+
+    let folders = watched
+        .keys()
+        .filter_map(|path| path.parent())
+        .collect::<BTreeSet<_>>();
+
+    for folder in folders {
+        watcher
+            .watch(folder, RecursiveMode::NonRecursive)
+            .map_err(notify_error)?;
+    }
+
+    let mut changed = BTreeSet::new();
+
+    loop {
+        //Wait as long as needed for the first change, then until things settle down.
+        let event = if changed.is_empty() {
+            match receiver.recv() {
+                Ok(event) => event,
+                Err(_) => return Ok(()),
+            }
+        } else {
+            match receiver.recv_timeout(debounce) {
+                Ok(event) => event,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let paths = std::mem::take(&mut changed).into_iter().collect::<Vec<_>>();
+
+                    if on_change(&paths).is_break() {
+                        return Ok(());
+                    }
+
+                    continue;
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        };
+
+        match event {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                changed.extend(
+                    event
+                        .paths
+                        .iter()
+                        .filter_map(|path| watched.get(path))
+                        .cloned(),
+                );
+            }
+            Ok(_) => {}
+            Err(err) => log::warn!("Watching files failed. Error: {}", err),
+        }
+    }
+}
@@ -1,12 +1,29 @@
-use std::path::PathBuf;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, Once,
+    },
+    time::Duration,
+};
 
-use what::{Asset, ShaderStages, What};
+use what::{
+    AlphaMode, Asset, AssetKind, Backend, BindingKind, Bytes, ColorSpace, DefaultBackend, Error,
+    EvictionPolicy, ExtraFiles, FilterMode, Footer, GltfOptions, GltfValidation, ImageEncoding,
+    Manifest, PngCompression, Priority, ResizeFilter, RetryPolicy, SamplerInfo, ShaderOptions,
+    ShaderStages, Texture3DData, TextureArrayData, TextureFormat, TextureOptions, TextureRegion,
+    What, WrapMode,
+};
 
 #[test]
 fn test_read_file_no_base() {
     let mut what = What::new(1e8 as usize, None);
 
-    let actual = what.load_file("tests/assets/error.png", 0).unwrap();
+    let actual = what
+        .load_file("tests/assets/error.png", Priority::Normal)
+        .unwrap();
     let expected = include_bytes!("assets/error.png");
     assert_eq!(expected, actual.as_slice());
 }
@@ -18,7 +35,7 @@ fn test_read_file_with_base() {
         Some(what::Location::File(PathBuf::from("tests/assets"))),
     );
 
-    let actual = what.load_file("error.png", 0).unwrap();
+    let actual = what.load_file("error.png", Priority::Normal).unwrap();
     let expected = include_bytes!("assets/error.png");
     assert_eq!(expected, actual.as_slice());
 }
@@ -30,7 +47,7 @@ fn test_load_asset() {
         Some(what::Location::File(PathBuf::from("tests/assets"))),
     );
 
-    let actual = what.load_asset("error.fur", 0).unwrap();
+    let actual = what.load_asset("error.fur", Priority::Normal, &[]).unwrap();
     let expected = include_bytes!("assets/error.png");
 
     if let Asset::Texture(data) = actual {
@@ -52,7 +69,9 @@ fn test_convert_asset() {
     what.convert_texture("error_gen.fur", "error.png", true)
         .unwrap();
 
-    let actual = what.load_asset("error_gen.fur", 0).unwrap();
+    let actual = what
+        .load_asset("error_gen.fur", Priority::Normal, &[])
+        .unwrap();
     let expected = include_bytes!("assets/error.png");
 
     if let Asset::Texture(data) = actual {
@@ -85,7 +104,9 @@ fn test_convert_cubemap() {
     )
     .unwrap();
 
-    let actual = what.load_asset("cubemap_gen.fur", 0).unwrap();
+    let actual = what
+        .load_asset("cubemap_gen.fur", Priority::Normal, &[])
+        .unwrap();
 
     let expected = include_bytes!("assets/error.png");
 
@@ -108,10 +129,12 @@ fn test_convert_shader() {
         Some(what::Location::File(PathBuf::from("tests/assets"))),
     );
 
-    what.convert_shader("shader_gen.fur", "shader.wgsl", true)
+    what.convert_shader("shader_gen.fur", "shader.wgsl", false, true)
         .unwrap();
 
-    let actual = what.load_asset("shader_gen.fur", 0).unwrap();
+    let actual = what
+        .load_asset("shader_gen.fur", Priority::Normal, &[])
+        .unwrap();
 
     let actual = if let Asset::Shader(data) = actual {
         data
@@ -135,3 +158,4350 @@ fn test_convert_shader() {
 
     assert_eq!(actual.stages, ShaderStages::VERTEX | ShaderStages::FRAGMENT);
 }
+
+#[test]
+fn test_convert_shader_optimized() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_shader("shader_plain_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+    what.convert_shader("shader_opt_gen.fur", "shader.wgsl", true, true)
+        .unwrap();
+
+    let mut modules = Vec::new();
+    let mut reflections = Vec::new();
+
+    for path in ["shader_plain_gen.fur", "shader_opt_gen.fur"] {
+        let actual =
+            if let Asset::Shader(data) = what.load_asset(path, Priority::Normal, &[]).unwrap() {
+                data
+            } else {
+                panic!("Expected shader.");
+            };
+
+        assert_eq!(actual.optimized, path == "shader_opt_gen.fur");
+        assert_eq!(actual.stages, ShaderStages::VERTEX | ShaderStages::FRAGMENT);
+
+        reflections.push(actual.reflection.clone());
+
+        let module = naga::front::spv::Frontend::new(
+            actual.data.into_iter(),
+            &naga::front::spv::Options::default(),
+        )
+        .parse()
+        .unwrap();
+
+        naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .unwrap();
+
+        modules.push(module);
+    }
+
+    let entry_points = |module: &naga::Module| {
+        module
+            .entry_points
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.stage))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(entry_points(&modules[0]), entry_points(&modules[1]));
+    assert_eq!(
+        modules[0].global_variables.len(),
+        modules[1].global_variables.len()
+    );
+    assert_eq!(reflections[0], reflections[1]);
+}
+
+#[test]
+fn test_shader_reflection() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_shader("shader_reflect_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+
+    let actual = what
+        .load_asset("shader_reflect_gen.fur", Priority::Normal, &[])
+        .unwrap();
+
+    let actual = if let Asset::Shader(data) = actual {
+        data
+    } else {
+        panic!("Expected shader.");
+    };
+
+    let reflection = actual.reflection.expect("Expected reflection data.");
+
+    let camera = reflection
+        .bindings
+        .iter()
+        .find(|binding| binding.name.as_deref() == Some("camera"))
+        .expect("Expected the camera uniform to be reported.");
+
+    assert_eq!(camera.group, 1);
+    assert_eq!(camera.binding, 0);
+    assert_eq!(camera.kind, BindingKind::UniformBuffer);
+    assert_eq!(camera.size, Some(64));
+
+    assert_eq!(reflection.bindings.len(), 3);
+    assert_eq!(reflection.push_constant_size, None);
+
+    let vertex = reflection
+        .entry_points
+        .iter()
+        .find(|entry| entry.name == "vs_main")
+        .unwrap();
+    assert_eq!(vertex.stage, ShaderStages::VERTEX);
+    assert_eq!(vertex.inputs.len(), 2);
+    assert_eq!(vertex.outputs.len(), 1);
+    assert_eq!(vertex.workgroup_size, None);
+}
+
+#[test]
+fn test_compute_workgroup_size() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/compute_gen.wgsl",
+        r#"
+@group(0) @binding(0) var<storage, read_write> values: array<f32>;
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    values[id.x] = values[id.x] * 2.0;
+}
+"#,
+    )
+    .unwrap();
+
+    what.convert_shader("compute_gen.fur", "compute_gen.wgsl", false, true)
+        .unwrap();
+
+    let actual = what
+        .load_asset("compute_gen.fur", Priority::Normal, &[])
+        .unwrap();
+
+    let actual = if let Asset::Shader(data) = actual {
+        data
+    } else {
+        panic!("Expected shader.");
+    };
+
+    assert_eq!(actual.stages, ShaderStages::COMPUTE);
+    assert_eq!(actual.workgroup_size, Some([8, 8, 1]));
+
+    //Other stages don't have a workgroup size.
+    what.convert_shader("compute_other_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+
+    match what
+        .load_asset("compute_other_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Shader(data) => assert_eq!(data.workgroup_size, None),
+        _ => panic!("Expected shader."),
+    }
+}
+
+#[test]
+fn test_convert_shader_program() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_shader_program(
+        "program_gen.fur",
+        &[
+            (ShaderStages::VERTEX, "program.vert.wgsl"),
+            (ShaderStages::FRAGMENT, "program.frag.wgsl"),
+        ],
+        false,
+        true,
+    )
+    .unwrap();
+
+    let actual = what
+        .load_asset("program_gen.fur", Priority::Normal, &[])
+        .unwrap();
+
+    let actual = if let Asset::ShaderProgram(data) = actual {
+        data
+    } else {
+        panic!("Expected shader program.");
+    };
+
+    assert_eq!(actual.stages.len(), 2);
+
+    for (stage, entry_point) in [
+        (ShaderStages::VERTEX, "vs_main"),
+        (ShaderStages::FRAGMENT, "fs_main"),
+    ] {
+        let shader = actual.stages.get(&stage).unwrap();
+        assert_eq!(shader.stages, stage);
+
+        let module = naga::front::spv::Frontend::new(
+            shader.data.clone().into_iter(),
+            &naga::front::spv::Options::default(),
+        )
+        .parse()
+        .unwrap();
+
+        assert_eq!(module.entry_points.len(), 1);
+        assert_eq!(module.entry_points[0].name, entry_point);
+    }
+}
+
+//Rewrite the header of the .fur file at `path` with `edit`, keeping its payload.
+fn edit_header(path: &str, edit: impl FnOnce(&mut serde_json::Value)) {
+    let data = std::fs::read(path).unwrap();
+    let size = u64::from_le_bytes(data[..8].try_into().unwrap()) as usize;
+    let mut header = serde_json::from_slice::<serde_json::Value>(&data[8..8 + size]).unwrap();
+
+    edit(&mut header);
+
+    let header = serde_json::to_vec(&header).unwrap();
+    let data = [
+        &(header.len() as u64).to_le_bytes(),
+        &header[..],
+        &data[8 + size..],
+    ]
+    .concat();
+    std::fs::write(path, data).unwrap();
+}
+
+fn is_out_of_range<T>(result: Result<T, Error>) -> bool {
+    matches!(result, Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
+#[test]
+fn test_corrupt_shader_offsets() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let stages = [
+        (ShaderStages::VERTEX, "program.vert.wgsl"),
+        (ShaderStages::FRAGMENT, "program.frag.wgsl"),
+    ];
+
+    //Past the end of the payload.
+    what.convert_shader_program("program_far_gen.fur", &stages, false, true)
+        .unwrap();
+    edit_header("tests/assets/program_far_gen.fur", |header| {
+        header["ctype"]["ShaderProgram"]["shaders"][1]["offset"] = 1_000_000.into();
+    });
+    assert!(is_out_of_range(what.load_asset(
+        "program_far_gen.fur",
+        Priority::Normal,
+        &[]
+    )));
+
+    //Out of order, so the first stage would end before it starts.
+    what.convert_shader_program("program_order_gen.fur", &stages, false, true)
+        .unwrap();
+    edit_header("tests/assets/program_order_gen.fur", |header| {
+        let shaders = &mut header["ctype"]["ShaderProgram"]["shaders"];
+        let second = shaders[1]["offset"].clone();
+        shaders[0]["offset"] = (second.as_u64().unwrap() + 4).into();
+    });
+    assert!(is_out_of_range(what.load_asset(
+        "program_order_gen.fur",
+        Priority::Normal,
+        &[]
+    )));
+
+    what.convert_shader("shader_far_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+    edit_header("tests/assets/shader_far_gen.fur", |header| {
+        header["ctype"]["Shader"]["offset"] = 1_000_000.into();
+    });
+    assert!(is_out_of_range(what.load_asset(
+        "shader_far_gen.fur",
+        Priority::Normal,
+        &[]
+    )));
+}
+
+#[test]
+fn test_convert_shader_program_from() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let _ = std::fs::remove_dir_all("tests/assets/program_dir_gen");
+    std::fs::create_dir_all("tests/assets/program_dir_gen").unwrap();
+    for stage in ["vert", "frag"] {
+        std::fs::copy(
+            format!("tests/assets/program.{}.wgsl", stage),
+            format!("tests/assets/program_dir_gen/material.{}.wgsl", stage),
+        )
+        .unwrap();
+    }
+
+    //By base name and by folder.
+    what.convert_shader_program_from("program_name_gen.fur", "program", false, true)
+        .unwrap();
+    what.convert_shader_program_from("program_dir_gen.fur", "program_dir_gen", false, true)
+        .unwrap();
+
+    for path in ["program_name_gen.fur", "program_dir_gen.fur"] {
+        let program = match what.load_asset(path, Priority::Normal, &[]).unwrap() {
+            Asset::ShaderProgram(program) => program,
+            _ => panic!("Expected shader program."),
+        };
+
+        assert_eq!(program.stages.len(), 2);
+
+        for (stage, entry_point) in [
+            (ShaderStages::VERTEX, "vs_main"),
+            (ShaderStages::FRAGMENT, "fs_main"),
+        ] {
+            let reflection = program.stages[&stage].reflection.as_ref().unwrap();
+            assert!(reflection
+                .entry_points
+                .iter()
+                .any(|entry| entry.name == entry_point && entry.stage == stage));
+        }
+    }
+
+    assert!(matches!(
+        what.convert_shader_program_from("program_none_gen.fur", "missing", false, true),
+        Err(Error::Conversion(_))
+    ));
+}
+
+#[test]
+fn test_gltf_images_to_texture_array() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_gltf("textures_gen.fur", "textures.gltf", true)
+        .unwrap();
+
+    let (document, images) = match what
+        .load_asset("textures_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Gltf(document, _, images, _) => (document, images),
+        _ => panic!("Expected glTF."),
+    };
+
+    let textures = TextureArrayData::from_gltf_images(&document, &images).unwrap();
+
+    assert_eq!(textures.keys, vec!["first", "second"]);
+    assert_eq!(textures.size, 512);
+
+    what.write_texture_array("gltf_textures_gen.fur", &textures, true)
+        .unwrap();
+
+    let actual = what
+        .load_asset("gltf_textures_gen.fur", Priority::Normal, &[])
+        .unwrap();
+
+    if let Asset::TextureArray(data) = actual {
+        assert_eq!(data.size, 512);
+        assert_eq!(data.keys, vec!["first", "second"]);
+        assert_eq!(data.format.as_deref(), Some("png"));
+
+        for layer in data.data {
+            let layer = image::load_from_memory(&layer).unwrap().to_rgba8();
+            assert_eq!(layer.as_raw(), &images[0].pixels);
+        }
+    } else {
+        panic!("Expected texture array.");
+    }
+}
+
+#[test]
+fn test_gltf_validation() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_gltf("broken_gen.fur", "broken.gltf", true)
+        .unwrap();
+
+    match what
+        .load_asset("broken_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Gltf(_, _, _, warnings) => {
+            assert_eq!(warnings.len(), 1);
+            assert!(warnings[0].contains("Accessor 0"));
+        }
+        _ => panic!("Expected glTF."),
+    }
+
+    what.set_gltf_validation(GltfValidation::Strict);
+
+    match what.load_asset("broken_gen.fur", Priority::Normal, &[]) {
+        Err(Error::GltfValidation(warnings)) => assert_eq!(warnings.len(), 1),
+        Err(err) => panic!("Expected validation error, got {:?}", err),
+        Ok(_) => panic!("Expected validation error."),
+    }
+}
+
+#[test]
+fn test_convert_cubemap_progress() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let mut calls = Vec::new();
+
+    what.convert_cubemap_with_progress(
+        "cubemap_progress_gen.fur",
+        &[
+            "error.png",
+            "error.png",
+            "error.png",
+            "error.png",
+            "error.png",
+            "error.png",
+        ],
+        true,
+        |current, total| calls.push((current, total)),
+    )
+    .unwrap();
+
+    assert_eq!(calls, (1..=6).map(|i| (i, 6)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_warm_progress() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let mut calls = Vec::new();
+
+    what.warm(
+        &["error.png", "shader.wgsl"],
+        Priority::Normal,
+        |current, total| calls.push((current, total)),
+    )
+    .unwrap();
+
+    assert_eq!(calls, vec![(1, 2), (2, 2)]);
+}
+
+#[test]
+fn test_convert_texture_array_many_layers() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let inputs = vec!["error.png"; 16];
+    let keys = (0..16).map(|i| i.to_string()).collect::<Vec<String>>();
+
+    what.convert_texture_array("array_gen.fur", Some(&keys), &inputs, true)
+        .unwrap();
+
+    //Build the same array by hand, one layer after another.
+    let expected = TextureArrayData {
+        size: 512,
+        format: Some("png".to_string()),
+        keys: keys.clone(),
+        data: vec![include_bytes!("assets/error.png").to_vec(); 16],
+    };
+
+    what.write_texture_array("array_expected_gen.fur", &expected, true)
+        .unwrap();
+
+    assert_eq!(
+        std::fs::read("tests/assets/array_gen.fur").unwrap(),
+        std::fs::read("tests/assets/array_expected_gen.fur").unwrap()
+    );
+
+    if let Asset::TextureArray(data) = what
+        .load_asset("array_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.keys, keys);
+        assert_eq!(data.data.len(), 16);
+    } else {
+        panic!("Expected texture array.");
+    }
+}
+
+#[test]
+fn test_convert_texture_array_parallel() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let inputs = (0..12)
+        .map(|i| {
+            let name = format!("parallel_layer_{}_gen.png", i);
+            image::RgbaImage::from_pixel(8, 8, image::Rgba([i * 20, 0, 0, 255]))
+                .save(format!("tests/assets/{}", name))
+                .unwrap();
+            PathBuf::from(name)
+        })
+        .collect::<Vec<PathBuf>>();
+
+    what.set_threads(Some(1)).unwrap();
+    what.convert_texture_array::<_, String>(
+        PathBuf::from("parallel_one_gen.fur"),
+        None,
+        &inputs,
+        true,
+    )
+    .unwrap();
+
+    what.set_threads(Some(4)).unwrap();
+    what.convert_texture_array::<_, String>(
+        PathBuf::from("parallel_many_gen.fur"),
+        None,
+        &inputs,
+        true,
+    )
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read("tests/assets/parallel_one_gen.fur").unwrap(),
+        std::fs::read("tests/assets/parallel_many_gen.fur").unwrap()
+    );
+
+    //Layer 3 has the wrong size and layer 8 is missing. The first one in input order is
+    //reported, no matter which thread gets there first.
+    image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]))
+        .save("tests/assets/parallel_small_gen.png")
+        .unwrap();
+
+    let mut broken = inputs.clone();
+    broken[3] = PathBuf::from("parallel_small_gen.png");
+    broken[8] = PathBuf::from("parallel_missing.png");
+
+    for _ in 0..4 {
+        let output = PathBuf::from("parallel_bad_gen.fur");
+
+        match what.convert_texture_array::<_, String>(output, None, &broken, true) {
+            Err(Error::Conversion(err)) => assert!(err.contains("parallel_small_gen"), "{}", err),
+            result => panic!("Expected the size error, got {:?}", result.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn test_load_large_file_mmap() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let expected = (0..8 * 1024 * 1024)
+        .map(|i: u32| (i % 251) as u8)
+        .collect::<Vec<u8>>();
+    std::fs::write("tests/assets/large_gen.bin", &expected).unwrap();
+
+    what.set_mmap_threshold(Some(1024 * 1024));
+
+    let actual = what.load_bytes("large_gen.bin", Priority::Normal).unwrap();
+    assert!(actual.is_mapped());
+    assert_eq!(&actual[..], expected.as_slice());
+
+    //Small files are still read normally.
+    let small = what.load_bytes("error.png", Priority::Normal).unwrap();
+    assert!(!small.is_mapped());
+    assert_eq!(&small[..], include_bytes!("assets/error.png"));
+}
+
+#[test]
+fn test_encrypted_asset() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.set_encryption_key(Some([7u8; 32]));
+    what.convert_texture("error_encrypted_gen.fur", "error.png", true)
+        .unwrap();
+
+    let raw = std::fs::read("tests/assets/error_encrypted_gen.fur").unwrap();
+    let expected = include_bytes!("assets/error.png");
+    assert!(!raw.windows(64).any(|window| window == &expected[..64]));
+
+    if let Asset::Texture(data) = what
+        .load_asset("error_encrypted_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(expected, data.data.as_slice());
+        assert_eq!(data.width, 512);
+    } else {
+        panic!("Expected texture.");
+    }
+
+    let mut other = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+    other.set_encryption_key(Some([8u8; 32]));
+
+    match other.load_asset("error_encrypted_gen.fur", Priority::Normal, &[]) {
+        Err(Error::Decryption(_)) => {}
+        Err(err) => panic!("Expected decryption error, got {:?}", err),
+        Ok(_) => panic!("Expected decryption error."),
+    }
+}
+
+#[test]
+fn test_load_from_archive() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_texture("archive_texture_gen.fur", "error.png", true)
+        .unwrap();
+    what.convert_shader("archive_shader_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+
+    let file = std::fs::File::create("tests/assets/bundle_gen.zip").unwrap();
+    let mut zip = zip::ZipWriter::new(file);
+
+    for (entry, path) in [
+        ("textures/error.fur", "tests/assets/archive_texture_gen.fur"),
+        ("shader.fur", "tests/assets/archive_shader_gen.fur"),
+    ] {
+        zip.start_file(entry, zip::write::FileOptions::default())
+            .unwrap();
+        std::io::Write::write_all(&mut zip, &std::fs::read(path).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::Archive(PathBuf::from(
+            "tests/assets/bundle_gen.zip",
+        ))),
+    );
+
+    if let Asset::Texture(data) = what
+        .load_asset("textures/error.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(include_bytes!("assets/error.png"), data.data.as_slice());
+    } else {
+        panic!("Expected texture.");
+    }
+
+    assert!(matches!(
+        what.load_asset("shader.fur", Priority::Normal, &[])
+            .unwrap(),
+        Asset::Shader(_)
+    ));
+
+    assert!(what.load_file("missing.fur", Priority::Normal).is_err());
+}
+
+#[test]
+fn test_write_manifest() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_texture("manifest_texture_gen.fur", "error.png", true)
+        .unwrap();
+    what.write_manifest("tests/assets/manifest_gen.json")
+        .unwrap();
+
+    let manifest: Manifest =
+        serde_json::from_slice(&std::fs::read("tests/assets/manifest_gen.json").unwrap()).unwrap();
+
+    assert_eq!(manifest.assets.len(), 1);
+
+    let entry = &manifest.assets[0];
+    assert_eq!(
+        entry.output,
+        PathBuf::from("tests/assets/manifest_texture_gen.fur")
+    );
+    assert_eq!(entry.kind, "texture");
+    assert_eq!(entry.sources, vec![PathBuf::from("tests/assets/error.png")]);
+    assert_eq!(entry.width, Some(512));
+    assert_eq!(entry.height, Some(512));
+    assert_eq!(entry.format.as_deref(), Some("png"));
+    assert_eq!(
+        entry.bytes,
+        std::fs::metadata("tests/assets/manifest_texture_gen.fur")
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn test_convert_default_image_formats() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+        4,
+        4,
+        image::Rgba([10, 20, 30, 255]),
+    ));
+
+    //Formats that come with image's default features, so they must stay enabled.
+    for ext in ["ppm", "qoi", "ff"] {
+        //Each encoder only takes its own pixel layout.
+        let path = format!("tests/assets/formats_gen.{}", ext);
+        match ext {
+            "ppm" => image.to_rgb8().save(path),
+            "ff" => image.to_rgba16().save(path),
+            _ => image.save(path),
+        }
+        .unwrap();
+
+        let output = format!("formats_{}_gen.fur", ext);
+        what.convert_texture(output.as_str(), &format!("formats_gen.{}", ext), true)
+            .unwrap_or_else(|err| panic!("Failed to convert {}: {:?}", ext, err));
+    }
+}
+
+#[cfg(feature = "webp")]
+#[test]
+fn test_convert_webp_texture() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_texture("webp_texture_gen.fur", "texture.webp", true)
+        .unwrap();
+
+    let actual = what
+        .load_asset("webp_texture_gen.fur", Priority::Normal, &[])
+        .unwrap();
+    let expected = include_bytes!("assets/texture.webp");
+
+    if let Asset::Texture(data) = actual {
+        assert_eq!(expected, data.data.as_slice());
+        assert_eq!(data.width, 16);
+        assert_eq!(data.height, 8);
+        assert_eq!(data.format.as_deref(), Some("webp"));
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_convert_report() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let report = what
+        .convert_texture("report_gen.fur", "error.png", true)
+        .unwrap();
+
+    assert_eq!(report.output, PathBuf::from("tests/assets/report_gen.fur"));
+    assert_eq!(
+        report.bytes_written,
+        std::fs::metadata("tests/assets/report_gen.fur")
+            .unwrap()
+            .len()
+    );
+}
+
+#[test]
+fn test_write_create_new() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_texture("create_new_gen.fur", "error.png", true)
+        .unwrap();
+    let expected = std::fs::read("tests/assets/create_new_gen.fur").unwrap();
+
+    match what.convert_texture("create_new_gen.fur", "error.png", false) {
+        Err(Error::AlreadyExists(path)) => {
+            assert_eq!(path, PathBuf::from("tests/assets/create_new_gen.fur"))
+        }
+        Err(err) => panic!("Expected already exists error, got {:?}", err),
+        Ok(_) => panic!("Expected already exists error."),
+    }
+
+    assert_eq!(
+        std::fs::read("tests/assets/create_new_gen.fur").unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_write_no_partial_file() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    //A directory in place of the output makes the final rename fail.
+    let _ = std::fs::remove_file("tests/assets/atomic_gen.fur");
+    std::fs::create_dir_all("tests/assets/atomic_gen.fur").unwrap();
+
+    let result = what.convert_texture("atomic_gen.fur", "error.png", true);
+    std::fs::remove_dir("tests/assets/atomic_gen.fur").unwrap();
+
+    assert!(result.is_err());
+    assert!(
+        !std::fs::read_dir("tests/assets").unwrap().any(|entry| entry
+            .unwrap()
+            .file_name()
+            .to_string_lossy()
+            .starts_with(".atomic_gen.fur."))
+    );
+    assert!(!PathBuf::from("tests/assets/atomic_gen.fur").exists());
+}
+
+#[test]
+fn test_concurrent_writes() {
+    let backend = DefaultBackend::new();
+    let expected = std::fs::read("tests/assets/error.png").unwrap();
+
+    //Every writer gets its own temp file, so none of them trips over another.
+    std::thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                backend
+                    .write_file(
+                        &PathBuf::from("tests/assets/concurrent_gen.bin"),
+                        &expected,
+                        true,
+                    )
+                    .unwrap()
+            });
+        }
+    });
+
+    assert_eq!(
+        std::fs::read("tests/assets/concurrent_gen.bin").unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn test_cubemap_dedup() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let inputs = vec!["error.png"; 6];
+
+    what.convert_cubemap("dedup_cubemap_gen.fur", &inputs, true)
+        .unwrap();
+
+    let face = std::fs::metadata("tests/assets/error.png").unwrap().len();
+    let size = std::fs::metadata("tests/assets/dedup_cubemap_gen.fur")
+        .unwrap()
+        .len();
+
+    //One face plus the header.
+    assert!(size < face + 1024);
+
+    let expected = include_bytes!("assets/error.png");
+
+    if let Asset::TextureArray(data) = what
+        .load_asset("dedup_cubemap_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.data.len(), 6);
+
+        for layer in data.data {
+            assert_eq!(expected, layer.as_slice());
+        }
+    } else {
+        panic!("Expected texture array.");
+    }
+}
+
+#[test]
+fn test_list_entries() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_cubemap("entries_cubemap_gen.fur", &["error.png"; 6], true)
+        .unwrap();
+
+    let face = std::fs::metadata("tests/assets/error.png").unwrap().len();
+    let entries = what.list_entries("entries_cubemap_gen.fur").unwrap();
+
+    let keys = entries
+        .iter()
+        .map(|entry| entry.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["+x", "-x", "+y", "-y", "+z", "-z"]);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.valid && entry.length == face));
+
+    //Cut off the end of the shared face, so every entry points past the payload.
+    let data = std::fs::read("tests/assets/entries_cubemap_gen.fur").unwrap();
+    std::fs::write(
+        "tests/assets/entries_truncated_gen.fur",
+        &data[..data.len() - 16],
+    )
+    .unwrap();
+
+    let entries = what.list_entries("entries_truncated_gen.fur").unwrap();
+    assert_eq!(entries.len(), 6);
+    assert!(entries.iter().all(|entry| !entry.valid));
+
+    what.convert_shader_program(
+        "entries_program_gen.fur",
+        &[
+            (ShaderStages::VERTEX, "program.vert.wgsl"),
+            (ShaderStages::FRAGMENT, "program.frag.wgsl"),
+        ],
+        false,
+        true,
+    )
+    .unwrap();
+
+    let entries = what.list_entries("entries_program_gen.fur").unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.iter().all(|entry| entry.valid));
+
+    assert!(matches!(
+        what.list_entries("error.fur"),
+        Err(Error::WrongAssetType { .. })
+    ));
+}
+
+#[test]
+fn test_texture_array_reader() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let keys = ["+x", "-x", "+y", "-y", "+z", "-z"];
+
+    let textures = TextureArrayData {
+        size: 16,
+        format: Some("raw".to_string()),
+        keys: keys.iter().map(|key| key.to_string()).collect(),
+        data: (0..6u8).map(|i| vec![i; 1024]).collect(),
+    };
+
+    what.write_texture_array("reader_gen.fur", &textures, true)
+        .unwrap();
+
+    let reader = what.open_texture_array("reader_gen.fur").unwrap();
+
+    assert_eq!(reader.size(), 16);
+    assert_eq!(reader.format(), Some("raw"));
+    assert_eq!(reader.keys(), keys);
+
+    assert_eq!(reader.layer("-x").unwrap(), vec![1; 1024]);
+    assert_eq!(reader.layer("-z").unwrap(), vec![5; 1024]);
+    assert!(reader.layer("missing").is_err());
+}
+
+#[test]
+fn test_corrupt_texture_array_length() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let textures = TextureArrayData {
+        size: 16,
+        format: Some("raw".to_string()),
+        keys: vec!["+x".to_string(), "-x".to_string()],
+        data: vec![vec![0; 1024], vec![1; 1024]],
+    };
+
+    what.write_texture_array("array_length_gen.fur", &textures, true)
+        .unwrap();
+
+    //offset + length overflows, which must not wrap around to a valid range.
+    edit_header("tests/assets/array_length_gen.fur", |header| {
+        let entry = &mut header["ctype"]["TextureArray"]["data"][1];
+        entry["length"] = u64::MAX.into();
+    });
+
+    assert!(is_out_of_range(what.load_asset(
+        "array_length_gen.fur",
+        Priority::Normal,
+        &[]
+    )));
+}
+
+#[test]
+fn test_read_range_huge_length() {
+    let base = Some(what::Location::File(PathBuf::from("tests/assets")));
+
+    //A length far beyond the file must fail with eof instead of allocating it up front.
+    match DefaultBackend::new().read_range(&base, "error.png", 0, Some(1 << 60)) {
+        Err(Error::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof),
+        other => panic!("Expected eof, got {:?}", other.map(|bytes| bytes.len())),
+    }
+
+    //Over http the end of the range would overflow.
+    let (url, requests) = serve_http(|_, _| (200, vec![0; 16]));
+    let base = Some(what::Location::Http(url));
+
+    match DefaultBackend::new().read_range(&base, "error.png", 8, Some(u64::MAX)) {
+        Err(Error::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof),
+        other => panic!("Expected eof, got {:?}", other.map(|bytes| bytes.len())),
+    }
+    assert!(requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_clear_cache() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write("tests/assets/clear_gen.bin", [1, 2, 3]).unwrap();
+    assert_eq!(
+        what.load_file("clear_gen.bin", Priority::Normal).unwrap(),
+        vec![1, 2, 3]
+    );
+    let guid = what.guid("clear_gen.bin").unwrap();
+
+    //Still served from the cache.
+    std::fs::write("tests/assets/clear_gen.bin", [4, 5, 6]).unwrap();
+    assert_eq!(
+        what.load_file("clear_gen.bin", Priority::Normal).unwrap(),
+        vec![1, 2, 3]
+    );
+
+    what.clear_cache();
+
+    assert_eq!(
+        what.load_file("clear_gen.bin", Priority::Normal).unwrap(),
+        vec![4, 5, 6]
+    );
+    assert_eq!(what.guid("clear_gen.bin"), Some(guid));
+}
+
+#[test]
+fn test_cached_paths() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let paths = ["error.png", "error.fur", "shader.wgsl"];
+
+    for path in paths {
+        what.load_file(path, Priority::Normal).unwrap();
+    }
+
+    let mut cached = what.cached_paths();
+    cached.sort();
+
+    assert_eq!(cached, ["error.fur", "error.png", "shader.wgsl"]);
+}
+
+#[test]
+fn test_reject_empty_and_animated_images() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    match what.convert_texture("empty_gen.fur", "empty.tga", true) {
+        Err(Error::Conversion(message)) => assert!(message.contains("no pixels")),
+        Err(err) => panic!("Expected conversion error, got {:?}", err),
+        Ok(_) => panic!("Expected conversion error."),
+    }
+
+    match what.convert_texture("animated_gen.fur", "animated.gif", true) {
+        Err(Error::Conversion(message)) => assert!(message.contains("animated")),
+        Err(err) => panic!("Expected conversion error, got {:?}", err),
+        Ok(_) => panic!("Expected conversion error."),
+    }
+
+    assert!(!PathBuf::from("tests/assets/empty_gen.fur").exists());
+    assert!(!PathBuf::from("tests/assets/animated_gen.fur").exists());
+}
+
+#[test]
+fn test_normalized_cache_keys() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.load_file("error.png", Priority::Normal).unwrap();
+    what.load_file("./error.png", Priority::Normal).unwrap();
+    what.load_file("missing/../error.png", Priority::Normal)
+        .unwrap();
+
+    assert_eq!(what.cached_paths(), ["error.png"]);
+    assert_eq!(what.guid("./error.png"), what.guid("error.png"));
+}
+
+#[test]
+fn test_texture_regions() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let regions = vec![
+        TextureRegion {
+            name: "player".to_string(),
+            x: 0,
+            y: 0,
+            w: 32,
+            h: 64,
+        },
+        TextureRegion {
+            name: "coin".to_string(),
+            x: 480,
+            y: 496,
+            w: 32,
+            h: 16,
+        },
+    ];
+
+    let options = TextureOptions {
+        regions: regions.clone(),
+        ..Default::default()
+    };
+
+    what.convert_texture_with("regions_gen.fur", "error.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("regions_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.regions, regions);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_texture_3d() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let volume = Texture3DData {
+        width: 4,
+        height: 4,
+        depth: 4,
+        format: Some("rgba8".to_string()),
+        data: (0..=255).collect(),
+    };
+
+    what.write_texture_3d("volume_gen.fur", &volume, true)
+        .unwrap();
+
+    if let Asset::Texture3D(data) = what
+        .load_asset("volume_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.width, 4);
+        assert_eq!(data.height, 4);
+        assert_eq!(data.depth, 4);
+        assert_eq!(data.format.as_deref(), Some("rgba8"));
+        assert_eq!(data.data, volume.data);
+    } else {
+        panic!("Expected 3D texture.");
+    }
+
+    what.write_texture_3d("volume_far_gen.fur", &volume, true)
+        .unwrap();
+    edit_header("tests/assets/volume_far_gen.fur", |header| {
+        header["ctype"]["Texture3D"]["offset"] = 1_000_000.into();
+    });
+
+    assert!(is_out_of_range(what.load_asset(
+        "volume_far_gen.fur",
+        Priority::Normal,
+        &[]
+    )));
+}
+
+#[test]
+fn test_convert_texture_3d() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let slices = (0..4u8)
+        .map(|z| {
+            let path = PathBuf::from(format!("slice_gen_{}.png", z));
+            image::RgbaImage::from_fn(4, 4, |x, y| image::Rgba([x as u8, y as u8, z, 255]))
+                .save(PathBuf::from("tests/assets").join(&path))
+                .unwrap();
+            path
+        })
+        .collect::<Vec<_>>();
+
+    what.convert_texture_3d(PathBuf::from("slices_gen.fur"), &slices, true)
+        .unwrap();
+
+    if let Asset::Texture3D(data) = what
+        .load_asset("slices_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!((data.width, data.height, data.depth), (4, 4, 4));
+        assert_eq!(data.format.as_deref(), Some("rgba8"));
+        assert_eq!(data.data.len(), 4 * 4 * 4 * 4);
+
+        //Slices are stored back to back, rows inside a slice top to bottom.
+        for z in 0..4 {
+            for y in 0..4 {
+                for x in 0..4 {
+                    let texel = ((z * 4 + y) * 4 + x) * 4;
+                    assert_eq!(
+                        data.data[texel..texel + 4],
+                        [x as u8, y as u8, z as u8, 255]
+                    );
+                }
+            }
+        }
+    } else {
+        panic!("Expected 3D texture.");
+    }
+}
+
+#[test]
+fn test_normal_from_flat_height() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::GrayImage::from_pixel(8, 8, image::Luma([100]))
+        .save("tests/assets/flat_height_gen.png")
+        .unwrap();
+
+    what.convert_normal_from_height("flat_normal_gen.fur", "flat_height_gen.png", 2.0, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("flat_normal_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.color_space, ColorSpace::Linear);
+        assert_eq!(data.format.as_deref(), Some("rgba8"));
+        assert_eq!((data.width, data.height), (8, 8));
+        assert_eq!(data.data.len(), 8 * 8 * 4);
+
+        for pixel in data.data.chunks_exact(4) {
+            assert_eq!(pixel, [128, 128, 255, 255]);
+        }
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_premultiply_alpha() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::RgbaImage::from_pixel(1, 1, image::Rgba([200, 100, 50, 128]))
+        .save("tests/assets/straight_gen.png")
+        .unwrap();
+
+    let options = TextureOptions {
+        alpha_mode: AlphaMode::Premultiplied,
+        ..Default::default()
+    };
+
+    what.convert_texture_with("premultiplied_gen.fur", "straight_gen.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("premultiplied_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.alpha_mode, AlphaMode::Premultiplied);
+
+        let pixel = *image::load_from_memory(&data.data)
+            .unwrap()
+            .to_rgba8()
+            .get_pixel(0, 0);
+
+        //200 * 128 / 255 = 100.4, 100 * 128 / 255 = 50.2, 50 * 128 / 255 = 25.1
+        assert_eq!(pixel.0, [100, 50, 25, 128]);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_texture_sampler() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let sampler = SamplerInfo {
+        wrap_s: WrapMode::ClampToEdge,
+        wrap_t: WrapMode::ClampToEdge,
+        min_filter: FilterMode::Linear,
+        mag_filter: FilterMode::Linear,
+        mip_filter: None,
+    };
+
+    let options = TextureOptions {
+        sampler: Some(sampler),
+        ..Default::default()
+    };
+
+    what.convert_texture_with("sampler_gen.fur", "error.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("sampler_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.sampler, Some(sampler));
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_convert_hdr_texture() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let pixels = vec![image::Rgb([4.0f32, 0.5, 0.25]); 4];
+
+    image::codecs::hdr::HdrEncoder::new(std::fs::File::create("tests/assets/hdr_gen.hdr").unwrap())
+        .encode(&pixels, 2, 2)
+        .unwrap();
+
+    what.convert_texture("hdr_gen.fur", "hdr_gen.hdr", true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("hdr_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.texture_format(), Some(TextureFormat::Rgba32F));
+        assert_eq!(data.data.len(), 2 * 2 * 4 * 4);
+
+        let texel = data.data[..16]
+            .chunks(4)
+            .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect::<Vec<f32>>();
+
+        assert_eq!(texel, [4.0, 0.5, 0.25, 1.0]);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+struct ClosureBackend<F: Fn(&str) -> Option<Vec<u8>>>(F);
+
+impl<F: Fn(&str) -> Option<Vec<u8>> + Send> Backend for ClosureBackend<F> {
+    fn read_file(
+        &self,
+        _base: &Option<what::Location>,
+        path: &str,
+        _mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        match (self.0)(path) {
+            Some(bytes) => Ok((bytes.into(), None)),
+            None => Err(Error::Io(std::io::ErrorKind::NotFound.into())),
+        }
+    }
+}
+
+#[test]
+fn test_custom_backend() {
+    let mut what = What::new(1e8 as usize, None);
+
+    what.set_backend(Box::new(ClosureBackend(|path: &str| {
+        (path == "virtual/error.fur").then(|| include_bytes!("assets/error.fur").to_vec())
+    })));
+
+    assert!(what.load_file("error.fur", Priority::Normal).is_err());
+
+    if let Asset::Texture(data) = what
+        .load_asset("virtual/error.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.data.as_slice(), include_bytes!("assets/error.png"));
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+//Minimal http server answering every request with `respond(path, headers)`.
+//Records the requested paths.
+fn serve_http<F>(respond: F) -> (String, Arc<Mutex<Vec<String>>>)
+where
+    F: Fn(&str, &[String]) -> (u16, Vec<u8>) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let log = requests.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let path = request.split(' ').nth(1).unwrap_or("/").to_string();
+
+            let mut headers = Vec::new();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                headers.push(line.trim_end().to_string());
+                line.clear();
+            }
+
+            log.lock().unwrap().push(path.clone());
+
+            let (status, body) = respond(&path, &headers);
+            write!(
+                stream,
+                "HTTP/1.1 {} X\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+        }
+    });
+
+    (url, requests)
+}
+
+#[test]
+fn test_http_url_resolution() {
+    let writer = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/scene_gen.gltf",
+        r#"{"asset":{"version":"2.0"},"images":[{"uri":"../textures/a.png"}]}"#,
+    )
+    .unwrap();
+    writer
+        .convert_gltf("scene_gen.fur", "scene_gen.gltf", true)
+        .unwrap();
+    let scene = std::fs::read("tests/assets/scene_gen.fur").unwrap();
+
+    let (url, requests) = serve_http(move |path, _| match path {
+        "/assets/error.fur" => (200, include_bytes!("assets/error.fur").to_vec()),
+        "/other/error.png" | "/assets/textures/a.png" => {
+            (200, include_bytes!("assets/error.png").to_vec())
+        }
+        "/assets/models/scene.fur" => (200, scene.clone()),
+        _ => (404, Vec::new()),
+    });
+
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::Http(format!("{}/assets", url))),
+    );
+
+    //Relative to the base.
+    assert!(what.load_asset("error.fur", Priority::Normal, &[]).is_ok());
+
+    //Absolute urls ignore the base.
+    let actual = what
+        .load_file(format!("{}/other/error.png", url), Priority::Normal)
+        .unwrap();
+    assert_eq!(actual.as_slice(), include_bytes!("assets/error.png"));
+
+    //Parent dirs inside a glTF resolve against the glTF's url.
+    if let Asset::Gltf(_, _, images, _) = what
+        .load_asset("models/scene.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(images.len(), 1);
+    } else {
+        panic!("Expected gltf.");
+    }
+
+    assert!(matches!(
+        what.load_file("missing.fur", Priority::Normal),
+        Err(Error::Io(err)) if err.kind() == std::io::ErrorKind::NotFound
+    ));
+
+    assert_eq!(
+        *requests.lock().unwrap(),
+        [
+            "/assets/error.fur",
+            "/other/error.png",
+            "/assets/models/scene.fur",
+            "/assets/textures/a.png",
+            "/assets/missing.fur",
+        ]
+    );
+}
+
+#[test]
+fn test_http_retry() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let counter = attempts.clone();
+
+    let (url, requests) = serve_http(move |_, _| {
+        if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+            (503, Vec::new())
+        } else {
+            (200, include_bytes!("assets/error.png").to_vec())
+        }
+    });
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Http(url)));
+    what.set_backend(Box::new(DefaultBackend::new().with_retry_policy(
+        RetryPolicy {
+            base_delay: Duration::from_millis(1),
+            ..Default::default()
+        },
+    )));
+
+    let actual = what.load_file("error.png", Priority::Normal).unwrap();
+    assert_eq!(actual.as_slice(), include_bytes!("assets/error.png"));
+    assert_eq!(requests.lock().unwrap().len(), 3);
+
+    //Not found isn't worth retrying.
+    what.set_backend(Box::new(DefaultBackend::new().with_retry_policy(
+        RetryPolicy {
+            retry_statuses: vec![],
+            ..Default::default()
+        },
+    )));
+    attempts.store(0, Ordering::SeqCst);
+
+    assert!(what.load_file("error.png", Priority::Normal).is_err());
+    assert_eq!(requests.lock().unwrap().len(), 4);
+}
+
+#[test]
+fn test_http_headers() {
+    let (url, _) = serve_http(|_, headers| {
+        if headers
+            .iter()
+            .any(|header| header == "Authorization: Bearer secret")
+        {
+            (200, include_bytes!("assets/error.png").to_vec())
+        } else {
+            (401, Vec::new())
+        }
+    });
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Http(url)));
+
+    assert!(what.load_file("error.png", Priority::Normal).is_err());
+
+    what.set_backend(Box::new(
+        DefaultBackend::new().with_header("Authorization", "Bearer secret"),
+    ));
+
+    let actual = what.load_file("error.png", Priority::Normal).unwrap();
+    assert_eq!(actual.as_slice(), include_bytes!("assets/error.png"));
+}
+
+#[test]
+fn test_dry_run() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+    what.set_dry_run(true);
+
+    let _ = std::fs::remove_dir_all("tests/assets/dry_gen");
+
+    let report = what
+        .convert_texture("dry_gen/error.fur", "error.png", false)
+        .unwrap();
+
+    assert_eq!(
+        report.output,
+        PathBuf::from("tests/assets/dry_gen/error.fur")
+    );
+    assert!(report.bytes_written > 0);
+    assert!(!PathBuf::from("tests/assets/dry_gen").exists());
+    assert_eq!(what.manifest().assets.len(), 1);
+}
+
+#[test]
+fn test_conversion_error_causes() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    match what.convert_texture("missing_gen.fur", "missing.png", true) {
+        Err(Error::Read(path, err)) => {
+            assert_eq!(path, PathBuf::from("tests/assets/missing.png"));
+            assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("Expected a read error, got {:?}", other.map(|_| ())),
+    }
+
+    std::fs::write("tests/assets/broken_gen.wgsl", "fn main( {").unwrap();
+    assert!(matches!(
+        what.convert_shader("broken_shader_gen.fur", "broken_gen.wgsl", false, true),
+        Err(Error::Shader(_, _))
+    ));
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::copy("tests/assets/error.png", "tests/assets/locked_gen.png").unwrap();
+        std::fs::set_permissions(
+            "tests/assets/locked_gen.png",
+            std::fs::Permissions::from_mode(0o000),
+        )
+        .unwrap();
+
+        //Root may read anything, in which case there is nothing to deny.
+        if std::fs::read("tests/assets/locked_gen.png").is_err() {
+            match what.convert_texture("locked_gen.fur", "locked_gen.png", true) {
+                Err(Error::Read(_, err)) => {
+                    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied)
+                }
+                other => panic!("Expected a read error, got {:?}", other.map(|_| ())),
+            }
+        }
+
+        std::fs::remove_file("tests/assets/locked_gen.png").unwrap();
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_load_permission_denied() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::create_dir_all("tests/assets/locked_dir_gen").unwrap();
+    std::fs::write("tests/assets/locked_dir_gen/file.bin", [1, 2, 3]).unwrap();
+    std::fs::set_permissions(
+        "tests/assets/locked_dir_gen",
+        std::fs::Permissions::from_mode(0o000),
+    )
+    .unwrap();
+
+    //Root may read anything, in which case there is nothing to deny.
+    if std::fs::metadata("tests/assets/locked_dir_gen/file.bin").is_err() {
+        match what.load_file("locked_dir_gen/file.bin", Priority::Normal) {
+            Err(Error::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied),
+            other => panic!("Expected a permission error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    std::fs::set_permissions(
+        "tests/assets/locked_dir_gen",
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_cubemap_face_count() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    for faces in [5, 7] {
+        let inputs = vec!["error.png"; faces];
+
+        match what.convert_cubemap("faces_gen.fur", &inputs, true) {
+            Err(Error::Conversion(message)) => assert!(message.contains("exactly 6 faces")),
+            other => panic!("Expected a conversion error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    assert!(!PathBuf::from("tests/assets/faces_gen.fur").exists());
+}
+
+#[test]
+fn test_cubemap_from_equirect() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    //Red sky above a blue ground.
+    image::RgbaImage::from_fn(64, 32, |_, y| {
+        if y < 16 {
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            image::Rgba([0, 0, 255, 255])
+        }
+    })
+    .save("tests/assets/panorama_gen.png")
+    .unwrap();
+
+    what.convert_cubemap_from_equirect("equirect_gen.fur", "panorama_gen.png", 16, true)
+        .unwrap();
+
+    if let Asset::TextureArray(data) = what
+        .load_asset("equirect_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.size, 16);
+        assert_eq!(data.keys, ["+x", "-x", "+y", "-y", "+z", "-z"]);
+
+        let faces = data
+            .data
+            .iter()
+            .map(|face| image::load_from_memory(face).unwrap().to_rgba8())
+            .collect::<Vec<_>>();
+
+        assert!(faces.iter().all(|face| face.dimensions() == (16, 16)));
+        assert!(faces[2].pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+        assert!(faces[3].pixels().all(|pixel| pixel.0 == [0, 0, 255, 255]));
+    } else {
+        panic!("Expected cubemap.");
+    }
+}
+
+#[test]
+fn test_texture_max_size() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::RgbaImage::from_pixel(2048, 1536, image::Rgba([10, 20, 30, 255]))
+        .save("tests/assets/large_gen.png")
+        .unwrap();
+
+    let options = TextureOptions {
+        max_size: Some(1024),
+        resize_filter: ResizeFilter::Triangle,
+        ..Default::default()
+    };
+
+    what.convert_texture_with("max_size_gen.fur", "large_gen.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("max_size_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!((data.width, data.height), (1024, 768));
+
+        let image = image::load_from_memory(&data.data).unwrap();
+        assert_eq!((image.width(), image.height()), (1024, 768));
+    } else {
+        panic!("Expected texture.");
+    }
+
+    let options = TextureOptions {
+        max_size: Some(1024),
+        power_of_two: true,
+        ..options
+    };
+
+    what.convert_texture_with("power_of_two_gen.fur", "large_gen.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("power_of_two_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!((data.width, data.height), (1024, 512));
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[cfg(feature = "bcn")]
+#[test]
+fn test_compress_bc7() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::RgbaImage::from_fn(10, 6, |x, y| {
+        image::Rgba([x as u8 * 25, y as u8 * 40, 0, 255])
+    })
+    .save("tests/assets/bc7_gen.png")
+    .unwrap();
+
+    let options = TextureOptions {
+        compress_to: Some(TextureFormat::Bc7),
+        ..Default::default()
+    };
+
+    what.convert_texture_with("bc7_gen.fur", "bc7_gen.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("bc7_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.texture_format(), Some(TextureFormat::Bc7));
+        assert_eq!((data.width, data.height), (10, 6));
+
+        //3x2 blocks of 16 bytes.
+        assert_eq!(data.data.len(), 3 * 2 * 16);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[cfg(feature = "bcn")]
+#[test]
+fn test_compress_astc() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let options = TextureOptions {
+        compress_to: Some(TextureFormat::Astc4x4),
+        ..Default::default()
+    };
+
+    what.convert_texture_with("astc_gen.fur", "error.png", &options, true)
+        .unwrap();
+
+    if let Asset::Texture(data) = what
+        .load_asset("astc_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.texture_format(), Some(TextureFormat::Astc4x4));
+
+        let blocks = data.width.div_ceil(4) * data.height.div_ceil(4);
+        assert_eq!(data.data.len(), blocks as usize * 16);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[cfg(feature = "bcn")]
+#[test]
+fn test_texture_variants() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::RgbaImage::from_fn(8, 4, |x, y| {
+        image::Rgba([x as u8 * 30, y as u8 * 60, 0, 255])
+    })
+    .save("tests/assets/variants_gen.png")
+    .unwrap();
+
+    let options = TextureOptions {
+        variants: vec![TextureFormat::Bc7, TextureFormat::Rgba8],
+        ..Default::default()
+    };
+
+    what.convert_texture_with("variants_gen.fur", "variants_gen.png", &options, true)
+        .unwrap();
+
+    let load = |what: &mut What, preferred: &[TextureFormat]| match what
+        .load_asset("variants_gen.fur", Priority::Normal, preferred)
+        .unwrap()
+    {
+        Asset::Texture(data) => data,
+        _ => panic!("Expected texture."),
+    };
+
+    let bc7 = load(&mut what, &[TextureFormat::Bc7, TextureFormat::Rgba8]);
+    assert_eq!(bc7.texture_format(), Some(TextureFormat::Bc7));
+    assert_eq!(bc7.data.len(), 2 * 16);
+
+    //Without Bc7 support the next preferred format is picked.
+    let rgba8 = load(&mut what, &[TextureFormat::Bc5, TextureFormat::Rgba8]);
+    assert_eq!(rgba8.texture_format(), Some(TextureFormat::Rgba8));
+    assert_eq!(&rgba8.data[..8], &[0, 0, 0, 255, 30, 0, 0, 255]);
+    assert!(rgba8.variants.is_empty());
+
+    //Nothing preferred falls back to the main texture.
+    let main = load(&mut what, &[]);
+    assert_eq!(main.format.as_deref(), Some("png"));
+    assert_eq!(
+        main.data,
+        std::fs::read("tests/assets/variants_gen.png").unwrap()
+    );
+}
+
+#[test]
+fn test_rgba8_variant() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::RgbaImage::from_fn(8, 4, |x, y| {
+        image::Rgba([x as u8 * 30, y as u8 * 60, 0, 255])
+    })
+    .save("tests/assets/rgba8_variant_gen.png")
+    .unwrap();
+
+    let options = TextureOptions {
+        variants: vec![TextureFormat::Rgba8],
+        ..Default::default()
+    };
+
+    what.convert_texture_with(
+        "rgba8_variant_gen.fur",
+        "rgba8_variant_gen.png",
+        &options,
+        true,
+    )
+    .unwrap();
+
+    match what
+        .load_asset(
+            "rgba8_variant_gen.fur",
+            Priority::Normal,
+            &[TextureFormat::Rgba8],
+        )
+        .unwrap()
+    {
+        Asset::Texture(data) => {
+            assert_eq!(data.texture_format(), Some(TextureFormat::Rgba8));
+            assert_eq!(data.data.len(), 8 * 4 * 4);
+            assert_eq!(&data.data[..8], &[0, 0, 0, 255, 30, 0, 0, 255]);
+        }
+        _ => panic!("Expected texture."),
+    }
+
+    //A variant reaching past the payload is an error, not a panic.
+    let mut file = std::fs::read("tests/assets/rgba8_variant_gen.fur").unwrap();
+    let at = file
+        .windows(12)
+        .position(|window| window == b"\"length\":128")
+        .unwrap();
+    file[at + 9..at + 12].copy_from_slice(b"999");
+    std::fs::write("tests/assets/rgba8_variant_bad_gen.fur", file).unwrap();
+
+    match what.load_asset(
+        "rgba8_variant_bad_gen.fur",
+        Priority::Normal,
+        &[TextureFormat::Rgba8],
+    ) {
+        Err(Error::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof),
+        other => panic!("Expected eof, got {:?}", other.map(|asset| asset.kind())),
+    }
+}
+
+#[test]
+fn test_verify_dir() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let _ = std::fs::remove_dir_all("tests/assets/verify_gen");
+    std::fs::create_dir_all("tests/assets/verify_gen").unwrap();
+
+    let good = include_bytes!("assets/error.fur");
+    std::fs::write("tests/assets/verify_gen/good.fur", good).unwrap();
+    std::fs::write("tests/assets/verify_gen/truncated.fur", &good[..20]).unwrap();
+
+    for deep in [false, true] {
+        let results = what.verify_dir("verify_gen", deep).unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].0,
+            PathBuf::from("tests/assets/verify_gen/good.fur")
+        );
+        assert!(results[0].1.is_ok());
+        assert_eq!(
+            results[1].0,
+            PathBuf::from("tests/assets/verify_gen/truncated.fur")
+        );
+        assert!(results[1].1.is_err());
+    }
+}
+
+#[test]
+fn test_asset_kind() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_shader("shader_kind_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+
+    assert_eq!(
+        what.asset_kind("shader_kind_gen.fur").unwrap(),
+        AssetKind::Shader
+    );
+    assert_eq!(what.asset_kind("error.fur").unwrap(), AssetKind::Texture);
+    assert!(what.cached_paths().is_empty());
+}
+
+#[test]
+fn test_debug_elides_data() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    if let Asset::Texture(texture) = what.load_asset("error.fur", Priority::Normal, &[]).unwrap() {
+        let debug = format!("{:?}", texture);
+
+        assert!(debug.contains(&format!("<{} bytes>", texture.data.len())));
+        assert!(debug.len() < 1000);
+
+        let copy = texture.clone();
+        assert_eq!(copy.data, texture.data);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_texture_data_json() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    if let Asset::Texture(texture) = what.load_asset("error.fur", Priority::Normal, &[]).unwrap() {
+        let json = serde_json::to_string(&texture).unwrap();
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert!(value["data"].is_string());
+
+        let copy = serde_json::from_str::<what::TextureData>(&json).unwrap();
+        assert_eq!(copy.data, texture.data);
+        assert_eq!((copy.width, copy.height), (texture.width, texture.height));
+        assert_eq!(copy.format, texture.format);
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+struct CaptureLogger(Mutex<Vec<String>>);
+
+impl log::Log for CaptureLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        self.0
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", record.level(), record.args()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+
+//The logger can only be installed once per process, so every test shares it.
+fn capture_logs() -> &'static CaptureLogger {
+    static INSTALL: Once = Once::new();
+
+    INSTALL.call_once(|| {
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Debug);
+    });
+
+    &LOGGER
+}
+
+#[test]
+fn test_eviction_logs() {
+    let logger = capture_logs();
+
+    let mut what = What::new(
+        9000,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.load_file("error.png", Priority::Normal).unwrap();
+    what.load_file("error.fur", Priority::Normal).unwrap();
+    what.load_file("error.png", Priority::Normal).unwrap();
+
+    let logs = logger.0.lock().unwrap();
+
+    assert!(logs.iter().any(|line| {
+        line.starts_with("DEBUG Evicted ")
+            && line.contains(" from the cache, freeing ")
+            && line.ends_with(" bytes.")
+    }));
+    assert!(logs
+        .iter()
+        .any(|line| line.starts_with("WARN error.png was evicted")));
+}
+
+#[test]
+fn test_what_eviction_policy() {
+    for (i, name) in ["a", "b", "c"].iter().enumerate() {
+        std::fs::write(
+            format!("tests/assets/policy_{}_gen.bin", name),
+            [i as u8; 1000],
+        )
+        .unwrap();
+    }
+
+    //Room for two files. a is used twice, but was loaded first.
+    let evicted = |policy| {
+        let mut what = What::with_eviction_policy(
+            2500,
+            0,
+            Some(what::Location::File(PathBuf::from("tests/assets"))),
+            policy,
+        );
+        assert_eq!(what.eviction_policy(), policy);
+
+        for name in ["a", "b", "a", "c"] {
+            what.load_file(format!("policy_{}_gen.bin", name), Priority::Normal)
+                .unwrap();
+        }
+
+        ["a", "b"]
+            .into_iter()
+            .filter(|name| {
+                !what
+                    .cached_paths()
+                    .contains(&format!("policy_{}_gen.bin", name))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(evicted(EvictionPolicy::Lfu), ["b"]);
+    assert_eq!(evicted(EvictionPolicy::Lru), ["b"]);
+    assert_eq!(evicted(EvictionPolicy::Fifo), ["a"]);
+}
+
+#[test]
+fn test_save_and_load_cache() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write("tests/assets/snapshot_a_gen.bin", [1, 2, 3]).unwrap();
+    std::fs::write("tests/assets/snapshot_b_gen.bin", [4, 5]).unwrap();
+
+    what.load_file("snapshot_a_gen.bin", Priority::High)
+        .unwrap();
+    what.load_file("./snapshot_b_gen.bin", Priority::Normal)
+        .unwrap();
+    what.save_cache("tests/assets/snapshot_gen.cache").unwrap();
+
+    //Changed files are read again instead of served stale from the snapshot.
+    std::fs::write("tests/assets/snapshot_a_gen.bin", [0]).unwrap();
+
+    let mut restored = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    assert_eq!(
+        restored
+            .load_cache("tests/assets/snapshot_gen.cache")
+            .unwrap(),
+        1
+    );
+
+    assert_eq!(restored.cached_paths(), ["snapshot_b_gen.bin"]);
+    assert_eq!(
+        restored.guid("snapshot_b_gen.bin"),
+        what.guid("snapshot_b_gen.bin")
+    );
+    assert_eq!(
+        restored
+            .load_file("snapshot_a_gen.bin", Priority::Normal)
+            .unwrap(),
+        vec![0]
+    );
+    assert_eq!(
+        restored
+            .load_file("snapshot_b_gen.bin", Priority::Normal)
+            .unwrap(),
+        vec![4, 5]
+    );
+}
+
+struct HintBackend(DefaultBackend, Arc<Mutex<Vec<u64>>>);
+
+impl Backend for HintBackend {
+    fn read_file(
+        &self,
+        base: &Option<what::Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.0.read_file(base, path, mmap_threshold)
+    }
+
+    fn read_file_with_hint(
+        &self,
+        base: &Option<what::Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+        size_hint: &mut dyn FnMut(u64),
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.0
+            .read_file_with_hint(base, path, mmap_threshold, &mut |size| {
+                self.1.lock().unwrap().push(size);
+                size_hint(size);
+            })
+    }
+}
+
+//Counts whole file reads and records the ranges read.
+struct CountingBackend(
+    DefaultBackend,
+    Arc<AtomicUsize>,
+    Arc<Mutex<Vec<(u64, Option<u64>)>>>,
+);
+
+impl Backend for CountingBackend {
+    fn read_file(
+        &self,
+        base: &Option<what::Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.1.fetch_add(1, Ordering::SeqCst);
+        self.0.read_file(base, path, mmap_threshold)
+    }
+
+    fn read_range(
+        &self,
+        base: &Option<what::Location>,
+        path: &str,
+        offset: u64,
+        length: Option<u64>,
+    ) -> Result<Vec<u8>, Error> {
+        self.2.lock().unwrap().push((offset, length));
+        self.0.read_range(base, path, offset, length)
+    }
+
+    fn read_tail(
+        &self,
+        base: &Option<what::Location>,
+        path: &str,
+        length: u64,
+    ) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.0.read_tail(base, path, length)
+    }
+}
+
+//Flips the last byte of everything it writes.
+struct CorruptingBackend(DefaultBackend);
+
+impl Backend for CorruptingBackend {
+    fn read_file(
+        &self,
+        base: &Option<what::Location>,
+        path: &str,
+        mmap_threshold: Option<u64>,
+    ) -> Result<(Bytes, ExtraFiles), Error> {
+        self.0.read_file(base, path, mmap_threshold)
+    }
+
+    fn write_file(
+        &self,
+        path: &std::path::Path,
+        content: &[u8],
+        overwrite: bool,
+    ) -> Result<(), Error> {
+        let mut content = content.to_vec();
+        if let Some(last) = content.last_mut() {
+            *last ^= 0xff;
+        }
+        self.0.write_file(path, &content, overwrite)
+    }
+}
+
+#[test]
+fn test_verify_written() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.set_verify(true);
+    what.convert_texture("verify_ok_gen.fur", "error.png", true)
+        .unwrap();
+
+    what.set_backend(Box::new(CorruptingBackend(DefaultBackend::new())));
+    match what.convert_texture("verify_bad_gen.fur", "error.png", true) {
+        Err(Error::Conversion(err)) => assert!(err.contains("payload differs"), "{}", err),
+        _ => panic!("Expected the verification to fail."),
+    }
+
+    //Without verify the broken file goes unnoticed.
+    what.set_verify(false);
+    what.convert_texture("verify_bad_gen.fur", "error.png", true)
+        .unwrap();
+}
+
+#[test]
+fn test_texture_info() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_texture("texture_info_gen.fur", "error.png", true)
+        .unwrap();
+    what.convert_shader("texture_info_shader_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    what.set_backend(Box::new(CountingBackend(
+        DefaultBackend::new(),
+        reads.clone(),
+        ranges.clone(),
+    )));
+
+    let (width, height, format) = what.texture_info("texture_info_gen.fur").unwrap();
+    assert_eq!((width, height), (512, 512));
+    assert_eq!(format.as_deref(), Some("png"));
+
+    //Only ranges within the header were read, none of the pixels.
+    let size = std::fs::metadata("tests/assets/texture_info_gen.fur")
+        .unwrap()
+        .len();
+    let png = std::fs::metadata("tests/assets/error.png").unwrap().len();
+    let ranges = ranges.lock().unwrap().clone();
+    assert_eq!(reads.load(Ordering::SeqCst), 0);
+    assert!(!ranges.is_empty());
+    assert!(ranges
+        .iter()
+        .all(|(offset, length)| length.is_some_and(|length| offset + length <= size - png)));
+
+    assert!(matches!(
+        what.texture_info("texture_info_shader_gen.fur"),
+        Err(Error::WrongAssetType { .. })
+    ));
+}
+
+#[test]
+fn test_texture_mip() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let options = TextureOptions {
+        mipmaps: true,
+        ..Default::default()
+    };
+
+    what.convert_texture_with("mip_gen.fur", "error.png", &options, true)
+        .unwrap();
+
+    let reads = Arc::new(AtomicUsize::new(0));
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    what.set_backend(Box::new(CountingBackend(
+        DefaultBackend::new(),
+        reads.clone(),
+        ranges.clone(),
+    )));
+
+    let mip = what.load_texture_mip("mip_gen.fur", 3).unwrap();
+    assert_eq!((mip.width, mip.height), (64, 64));
+    assert_eq!(mip.format.as_deref(), Some("png"));
+    let image = image::load_from_memory(&mip.data).unwrap();
+    assert_eq!((image.width(), image.height()), (64, 64));
+
+    //Only the header and the level itself were read, the base level comes last.
+    let size = std::fs::metadata("tests/assets/mip_gen.fur").unwrap().len();
+    let png = std::fs::metadata("tests/assets/error.png").unwrap().len();
+    let ranges = ranges.lock().unwrap().clone();
+    assert_eq!(reads.load(Ordering::SeqCst), 0);
+    assert!(ranges
+        .iter()
+        .all(|(offset, length)| length.is_some_and(|length| offset + length <= size - png)));
+
+    //Level 0 is the texture, and 512x512 has 9 levels below it.
+    let base = what.load_texture_mip("mip_gen.fur", 0).unwrap();
+    assert_eq!((base.width, base.height), (512, 512));
+    let last = what.load_texture_mip("mip_gen.fur", 9).unwrap();
+    assert_eq!((last.width, last.height), (1, 1));
+    assert!(what.load_texture_mip("mip_gen.fur", 10).is_err());
+}
+
+#[test]
+fn test_image_encoding() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let mut encode = |output: &str, encoding: ImageEncoding| {
+        let options = TextureOptions {
+            encoding,
+            ..Default::default()
+        };
+
+        what.convert_texture_with(output, "error.png", &options, true)
+            .unwrap();
+        what.load_texture(output, Priority::Normal, &[]).unwrap()
+    };
+
+    let low = encode("jpeg_low_gen.fur", ImageEncoding::Jpeg(10));
+    let high = encode("jpeg_high_gen.fur", ImageEncoding::Jpeg(95));
+
+    assert_eq!(low.format.as_deref(), Some("jpg"));
+    assert!(low.data.len() < high.data.len());
+
+    let image = image::load_from_memory(&low.data).unwrap();
+    assert_eq!((image.width(), image.height()), (512, 512));
+
+    //Png is only re-encoded when something changes the image.
+    let png = encode("png_best_gen.fur", ImageEncoding::Png(PngCompression::Best));
+    assert_eq!(png.data, include_bytes!("assets/error.png"));
+
+    let options = TextureOptions {
+        encoding: ImageEncoding::Jpeg(0),
+        ..Default::default()
+    };
+    assert!(what
+        .convert_texture_with("jpeg_zero_gen.fur", "error.png", &options, true)
+        .is_err());
+}
+
+#[test]
+fn test_static_location() {
+    static FILES: &[(&str, &[u8])] = &[
+        ("textures/error.fur", include_bytes!("assets/error.fur")),
+        ("shader.wgsl", include_bytes!("assets/shader.wgsl")),
+    ];
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Static(FILES)));
+
+    match what
+        .load_asset("./textures/error.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Texture(data) => assert_eq!((data.width, data.height), (512, 512)),
+        _ => panic!("Expected texture."),
+    }
+
+    //Embedded files are handed out without a copy.
+    match what.load_bytes("shader.wgsl", Priority::Normal).unwrap() {
+        Bytes::Static(bytes) => assert_eq!(bytes, include_bytes!("assets/shader.wgsl")),
+        _ => panic!("Expected static bytes."),
+    }
+
+    assert_eq!(
+        what.load_header("textures/error.fur").unwrap().kind,
+        AssetKind::Texture
+    );
+
+    match what.load_file("missing.fur", Priority::Normal) {
+        Err(Error::Io(err)) => assert_eq!(err.kind(), std::io::ErrorKind::NotFound),
+        _ => panic!("Expected NotFound."),
+    }
+}
+
+#[test]
+fn test_http_content_length_preallocates() {
+    let (url, _) = serve_http(|_, _| (200, vec![7; 1000]));
+    let hints = Arc::new(Mutex::new(Vec::new()));
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Http(url)));
+    what.set_backend(Box::new(HintBackend(DefaultBackend::new(), hints.clone())));
+
+    match what.load_bytes("sized.bin", Priority::Normal).unwrap() {
+        Bytes::Owned(bytes) => {
+            assert_eq!(bytes.len(), 1000);
+            assert_eq!(bytes.capacity(), 1000);
+        }
+        _ => panic!("Expected owned bytes."),
+    }
+
+    assert_eq!(*hints.lock().unwrap(), [1000]);
+}
+
+#[test]
+fn test_what_is_send() {
+    fn assert_send<T: Send>(_: &T) {}
+
+    let mut what = What::new(1e8 as usize, None);
+    what.set_read_progress(|_, _, _| {});
+    what.add_post_processor(AssetKind::Texture, |_| {});
+
+    assert_send(&what);
+    std::thread::spawn(move || what.load_file("tests/assets/error.png", Priority::Normal))
+        .join()
+        .unwrap()
+        .unwrap();
+}
+
+#[test]
+fn test_read_progress() {
+    let body = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+    let expected = body.clone();
+    let (url, _) = serve_http(move |_, _| (200, body.clone()));
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let log = calls.clone();
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Http(url)));
+    what.set_read_progress(move |path, read, total| {
+        log.lock().unwrap().push((path.to_string(), read, total));
+    });
+
+    assert_eq!(
+        what.load_file("progress.bin", Priority::Normal).unwrap(),
+        expected
+    );
+
+    let reported = calls.lock().unwrap().clone();
+    assert!(reported.len() > 1);
+    assert!(reported
+        .iter()
+        .all(|(path, _, total)| path == "progress.bin" && *total == Some(200_000)));
+    assert!(reported.windows(2).all(|pair| pair[0].1 < pair[1].1));
+    assert_eq!(reported.last().unwrap().1, 200_000);
+
+    //Cache hits don't read anything.
+    what.load_file("progress.bin", Priority::Normal).unwrap();
+    assert_eq!(calls.lock().unwrap().len(), reported.len());
+}
+
+#[test]
+fn test_not_found_ttl() {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counter = reads.clone();
+
+    let mut what = What::new(1e8 as usize, None);
+    what.set_backend(Box::new(ClosureBackend(move |_: &str| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        None
+    })));
+
+    assert!(what.load_file("missing.bin", Priority::Normal).is_err());
+    assert_eq!(what.guid("missing.bin"), None);
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+    //Without a ttl every load asks the backend.
+    assert!(what.load_file("missing.bin", Priority::Normal).is_err());
+    assert_eq!(reads.load(Ordering::SeqCst), 2);
+
+    what.set_not_found_ttl(Some(Duration::from_secs(60)));
+
+    assert!(what.load_file("missing.bin", Priority::Normal).is_err());
+    assert!(what.load_file("missing.bin", Priority::Normal).is_err());
+    assert_eq!(reads.load(Ordering::SeqCst), 3);
+
+    what.set_not_found_ttl(Some(Duration::ZERO));
+
+    assert!(what.load_file("missing.bin", Priority::Normal).is_err());
+    assert!(what.load_file("missing.bin", Priority::Normal).is_err());
+    assert_eq!(reads.load(Ordering::SeqCst), 5);
+}
+
+#[test]
+fn test_incremental_build() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+    what.set_incremental(true);
+
+    let _ = std::fs::remove_file("tests/assets/incremental_gen.fur");
+
+    let first = what
+        .convert_texture("incremental_gen.fur", "error.png", false)
+        .unwrap();
+    assert!(!first.up_to_date);
+
+    //Unchanged, so this neither converts nor fails on the existing output.
+    let second = what
+        .convert_texture("incremental_gen.fur", "error.png", false)
+        .unwrap();
+    assert!(second.up_to_date);
+    assert_eq!(second.bytes_written, 0);
+
+    let options = TextureOptions {
+        max_size: Some(16),
+        ..Default::default()
+    };
+    let third = what
+        .convert_texture_with("incremental_gen.fur", "error.png", &options, true)
+        .unwrap();
+    assert!(!third.up_to_date);
+
+    let manifest = what.manifest();
+    assert_eq!(manifest.assets.len(), 3);
+    assert_eq!(manifest.assets[1].bytes, first.bytes_written);
+}
+
+#[test]
+fn test_incremental_encryption() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+    what.set_incremental(true);
+
+    let convert = |what: &What| {
+        what.convert_texture("incremental_key_gen.fur", "error.png", true)
+            .unwrap()
+            .up_to_date
+    };
+
+    convert(&what);
+    assert!(convert(&what));
+
+    //Encrypting, or switching keys, rewrites outputs of unchanged sources.
+    what.set_encryption_key(Some([1; 32]));
+    assert!(!convert(&what));
+    assert!(convert(&what));
+
+    what.set_encryption_key(Some([2; 32]));
+    assert!(!convert(&what));
+    assert!(what
+        .load_asset("incremental_key_gen.fur", Priority::Normal, &[])
+        .is_ok());
+
+    what.set_encryption_key(None);
+    assert!(!convert(&what));
+}
+
+#[test]
+fn test_cli_texture_array_keys() {
+    let _ = std::fs::remove_file("tests/assets/cli_array_gen.fur");
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_what"))
+        .args([
+            "texture-array",
+            "tests/assets/error.png",
+            "tests/assets/error.png",
+            "tests/assets/error.png",
+            "-o",
+            "tests/assets/cli_array_gen.fur",
+            "--keys",
+            "red,green,blue",
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    if let Asset::TextureArray(array) = what
+        .load_asset("cli_array_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(array.keys, ["red", "green", "blue"]);
+        assert_eq!(array.data.len(), 3);
+    } else {
+        panic!("Expected texture array.");
+    }
+
+    //Key count has to match the inputs.
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_what"))
+        .args([
+            "texture-array",
+            "tests/assets/error.png",
+            "-o",
+            "tests/assets/cli_array_gen.fur",
+            "--keys",
+            "red,green",
+        ])
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
+
+#[test]
+fn test_gltf_missing_texture() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/missing_texture_gen.gltf",
+        r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "textures/missing_gen.png" }],
+            "textures": [{ "source": 0 }]
+        }"#,
+    )
+    .unwrap();
+
+    what.convert_gltf("missing_texture_gen.fur", "missing_texture_gen.gltf", true)
+        .unwrap();
+
+    match what.load_asset("missing_texture_gen.fur", Priority::Normal, &[]) {
+        Err(Error::GltfError(_)) => {}
+        Err(err) => panic!("Expected a glTF error, got {:?}", err),
+        Ok(_) => panic!("Expected a glTF error."),
+    }
+}
+
+#[test]
+fn test_gltf_resources() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/resources_gen.gltf",
+        r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "textures/albedo.png" }, { "uri": "./textures/../normal.png" }],
+            "textures": [{ "source": 0 }, { "source": 1 }]
+        }"#,
+    )
+    .unwrap();
+
+    what.convert_gltf("resources_gen.fur", "resources_gen.gltf", true)
+        .unwrap();
+
+    assert_eq!(
+        what.gltf_resources("resources_gen.fur").unwrap(),
+        ["textures/albedo.png", "normal.png"]
+    );
+    assert!(what.gltf_resources("error.fur").is_err());
+
+    what.convert_gltf("resources_far_gen.fur", "resources_gen.gltf", true)
+        .unwrap();
+    edit_header("tests/assets/resources_far_gen.fur", |header| {
+        header["ctype"]["Gltf"]["offset"] = 1_000_000.into();
+    });
+
+    assert!(is_out_of_range(
+        what.gltf_resources("resources_far_gen.fur")
+    ));
+    assert!(is_out_of_range(what.load_asset(
+        "resources_far_gen.fur",
+        Priority::Normal,
+        &[]
+    )));
+}
+
+#[test]
+fn test_gltf_data_uri_buffer() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/data_uri_gen.gltf",
+        r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [{ "byteLength": 4, "uri": "data:application/octet-stream;base64,AQIDBA==" }]
+        }"#,
+    )
+    .unwrap();
+
+    what.convert_gltf("data_uri_gen.fur", "data_uri_gen.gltf", true)
+        .unwrap();
+
+    //Only the asset itself may be requested from the backend.
+    let fur = std::fs::read("tests/assets/data_uri_gen.fur").unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let log = requests.clone();
+
+    what.set_backend(Box::new(ClosureBackend(move |path: &str| {
+        log.lock().unwrap().push(path.to_string());
+        (path == "data_uri_gen.fur").then(|| fur.clone())
+    })));
+
+    if let Asset::Gltf(_, buffers, _, _) = what
+        .load_asset("data_uri_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(&buffers[0].0[..4], [1, 2, 3, 4]);
+    } else {
+        panic!("Expected glTF.");
+    }
+
+    assert_eq!(*requests.lock().unwrap(), ["data_uri_gen.fur"]);
+}
+
+#[test]
+fn test_gltf_split() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write("tests/assets/split_gen.bin", [5, 6, 7, 8, 9, 10, 11, 12]).unwrap();
+    std::fs::write(
+        "tests/assets/split_gen.gltf",
+        r#"{
+            "asset": { "version": "2.0" },
+            "buffers": [
+                { "byteLength": 4, "uri": "data:application/octet-stream;base64,AQIDBA==" },
+                { "byteLength": 8, "uri": "split_gen.bin" }
+            ],
+            "images": [{ "uri": "error.png" }]
+        }"#,
+    )
+    .unwrap();
+
+    what.convert_gltf("split_whole_gen.fur", "split_gen.gltf", true)
+        .unwrap();
+    what.convert_gltf_with(
+        "split_gen.fur",
+        "split_gen.gltf",
+        &GltfOptions { split: true },
+        true,
+    )
+    .unwrap();
+
+    let entries = what.list_entries("split_gen.fur").unwrap();
+    let keys = entries
+        .iter()
+        .map(|entry| entry.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["buffers/0", "buffers/1", "images/0"]);
+    assert!(entries.iter().all(|entry| entry.valid));
+
+    assert_eq!(
+        what.load_gltf_entry("split_gen.fur", "buffers/1").unwrap(),
+        [5, 6, 7, 8, 9, 10, 11, 12]
+    );
+    assert_eq!(
+        what.load_gltf_entry("split_gen.fur", "images/0").unwrap(),
+        include_bytes!("assets/error.png")
+    );
+    assert!(what.load_gltf_entry("split_gen.fur", "buffers/2").is_err());
+
+    //Everything is packed, so nothing is resolved next to the file.
+    assert!(what.gltf_resources("split_gen.fur").unwrap().is_empty());
+    assert_eq!(
+        what.gltf_resources("split_whole_gen.fur").unwrap(),
+        ["split_gen.bin", "error.png"]
+    );
+
+    let (whole, whole_buffers, whole_images, _) = what
+        .load_gltf("split_whole_gen.fur", Priority::Normal)
+        .unwrap();
+
+    let fur = std::fs::read("tests/assets/split_gen.fur").unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let log = requests.clone();
+
+    what.set_backend(Box::new(ClosureBackend(move |path: &str| {
+        log.lock().unwrap().push(path.to_string());
+        (path == "split_gen.fur").then(|| fur.clone())
+    })));
+
+    let (document, buffers, images, _) = what.load_gltf("split_gen.fur", Priority::Normal).unwrap();
+    assert_eq!(*requests.lock().unwrap(), ["split_gen.fur"]);
+
+    assert_eq!(document.buffers().count(), whole.buffers().count());
+    assert_eq!(document.images().count(), whole.images().count());
+    assert!(buffers
+        .iter()
+        .zip(&whole_buffers)
+        .all(|(split, whole)| split.0 == whole.0));
+    assert_eq!(images[0].pixels, whole_images[0].pixels);
+
+    //An entry past the document fails instead of underflowing.
+    let mut fur = std::fs::read("tests/assets/split_gen.fur").unwrap();
+    let entry = b"\"offset\":4,\"length\":8";
+    let at = fur
+        .windows(entry.len())
+        .position(|window| window == entry)
+        .unwrap();
+    fur[at..at + entry.len()].copy_from_slice(b"\"offset\":999999999999");
+    std::fs::write("tests/assets/split_corrupt_gen.fur", fur).unwrap();
+
+    what.set_backend(Box::new(DefaultBackend::new()));
+    assert!(what
+        .load_gltf_entry("split_corrupt_gen.fur", "buffers/1")
+        .is_err());
+    assert!(what
+        .load_gltf("split_corrupt_gen.fur", Priority::Normal)
+        .is_err());
+
+    //Strict validation applies to the split conversion like it does to loads.
+    what.set_gltf_validation(GltfValidation::Strict);
+    assert!(matches!(
+        what.convert_gltf_with(
+            "split_broken_gen.fur",
+            "broken.gltf",
+            &GltfOptions { split: true },
+            true,
+        ),
+        Err(Error::GltfValidation(_))
+    ));
+}
+
+#[test]
+fn test_shader_capabilities() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/push_constant_gen.wgsl",
+        r#"
+struct Tint { color: vec4<f32> }
+var<push_constant> tint: Tint;
+
+@fragment
+fn main() -> @location(0) vec4<f32> {
+    return tint.color;
+}
+"#,
+    )
+    .unwrap();
+
+    what.convert_shader_with(
+        "push_constant_gen.fur",
+        "push_constant_gen.wgsl",
+        &ShaderOptions::default(),
+        true,
+    )
+    .unwrap();
+
+    let options = ShaderOptions {
+        capabilities: naga::valid::Capabilities::all() - naga::valid::Capabilities::PUSH_CONSTANT,
+        ..Default::default()
+    };
+
+    match what.convert_shader_with(
+        "push_constant_gen.fur",
+        "push_constant_gen.wgsl",
+        &options,
+        true,
+    ) {
+        Err(Error::Shader(path, _)) => assert!(path.ends_with("push_constant_gen.wgsl")),
+        Err(err) => panic!("Expected shader error, got {:?}", err),
+        Ok(_) => panic!("Expected shader error."),
+    }
+}
+
+#[test]
+fn test_shader_defines() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::write(
+        "tests/assets/defines_gen.wgsl",
+        r#"
+@fragment
+fn main() -> @location(0) vec4<f32> {
+#ifdef SHADOWS
+    return vec4<f32>(SHADOW_LEVEL, 0.0, 0.0, 1.0);
+#else
+    return vec4<f32>(1.0, 1.0, 1.0, 1.0);
+#endif
+}
+"#,
+    )
+    .unwrap();
+
+    what.convert_shader("defines_plain_gen.fur", "defines_gen.wgsl", false, true)
+        .unwrap();
+
+    let options = ShaderOptions {
+        defines: vec![
+            ("SHADOWS".to_string(), "1".to_string()),
+            ("SHADOW_LEVEL".to_string(), "0.5".to_string()),
+        ],
+        ..Default::default()
+    };
+    what.convert_shader_with(
+        "defines_shadows_gen.fur",
+        "defines_gen.wgsl",
+        &options,
+        true,
+    )
+    .unwrap();
+
+    let (plain, shadows) = match (
+        what.load_asset("defines_plain_gen.fur", Priority::Normal, &[])
+            .unwrap(),
+        what.load_asset("defines_shadows_gen.fur", Priority::Normal, &[])
+            .unwrap(),
+    ) {
+        (Asset::Shader(plain), Asset::Shader(shadows)) => (plain, shadows),
+        _ => panic!("Expected shaders."),
+    };
+
+    assert_ne!(plain.data, shadows.data);
+    assert!(plain.defines.is_empty());
+    assert_eq!(shadows.defines, options.defines);
+}
+
+#[test]
+fn test_watch() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::copy("tests/assets/error.png", "tests/assets/watch_gen.png").unwrap();
+    std::fs::copy("tests/assets/error.png", "tests/assets/watch_stop_gen.png").unwrap();
+
+    std::thread::spawn(|| {
+        std::thread::sleep(Duration::from_millis(500));
+
+        //Two quick saves settle into one conversion.
+        let png = std::fs::read("tests/assets/error.png").unwrap();
+        std::fs::write("tests/assets/watch_gen.png", &png).unwrap();
+        std::fs::write("tests/assets/watch_gen.png", &png).unwrap();
+
+        std::thread::sleep(Duration::from_millis(1000));
+        std::fs::write("tests/assets/watch_stop_gen.png", &png).unwrap();
+    });
+
+    let mut converted = Vec::new();
+
+    what.watch(
+        &["watch_gen.png", "watch_stop_gen.png"],
+        "",
+        Duration::from_millis(200),
+        |input, result| {
+            assert!(result.is_ok());
+            converted.push(input.to_path_buf());
+
+            match input.ends_with("watch_stop_gen.png") {
+                true => std::ops::ControlFlow::Break(()),
+                false => std::ops::ControlFlow::Continue(()),
+            }
+        },
+    )
+    .unwrap();
+
+    assert_eq!(
+        converted,
+        [
+            PathBuf::from("watch_gen.png"),
+            PathBuf::from("watch_stop_gen.png")
+        ]
+    );
+    assert!(PathBuf::from("tests/assets/watch_gen.fur").exists());
+}
+
+#[test]
+fn test_write_output_folders() {
+    let what = What::new(1e8 as usize, None);
+
+    //A bare file name lands in the working directory.
+    let report = what
+        .convert_texture("bare_gen.fur", "tests/assets/error.png", true)
+        .unwrap();
+    assert_eq!(report.output, PathBuf::from("bare_gen.fur"));
+    assert!(PathBuf::from("bare_gen.fur").exists());
+    std::fs::remove_file("bare_gen.fur").unwrap();
+
+    let _ = std::fs::remove_dir_all("tests/assets/nested_gen");
+
+    what.convert_texture(
+        "tests/assets/nested_gen/textures/error.fur",
+        "tests/assets/error.png",
+        false,
+    )
+    .unwrap();
+    assert!(PathBuf::from("tests/assets/nested_gen/textures/error.fur").exists());
+}
+
+#[test]
+fn test_output_name_custom_extension() {
+    let mut what = What::new(1e8 as usize, None);
+
+    assert_eq!(what.extension(), what::DEFAULT_EXTENSION);
+    assert_eq!(
+        what.output_name("tests/assets/error.png"),
+        Some(PathBuf::from("error.fur"))
+    );
+
+    what.set_extension(".pak");
+
+    assert_eq!(
+        what.output_name("tests/assets/shaders/water.frag.wgsl"),
+        Some(PathBuf::from("water.pak"))
+    );
+}
+
+#[test]
+fn test_append_texture_array_layer() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let keys = ["+x", "-x", "+y", "-y", "+z", "-z"];
+    let inputs = ["error.png"; 6];
+
+    what.convert_texture_array("append_gen.fur", Some(&keys), &inputs, true)
+        .unwrap();
+
+    //Load it once, so the append has to replace the cached copy.
+    assert!(what
+        .load_asset("append_gen.fur", Priority::Normal, &[])
+        .is_ok());
+
+    what.append_texture_array_layer("append_gen.fur", "extra", "error.png")
+        .unwrap();
+
+    let reader = what.open_texture_array("append_gen.fur").unwrap();
+    assert_eq!(reader.keys(), ["+x", "-x", "+y", "-y", "+z", "-z", "extra"]);
+    assert_eq!(
+        reader.layer("extra").unwrap(),
+        std::fs::read("tests/assets/error.png").unwrap()
+    );
+
+    match what
+        .load_asset("append_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::TextureArray(array) => assert_eq!(array.keys.len(), 7),
+        _ => panic!("Expected texture array."),
+    }
+
+    //Existing keys are replaced instead of added.
+    what.append_texture_array_layer("append_gen.fur", "+x", "error.png")
+        .unwrap();
+    assert_eq!(
+        what.open_texture_array("append_gen.fur")
+            .unwrap()
+            .keys()
+            .len(),
+        7
+    );
+
+    assert!(what
+        .append_texture_array_layer("error.fur", "extra", "error.png")
+        .is_err());
+}
+
+#[test]
+fn test_cli_convert_dispatch() {
+    let convert = |args: &[&str]| {
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_what"))
+            .arg("convert")
+            .args(args)
+            .arg("--overwrite")
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+
+    convert(&[
+        "tests/assets/shader.wgsl",
+        "-o",
+        "tests/assets/cli_shader_gen.fur",
+    ]);
+
+    std::fs::write(
+        "tests/assets/cli_glsl_gen.frag.glsl",
+        "#version 450\nlayout(location = 0) out vec4 color;\nvoid main() { color = vec4(1.0); }\n",
+    )
+    .unwrap();
+    convert(&[
+        "tests/assets/cli_glsl_gen.frag.glsl",
+        "-o",
+        "tests/assets/cli_glsl_gen.fur",
+    ]);
+    convert(&[
+        "tests/assets/textures.gltf",
+        "-o",
+        "tests/assets/cli_gltf_gen.fur",
+    ]);
+    convert(&[
+        "tests/assets/error.png",
+        "-o",
+        "tests/assets/cli_texture_gen.fur",
+    ]);
+    convert(&[
+        "tests/assets/error.png",
+        "tests/assets/error.png",
+        "-o",
+        "tests/assets/cli_images_gen.fur",
+    ]);
+
+    let faces = ["tests/assets/error.png"; 6];
+    let mut args = faces.to_vec();
+    args.extend(["-o", "tests/assets/cli_cubemap_gen.fur", "--cubemap"]);
+    convert(&args);
+
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    assert_eq!(
+        what.asset_kind("cli_shader_gen.fur").unwrap(),
+        AssetKind::Shader
+    );
+    assert_eq!(
+        what.asset_kind("cli_gltf_gen.fur").unwrap(),
+        AssetKind::Gltf
+    );
+
+    match what
+        .load_asset("cli_glsl_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Shader(shader) => assert_eq!(shader.stages, ShaderStages::FRAGMENT),
+        _ => panic!("Expected shader."),
+    }
+
+    //Glsl has no stage attributes, without one in the name there is nothing to compile.
+    std::fs::copy(
+        "tests/assets/cli_glsl_gen.frag.glsl",
+        "tests/assets/cli_glsl_gen.glsl",
+    )
+    .unwrap();
+    assert!(matches!(
+        what.convert_shader("cli_glsl_stage_gen.fur", "cli_glsl_gen.glsl", false, true),
+        Err(Error::Shader(_, _))
+    ));
+    assert_eq!(
+        what.asset_kind("cli_texture_gen.fur").unwrap(),
+        AssetKind::Texture
+    );
+
+    match what
+        .load_asset("cli_images_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::TextureArray(array) => assert_eq!(array.keys, ["error", "error"]),
+        _ => panic!("Expected texture array."),
+    }
+
+    match what
+        .load_asset("cli_cubemap_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::TextureArray(array) => {
+            assert_eq!(array.keys, ["+x", "-x", "+y", "-y", "+z", "-z"])
+        }
+        _ => panic!("Expected cubemap."),
+    }
+
+    //No inputs is a plain failure, not a panic.
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_what"))
+        .args(["convert", "-o", "tests/assets/cli_nothing_gen.fur"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(1));
+}
+
+#[test]
+fn test_threads() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let inputs = ["error.png"; 4];
+
+    assert!(what.set_threads(Some(0)).is_err());
+
+    what.set_threads(Some(1)).unwrap();
+    assert_eq!(what.threads(), 1);
+    what.convert_texture_array::<_, String>("threads_one_gen.fur", None, &inputs, true)
+        .unwrap();
+
+    #[cfg(feature = "parallel")]
+    {
+        what.set_threads(Some(3)).unwrap();
+        assert_eq!(what.threads(), 3);
+    }
+
+    what.convert_texture_array::<_, String>("threads_many_gen.fur", None, &inputs, true)
+        .unwrap();
+
+    //One thread takes the sequential path, but has to produce the same file.
+    assert_eq!(
+        std::fs::read("tests/assets/threads_one_gen.fur").unwrap(),
+        std::fs::read("tests/assets/threads_many_gen.fur").unwrap()
+    );
+}
+
+#[test]
+fn test_cli_batch_summary() {
+    let _ = std::fs::remove_dir_all("tests/assets/batch_gen");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_what"))
+        .args([
+            "convert",
+            "tests/assets/error.png",
+            "tests/assets/empty.tga",
+            "tests/assets/shader.wgsl",
+            "--batch",
+            "-o",
+            "tests/assets/batch_gen",
+        ])
+        .output()
+        .unwrap();
+
+    let log = String::from_utf8_lossy(&output.stdout).to_string()
+        + &String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(log.contains("Converted 2 assets, 1 failed"), "{}", log);
+    assert!(PathBuf::from("tests/assets/batch_gen/error.fur").exists());
+    assert!(PathBuf::from("tests/assets/batch_gen/shader.fur").exists());
+}
+
+#[test]
+fn test_estimated_size() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let textures = TextureArrayData {
+        size: 16,
+        format: Some("raw".to_string()),
+        keys: vec!["a".to_string(), "b".to_string()],
+        data: vec![vec![1; 1024], vec![2; 1024]],
+    };
+    let asset = what::AssetInput::TextureArray(&textures);
+
+    let estimate = what.estimated_size(&asset).unwrap();
+    let report = what
+        .write_texture_array("estimate_gen.fur", &textures, true)
+        .unwrap();
+    assert_eq!(estimate, report.bytes_written);
+
+    //Encryption grows header and payload.
+    what.set_encryption_key(Some([7; 32]));
+
+    let encrypted = what.estimated_size(&asset).unwrap();
+    let report = what
+        .write_texture_array("estimate_gen.fur", &textures, true)
+        .unwrap();
+    assert!(encrypted > estimate);
+    assert_eq!(encrypted, report.bytes_written);
+}
+
+#[test]
+fn test_invalid_header_size() {
+    //u64::MAX truncates on 32 bit targets and wraps when added to the header start. Just
+    //above u32::MAX is what a 32 bit target would see after truncation went wrong.
+    static MAX: [u8; 10] = *b"\xff\xff\xff\xff\xff\xff\xff\xff{}";
+    static ABOVE_U32: [u8; 10] = *b"\x00\x00\x00\x00\x01\x00\x00\x00{}";
+    //Fits into memory, but not into the file.
+    static PAST_END: [u8; 10] = *b"\x64\x00\x00\x00\x00\x00\x00\x00{}";
+    static FILES: &[(&str, &[u8])] = &[
+        ("max.fur", &MAX),
+        ("above_u32.fur", &ABOVE_U32),
+        ("past_end.fur", &PAST_END),
+    ];
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Static(FILES)));
+
+    for (path, size) in [
+        ("max.fur", u64::MAX),
+        ("above_u32.fur", 1 << 32),
+        ("past_end.fur", 100),
+    ] {
+        match what.load_asset(path, Priority::Normal, &[]) {
+            Err(Error::InvalidHeader(found, found_size)) => {
+                assert_eq!((found.as_str(), found_size), (path, size))
+            }
+            result => panic!("Expected InvalidHeader, got {:?}", result.map(|_| ())),
+        }
+
+        //Ranged reads check the size against the file length, too.
+        match what.load_header(path) {
+            Err(Error::InvalidHeader(found, found_size)) => {
+                assert_eq!((found.as_str(), found_size), (path, size))
+            }
+            result => panic!("Expected InvalidHeader, got {:?}", result.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn test_load_raw_png() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    match what.load_asset("error.png", Priority::Normal, &[]) {
+        Err(Error::NotAFurFile(path)) => assert_eq!(path, "error.png"),
+        result => panic!("Expected NotAFurFile, got {:?}", result.map(|_| ())),
+    }
+    assert!(matches!(
+        what.load_header("error.png"),
+        Err(Error::NotAFurFile(_))
+    ));
+
+    //Too short to even hold the header size.
+    std::fs::write("tests/assets/short_gen.fur", b"abc").unwrap();
+    assert!(matches!(
+        what.load_header("short_gen.fur"),
+        Err(Error::NotAFurFile(_))
+    ));
+
+    //Servers ignoring the range send short files whole instead of failing.
+    let (url, _) = serve_http(|_, _| (200, b"abc".to_vec()));
+    let mut short = What::new(1e8 as usize, Some(what::Location::Http(url)));
+    assert!(matches!(
+        short.load_header("short.fur"),
+        Err(Error::NotAFurFile(_))
+    ));
+
+    let png = std::fs::read("tests/assets/error.png").unwrap();
+
+    match what
+        .load_asset_or_raw("error.png", Priority::Normal)
+        .unwrap()
+    {
+        Asset::Texture(texture) => {
+            assert_eq!(texture.format.as_deref(), Some("png"));
+            assert!(texture.width > 0 && texture.height > 0);
+            assert_eq!(texture.data, png);
+        }
+        _ => panic!("Expected texture."),
+    }
+
+    //Real assets load as usual.
+    assert!(matches!(
+        what.load_asset_or_raw("error.fur", Priority::Normal),
+        Ok(Asset::Texture(_))
+    ));
+}
+
+#[test]
+fn test_timing_report() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.load_asset("error.fur", Priority::Normal, &[]).unwrap();
+    assert_eq!(what.timing_report(), what::TimingReport::default());
+
+    what.clear_cache();
+    what.set_timing(true);
+
+    what.load_asset("error.fur", Priority::Normal, &[]).unwrap();
+    //Served from the cache, so only decoded again.
+    what.load_asset("error.fur", Priority::Normal, &[]).unwrap();
+
+    let timing = what.timing_report();
+    assert_eq!(timing.reads, 1);
+    assert_eq!(
+        timing.bytes_read,
+        std::fs::metadata("tests/assets/error.fur").unwrap().len()
+    );
+    assert_eq!(timing.decodes, 2);
+}
+
+#[test]
+fn test_pinned_priority() {
+    let mut what = What::new(
+        5000,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    for i in 0..10 {
+        std::fs::write(format!("tests/assets/priority_{}_gen.bin", i), [i; 1000]).unwrap();
+    }
+
+    what.load_file("priority_0_gen.bin", Priority::Pinned)
+        .unwrap();
+
+    //Far more than fits, used more often than the pinned file.
+    for _ in 0..3 {
+        for i in 1..10 {
+            what.load_file(format!("priority_{}_gen.bin", i), Priority::High)
+                .unwrap();
+        }
+    }
+
+    let cached = what.cached_paths();
+    assert!(cached.len() < 10);
+    assert!(cached.contains(&"priority_0_gen.bin".to_string()));
+    assert!(Priority::Low < Priority::Normal && Priority::High < Priority::Pinned);
+}
+
+#[test]
+fn test_pinned_over_capacity() {
+    let mut what = What::new(
+        2000,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    for i in 0..10 {
+        std::fs::write(format!("tests/assets/pinned_{}_gen.bin", i), [i; 1000]).unwrap();
+    }
+
+    what.load_file("pinned_0_gen.bin", Priority::Pinned)
+        .unwrap();
+
+    //Flood the cache with files loaded more often and at a high priority.
+    for _ in 0..3 {
+        for i in 2..10 {
+            what.load_file(format!("pinned_{}_gen.bin", i), Priority::High)
+                .unwrap();
+        }
+    }
+
+    assert!(what
+        .cached_paths()
+        .contains(&"pinned_0_gen.bin".to_string()));
+
+    //A second pinned file doesn't fit next to the first one.
+    assert!(matches!(
+        what.load_file("pinned_1_gen.bin", Priority::Pinned),
+        Err(Error::CacheFull(_, _))
+    ));
+
+    what.unpin("pinned_0_gen.bin");
+    what.load_file("pinned_1_gen.bin", Priority::Pinned)
+        .unwrap();
+}
+
+//PNG chunk with a valid crc, so decoders accept it.
+fn png_chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for byte in kind.iter().chain(data) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    [
+        &(data.len() as u32).to_be_bytes()[..],
+        kind,
+        data,
+        &(!crc).to_be_bytes(),
+    ]
+    .concat()
+}
+
+#[test]
+fn test_strip_metadata() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    //Insert metadata right after the IHDR chunk.
+    let png = std::fs::read("tests/assets/error.png").unwrap();
+    let ihdr_end = 8 + 12 + 13;
+    let tagged = [
+        &png[..ihdr_end],
+        &png_chunk(b"sRGB", &[0]),
+        &png_chunk(b"eXIf", b"MM\0*\0\0\0\x08\0\0"),
+        &png_chunk(b"tEXt", b"Author\0Someone"),
+        &png[ihdr_end..],
+    ]
+    .concat();
+    std::fs::write("tests/assets/exif_gen.png", &tagged).unwrap();
+
+    let options = TextureOptions {
+        strip_metadata: true,
+        ..Default::default()
+    };
+    what.convert_texture_with("exif_gen.fur", "exif_gen.png", &options, true)
+        .unwrap();
+
+    let stripped = match what
+        .load_asset("exif_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Texture(texture) => texture.data,
+        _ => panic!("Expected texture."),
+    };
+
+    let contains = |data: &[u8], kind: &[u8]| data.windows(4).any(|window| window == kind);
+
+    assert!(stripped.len() < tagged.len());
+    assert!(!contains(&stripped, b"eXIf"));
+    assert!(!contains(&stripped, b"tEXt"));
+    assert!(!contains(&stripped, b"sRGB"));
+    assert_eq!(
+        image::load_from_memory(&stripped).unwrap(),
+        image::load_from_memory(&tagged).unwrap()
+    );
+
+    //Color chunks can stay.
+    let options = TextureOptions {
+        strip_metadata: true,
+        keep_icc: true,
+        ..Default::default()
+    };
+    what.convert_texture_with("exif_gen.fur", "exif_gen.png", &options, true)
+        .unwrap();
+    what.clear_cache();
+
+    match what
+        .load_asset("exif_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Texture(texture) => {
+            assert!(contains(&texture.data, b"sRGB"));
+            assert!(!contains(&texture.data, b"eXIf"));
+        }
+        _ => panic!("Expected texture."),
+    }
+}
+
+#[test]
+fn test_convert_texture_raw() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    //Not an image at all, so it would fail to decode.
+    let bytes = (0..=255u8).cycle().take(4096).collect::<Vec<u8>>();
+    std::fs::write("tests/assets/raw_gen.bin", &bytes).unwrap();
+
+    what.convert_texture_raw("raw_gen.fur", "raw_gen.bin", 32, 32, Some("rgba8"), true)
+        .unwrap();
+
+    match what
+        .load_asset("raw_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Texture(texture) => {
+            assert_eq!(texture.data, bytes);
+            assert_eq!((texture.width, texture.height), (32, 32));
+            assert_eq!(texture.format.as_deref(), Some("rgba8"));
+        }
+        _ => panic!("Expected texture."),
+    }
+}
+
+#[test]
+fn test_cli_exit_codes() {
+    let convert = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_what"))
+            .arg("convert")
+            .args(args)
+            .output()
+            .unwrap()
+            .status
+            .code()
+    };
+
+    assert_eq!(
+        convert(&[
+            "tests/assets/missing.png",
+            "-o",
+            "tests/assets/missing_gen.fur"
+        ]),
+        Some(2)
+    );
+
+    std::fs::write("tests/assets/exists_gen.fur", []).unwrap();
+    assert_eq!(
+        convert(&[
+            "tests/assets/error.png",
+            "-o",
+            "tests/assets/exists_gen.fur"
+        ]),
+        Some(3)
+    );
+
+    std::fs::write("tests/assets/broken_code_gen.wgsl", "fn main( {").unwrap();
+    assert_eq!(
+        convert(&[
+            "tests/assets/broken_code_gen.wgsl",
+            "-o",
+            "tests/assets/broken_code_gen.fur"
+        ]),
+        Some(5)
+    );
+}
+
+#[test]
+fn test_gltf_sibling_texture() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::create_dir_all("tests/assets/scene_gen/b").unwrap();
+    std::fs::copy(
+        "tests/assets/error.png",
+        "tests/assets/scene_gen/b/texture.png",
+    )
+    .unwrap();
+    std::fs::write(
+        "tests/assets/scene_gen/b/scene.gltf",
+        r#"{
+            "asset": { "version": "2.0" },
+            "images": [{ "uri": "texture.png" }]
+        }"#,
+    )
+    .unwrap();
+
+    what.convert_gltf("scene_gen/b/scene.fur", "scene_gen/b/scene.gltf", true)
+        .unwrap();
+
+    match what
+        .load_asset("scene_gen/b/scene.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::Gltf(_, _, images, _) => assert_eq!(images.len(), 1),
+        _ => panic!("Expected glTF."),
+    }
+
+    assert_eq!(
+        what.gltf_resources("scene_gen/b/scene.fur").unwrap(),
+        ["scene_gen/b/texture.png"]
+    );
+}
+
+#[test]
+fn test_forget_failed_path() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    for file in ["forget_gen.bin", "gc_gen.bin"] {
+        std::fs::write(format!("tests/assets/{}", file), [1, 2, 3]).unwrap();
+        what.load_file(file, Priority::Normal).unwrap();
+    }
+
+    let forget_guid = what.guid("forget_gen.bin").unwrap();
+
+    //Registered paths stay registered when a later load fails.
+    what.clear_cache();
+    for file in ["forget_gen.bin", "gc_gen.bin"] {
+        std::fs::remove_file(format!("tests/assets/{}", file)).unwrap();
+        assert!(what.load_file(file, Priority::Normal).is_err());
+        assert!(what.guid(file).is_some());
+    }
+
+    what.forget_path("forget_gen.bin");
+    assert_eq!(what.guid("forget_gen.bin"), None);
+
+    std::fs::write("tests/assets/forget_gen.bin", [4, 5, 6]).unwrap();
+    assert_eq!(
+        what.load_file("forget_gen.bin", Priority::Normal).unwrap(),
+        vec![4, 5, 6]
+    );
+    assert_ne!(what.guid("forget_gen.bin").unwrap(), forget_guid);
+
+    //gc only sweeps the failed path, the reloaded one stays.
+    assert_eq!(what.gc(), 1);
+    assert_eq!(what.guid("gc_gen.bin"), None);
+    assert!(what.guid("forget_gen.bin").is_some());
+    assert_eq!(what.gc(), 0);
+}
+
+#[test]
+fn test_content_dedup() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.set_content_dedup(true);
+
+    for file in ["dedup_a_gen.bin", "dedup_b_gen.bin"] {
+        std::fs::write(format!("tests/assets/{}", file), [1, 2, 3, 4]).unwrap();
+    }
+    std::fs::write("tests/assets/dedup_c_gen.bin", [5, 6, 7, 8]).unwrap();
+
+    let a = what
+        .load_bytes("dedup_a_gen.bin", Priority::Normal)
+        .unwrap();
+    let b = what
+        .load_bytes("dedup_b_gen.bin", Priority::Normal)
+        .unwrap();
+    let c = what
+        .load_bytes("dedup_c_gen.bin", Priority::Normal)
+        .unwrap();
+
+    //Both paths keep their own entry, but share the memory.
+    assert_ne!(what.guid("dedup_a_gen.bin"), what.guid("dedup_b_gen.bin"));
+    assert_eq!(a.as_ptr(), b.as_ptr());
+    assert_ne!(a.as_ptr(), c.as_ptr());
+    assert_eq!(*c, [5, 6, 7, 8]);
+
+    //Forgetting one path keeps the entry for the other.
+    what.forget_path("dedup_a_gen.bin");
+    assert!(what.cached_paths().contains(&"dedup_b_gen.bin".to_string()));
+
+    //After an eviction each path reloads its own file.
+    std::fs::write("tests/assets/dedup_b_gen.bin", [9, 9, 9, 9]).unwrap();
+    what.evict_priority_below(Priority::High);
+
+    assert_eq!(
+        *what
+            .load_bytes("dedup_b_gen.bin", Priority::Normal)
+            .unwrap(),
+        [9, 9, 9, 9]
+    );
+    assert_eq!(
+        *what
+            .load_bytes("dedup_a_gen.bin", Priority::Normal)
+            .unwrap(),
+        [1, 2, 3, 4]
+    );
+}
+
+#[test]
+fn test_footer() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.set_footer(true);
+    what.convert_texture_array(
+        "footer_gen.fur",
+        Some(&["a", "b"]),
+        &["error.png", "error.png"],
+        true,
+    )
+    .unwrap();
+
+    let data = std::fs::read("tests/assets/footer_gen.fur").unwrap();
+    let footer = Footer::parse(&data).expect("Expected a footer.");
+
+    //The header starts right after its size and the payload fills the rest.
+    let offset = footer.header_offset as usize;
+    let size = u64::from_le_bytes(data[..offset].try_into().unwrap()) as usize;
+    assert_eq!(offset, 8);
+    assert_eq!(data[offset], b'{');
+    assert_eq!(
+        offset + size + footer.payload_length as usize + Footer::SIZE,
+        data.len()
+    );
+
+    //Readers leave the footer out of the payload.
+    let png = std::fs::read("tests/assets/error.png").unwrap();
+    match what
+        .load_asset("footer_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::TextureArray(array) => assert!(array.data.iter().all(|layer| *layer == png)),
+        _ => panic!("Expected texture array."),
+    }
+
+    //Ranged readers find the header through the footer instead of its size prefix.
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    what.set_backend(Box::new(CountingBackend(
+        DefaultBackend::new(),
+        Arc::new(AtomicUsize::new(0)),
+        ranges.clone(),
+    )));
+
+    let reader = what.open_texture_array("footer_gen.fur").unwrap();
+    assert_eq!(reader.keys(), ["a", "b"]);
+    assert_eq!(reader.layer("b").unwrap(), png);
+    assert_eq!(
+        ranges.lock().unwrap()[0],
+        (offset as u64, Some(size as u64))
+    );
+
+    what.set_backend(Box::new(DefaultBackend::new()));
+
+    //Files without a footer are read as before.
+    what.set_footer(false);
+    what.convert_texture("footer_none_gen.fur", "error.png", true)
+        .unwrap();
+
+    let data = std::fs::read("tests/assets/footer_none_gen.fur").unwrap();
+    assert_eq!(Footer::parse(&data), None);
+}
+
+#[test]
+fn test_empty_texture_array() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let inputs: [&str; 0] = [];
+    let result = what.convert_texture_array("empty_array_gen.fur", None::<&[&str]>, &inputs, true);
+    assert!(matches!(result, Err(Error::Conversion(_))));
+    assert!(!std::path::Path::new("tests/assets/empty_array_gen.fur").exists());
+
+    //Written by hand, arrays without layers still load.
+    let empty = TextureArrayData {
+        size: 0,
+        format: None,
+        keys: Vec::new(),
+        data: Vec::new(),
+    };
+    what.write_texture_array("empty_written_gen.fur", &empty, true)
+        .unwrap();
+
+    match what
+        .load_asset("empty_written_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        Asset::TextureArray(array) => {
+            assert_eq!(array.size, 0);
+            assert!(array.keys.is_empty());
+            assert!(array.data.is_empty());
+        }
+        _ => panic!("Expected texture array."),
+    }
+}
+
+#[test]
+fn test_typed_loads() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    what.convert_shader("typed_shader_gen.fur", "shader.wgsl", false, true)
+        .unwrap();
+    what.convert_texture("typed_texture_gen.fur", "error.png", true)
+        .unwrap();
+
+    let shader = what
+        .load_shader("typed_shader_gen.fur", Priority::Normal)
+        .unwrap();
+    assert!(!shader.data.is_empty());
+
+    let texture = what
+        .load_texture("typed_texture_gen.fur", Priority::Normal, &[])
+        .unwrap();
+    assert_eq!((texture.width, texture.height), (512, 512));
+
+    match what.load_texture("typed_shader_gen.fur", Priority::Normal, &[]) {
+        Err(Error::WrongAssetType { expected, found }) => {
+            assert_eq!(expected, AssetKind::Texture);
+            assert_eq!(found, AssetKind::Shader);
+        }
+        _ => panic!("Expected WrongAssetType."),
+    }
+
+    assert!(matches!(
+        what.load_texture_array("typed_texture_gen.fur", Priority::Normal),
+        Err(Error::WrongAssetType {
+            expected: AssetKind::TextureArray,
+            found: AssetKind::Texture,
+        })
+    ));
+    assert!(matches!(
+        what.load_gltf("typed_texture_gen.fur", Priority::Normal),
+        Err(Error::WrongAssetType { .. })
+    ));
+}
+#[test]
+fn test_post_processor() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let runs = Arc::new(AtomicUsize::new(0));
+    let counter = runs.clone();
+
+    what.add_post_processor(AssetKind::Texture, move |asset| {
+        if let Asset::Texture(texture) = asset {
+            texture.data[0] = 0;
+            counter.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    what.add_post_processor(AssetKind::Shader, |_| panic!("Not a shader."));
+
+    let texture = what
+        .load_texture("error.fur", Priority::Normal, &[])
+        .unwrap();
+    assert_eq!(texture.data[0], 0);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+    //Processors work on the decoded asset, the cached file stays untouched.
+    what.clear_post_processors();
+
+    let texture = what
+        .load_texture("error.fur", Priority::Normal, &[])
+        .unwrap();
+    assert_ne!(texture.data[0], 0);
+    assert_eq!(runs.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_texture_array_layer() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 0, 255]))
+        .save("tests/assets/layer_face_gen.png")
+        .unwrap();
+    image::RgbaImage::from_pixel(4, 4, image::Rgba([0, 0, 255, 255]))
+        .save("tests/assets/layer_face_z_gen.png")
+        .unwrap();
+
+    let mut inputs = ["layer_face_gen.png"; 6];
+    inputs[4] = "layer_face_z_gen.png";
+
+    what.convert_cubemap("layer_cubemap_gen.fur", &inputs, true)
+        .unwrap();
+
+    let Asset::TextureArray(data) = what
+        .load_asset("layer_cubemap_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    else {
+        panic!("Expected cubemap.");
+    };
+
+    let face = data.layer("+z").unwrap();
+    assert_eq!(
+        face,
+        std::fs::read("tests/assets/layer_face_z_gen.png").unwrap()
+    );
+    assert_eq!(
+        image::load_from_memory(face)
+            .unwrap()
+            .to_rgba8()
+            .get_pixel(0, 0),
+        &image::Rgba([0, 0, 255, 255])
+    );
+    assert!(data.layer("z").is_none());
+
+    let keys = data.layers().map(|(key, _)| key).collect::<Vec<_>>();
+    assert_eq!(keys, ["+x", "-x", "+y", "-y", "+z", "-z"]);
+    assert!(data
+        .layers()
+        .all(|(key, layer)| (key == "+z") == (layer == face)));
+}
+
+#[test]
+fn test_write_texture_rgba() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    //Red, green, blue and transparent white.
+    let pixels = [
+        255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 0,
+    ];
+
+    what.write_texture_rgba("rgba_gen.fur", 2, 2, &pixels, true)
+        .unwrap();
+
+    let texture = what
+        .load_texture("rgba_gen.fur", Priority::Normal, &[])
+        .unwrap();
+
+    assert_eq!(texture.data, pixels);
+    assert_eq!((texture.width, texture.height), (2, 2));
+    assert_eq!(texture.texture_format(), Some(TextureFormat::Rgba8));
+
+    assert!(matches!(
+        what.write_texture_rgba("rgba_short_gen.fur", 2, 2, &pixels[..12], true),
+        Err(Error::Conversion(_))
+    ));
+}
+
+#[test]
+fn test_http_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    //Accepts connections and keeps them open, but never answers.
+    std::thread::spawn(move || {
+        let mut open = Vec::new();
+
+        for stream in listener.incoming() {
+            open.push(stream);
+        }
+    });
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Http(url)));
+    what.set_backend(Box::new(
+        DefaultBackend::new()
+            .with_retry_policy(RetryPolicy::none())
+            .with_timeouts(Duration::from_secs(1), Duration::from_millis(200)),
+    ));
+
+    let started = std::time::Instant::now();
+
+    match what.load_file("error.png", Priority::Normal) {
+        Err(Error::Timeout(url)) => assert!(url.ends_with("error.png"), "{}", url),
+        other => panic!("Expected a timeout, got {:?}.", other.map(|_| ())),
+    }
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_http_connection_reuse() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+
+    //Answers every request of a connection until the client closes it.
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            count.fetch_add(1, Ordering::SeqCst);
+
+            std::thread::spawn(move || {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+
+                while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                    if line == "\r\n" {
+                        write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nfile").unwrap();
+                    }
+
+                    line.clear();
+                }
+            });
+        }
+    });
+
+    let mut what = What::new(1e8 as usize, Some(what::Location::Http(url)));
+
+    for file in ["a.bin", "b.bin", "c.bin"] {
+        assert_eq!(*what.load_bytes(file, Priority::Normal).unwrap(), *b"file");
+    }
+
+    //All requests went through the same pooled connection.
+    assert_eq!(connections.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn test_convert_dir() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let _ = std::fs::remove_dir_all("tests/assets/convert_dir_gen");
+    std::fs::create_dir_all("tests/assets/convert_dir_gen/src/shaders").unwrap();
+
+    std::fs::copy(
+        "tests/assets/error.png",
+        "tests/assets/convert_dir_gen/src/error.png",
+    )
+    .unwrap();
+    std::fs::copy(
+        "tests/assets/shader.wgsl",
+        "tests/assets/convert_dir_gen/src/shaders/shader.wgsl",
+    )
+    .unwrap();
+    std::fs::write("tests/assets/convert_dir_gen/src/notes.txt", "Skipped").unwrap();
+
+    let results = what.convert_dir("convert_dir_gen/src", "convert_dir_gen/dst", true);
+
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+    assert!(PathBuf::from("tests/assets/convert_dir_gen/dst/error.fur").exists());
+    assert!(PathBuf::from("tests/assets/convert_dir_gen/dst/shaders/shader.fur").exists());
+    assert!(!PathBuf::from("tests/assets/convert_dir_gen/dst/notes.fur").exists());
+}
+
+#[test]
+fn test_format_version() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    assert_eq!(what::format_version(), (1, 1));
+    assert_eq!(what::format_version(), what::FORMAT_VERSION);
+
+    what.convert_texture("format_version_gen.fur", "error.png", true)
+        .unwrap();
+
+    assert_eq!(
+        what.load_header("format_version_gen.fur").unwrap().version,
+        what::format_version()
+    );
+}
+
+#[test]
+fn test_migrate_file() {
+    let mut what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    //Written by 1.0, before encryption, fingerprints and the footer.
+    std::fs::copy("tests/assets/error.fur", "tests/assets/migrate_old_gen.fur").unwrap();
+    assert_eq!(
+        what.load_header("migrate_old_gen.fur").unwrap().version,
+        (1, 0)
+    );
+
+    what.set_strict_version(true);
+    assert!(matches!(
+        what.load_asset("migrate_old_gen.fur", Priority::Normal, &[]),
+        Err(Error::UnsupportedVersion(1, 0))
+    ));
+
+    assert!(what.migrate_file("migrate_old_gen.fur").unwrap());
+    assert!(!what.migrate_file("migrate_old_gen.fur").unwrap());
+
+    //The failed load cached the old bytes.
+    what.forget_path("migrate_old_gen.fur");
+
+    assert_eq!(
+        what.load_header("migrate_old_gen.fur").unwrap().version,
+        what::format_version()
+    );
+    if let Asset::Texture(data) = what
+        .load_asset("migrate_old_gen.fur", Priority::Normal, &[])
+        .unwrap()
+    {
+        assert_eq!(data.data.as_slice(), include_bytes!("assets/error.png"));
+    } else {
+        panic!("Expected texture.");
+    }
+}
+
+#[test]
+fn test_migrate_dir() {
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    let _ = std::fs::remove_dir_all("tests/assets/migrate_gen");
+    std::fs::create_dir_all("tests/assets/migrate_gen").unwrap();
+
+    let old = include_bytes!("assets/error.fur");
+    let with_version = |from: &[u8], to: &[u8]| {
+        let at = old.windows(from.len()).position(|w| w == from).unwrap();
+        [&old[..at], to, &old[at + from.len()..]].concat()
+    };
+
+    let current = with_version(b"\"minor\":0", b"\"minor\":1");
+    std::fs::write("tests/assets/migrate_gen/current.fur", &current).unwrap();
+    std::fs::write(
+        "tests/assets/migrate_gen/newer.fur",
+        with_version(b"\"minor\":0", b"\"minor\":2"),
+    )
+    .unwrap();
+    std::fs::write("tests/assets/migrate_gen/old.fur", old).unwrap();
+    std::fs::write(
+        "tests/assets/migrate_gen/other.fur",
+        with_version(b"\"major\":1", b"\"major\":0"),
+    )
+    .unwrap();
+
+    let results = what.migrate_dir("migrate_gen").unwrap();
+
+    assert_eq!(results.len(), 4);
+    assert!(matches!(results[0].1, Ok(false)));
+    assert!(matches!(results[1].1, Err(Error::UnsupportedVersion(1, 2))));
+    assert!(matches!(results[2].1, Ok(true)));
+    assert!(matches!(results[3].1, Err(Error::UnsupportedVersion(0, 0))));
+
+    //Current files aren't rewritten.
+    assert_eq!(
+        std::fs::read("tests/assets/migrate_gen/current.fur").unwrap(),
+        current
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_non_utf8_path() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let what = What::new(
+        1e8 as usize,
+        Some(what::Location::File(PathBuf::from("tests/assets"))),
+    );
+
+    std::fs::create_dir_all("tests/assets/non_utf8_gen").unwrap();
+
+    let name = PathBuf::from("non_utf8_gen").join(OsStr::from_bytes(b"layer\xff.png"));
+    std::fs::copy(
+        "tests/assets/error.png",
+        PathBuf::from("tests/assets").join(&name),
+    )
+    .unwrap();
+
+    //Opened by its exact name, the extension is still readable.
+    what.convert_texture(
+        PathBuf::from("non_utf8_texture_gen.fur"),
+        name.clone(),
+        true,
+    )
+    .unwrap();
+
+    //The stem would become the key, which can't be stored without changing it.
+    let inputs = [PathBuf::from("error.png"), name.clone()];
+    match what.convert_texture_array(
+        PathBuf::from("non_utf8_array_gen.fur"),
+        None::<&[&str]>,
+        &inputs,
+        true,
+    ) {
+        Err(Error::InvalidPath(path)) => assert!(path.ends_with(&name), "{}", path.display()),
+        result => panic!("Expected InvalidPath, got {:?}", result.map(|_| ())),
+    }
+
+    what.convert_texture_array(
+        PathBuf::from("non_utf8_array_gen.fur"),
+        Some(&["a", "b"]),
+        &inputs,
+        true,
+    )
+    .unwrap();
+}